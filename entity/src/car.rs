@@ -0,0 +1,118 @@
+use std::sync::OnceLock;
+
+use super::*;
+
+/// Допустимые идентификаторы деталей каждого вида.
+///
+/// Таблица лежит в `data/components.json`, а не в коде: её нужно сверять
+/// с деталями, которые есть в клиенте, и дополнять без правки проверок.
+///
+/// Ресурсов клиента в репозитории нет, и таблица с ними пока не сверена.
+/// Поэтому по ней проверяется только выбор деталей на сайте, а ходы
+/// и повторы с неизвестными деталями лишь отмечаются в логе.
+#[derive(Debug, Deserialize)]
+struct Catalog {
+    pers: Vec<u32>,
+    car: Vec<u32>,
+    wheel: Vec<u32>,
+}
+
+fn catalog() -> &'static Catalog {
+    static CATALOG: OnceLock<Catalog> = OnceLock::new();
+
+    CATALOG.get_or_init(|| {
+        ::serde_json::from_str(include_str!("../data/components.json"))
+            .expect("data/components.json is a valid component catalog")
+    })
+}
+
+/// вид детали мехоса
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Component {
+    /// персонаж
+    Pers,
+    /// кузов
+    Car,
+    /// колёса (передние и задние выбираются из одного набора)
+    Wheel,
+}
+
+impl Component {
+    /// допустимые идентификаторы деталей этого вида
+    pub fn ids(self) -> &'static [u32] {
+        let catalog = catalog();
+        match self {
+            Component::Pers => &catalog.pers,
+            Component::Car => &catalog.car,
+            Component::Wheel => &catalog.wheel,
+        }
+    }
+
+    pub fn is_available(self, id: u32) -> bool {
+        self.ids().contains(&id)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Component::Pers => "pers",
+            Component::Car => "car",
+            Component::Wheel => "wheel",
+        }
+    }
+}
+
+/// комплектация мехоса игрока
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Loadout {
+    pub pers: u32,
+    pub car: u32,
+    pub fwheel: u32,
+    pub bwheel: u32,
+}
+
+impl Default for Loadout {
+    fn default() -> Self {
+        Self {
+            pers: 1,
+            car: 1,
+            fwheel: 1,
+            bwheel: 1,
+        }
+    }
+}
+
+impl Loadout {
+    /// возвращает первую деталь, отсутствующую в каталоге
+    pub fn validate(&self) -> Result<(), (Component, u32)> {
+        [
+            (Component::Pers, self.pers),
+            (Component::Car, self.car),
+            (Component::Wheel, self.fwheel),
+            (Component::Wheel, self.bwheel),
+        ]
+        .into_iter()
+        .find(|&(component, id)| !component.is_available(id))
+        .map_or(Ok(()), Err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn catalog_has_default_loadout() {
+        for component in [Component::Pers, Component::Car, Component::Wheel] {
+            assert!(!component.ids().is_empty(), "{}", component.name());
+        }
+        assert_eq!(Loadout::default().validate(), Ok(()));
+        assert_eq!(
+            Loadout {
+                car: 0,
+                ..Default::default()
+            }
+            .validate(),
+            Err((Component::Car, 0))
+        );
+    }
+}
@@ -32,11 +32,78 @@ pub struct Model {
     pub is_express: bool,
     #[sea_orm(default_value = "2")]
     pub players_cnt: u32,
+    /// момент, когда был обсчитан финальный ход игры
+    pub finished_at: Option<ChronoDateTime>,
+    /// момент, когда владелец отменил набор игроков
+    pub cancelled_at: Option<ChronoDateTime>,
+    /// счётчик изменений: увеличивается каждый раз, когда игра захватывается на запись
+    #[sea_orm(default_value = "0")]
+    pub version: u32,
+    /// pid только для чтения, по которому зрители получают пакеты завершённых ходов;
+    /// `None`, если владелец не открыл игру для зрителей
+    pub spectator_pid: Option<u32>,
+    /// приватная игра не видна в списках, войти в неё можно только по ссылке-приглашению
+    #[sea_orm(default_value = "false")]
+    pub is_private: bool,
+    /// секрет ссылки-приглашения в приватную игру; `None`, если владелец отозвал ссылку
+    pub invite_token: Option<String>,
+    /// время начала игры: до него набор игроков открыт, даже если все места заняты
+    pub starts_at: Option<ChronoDateTime>,
+    /// к назначенному времени свободные места занимают роботы
+    #[sea_orm(default_value = "false")]
+    pub fill_with_robots: bool,
+    /// момент, с которого игра стоит на паузе по решению игроков
+    pub paused_at: Option<ChronoDateTime>,
+    /// Когда открылся текущий шаг: от него отсчитывается время на ход.
+    /// Снятие паузы сдвигает его на время паузы.
+    pub step_started_at: Option<ChronoDateTime>,
+    /// Номер игры, из записи которой загружена эта игра. Загруженная игра
+    /// завершена и доступна только для просмотра.
+    pub imported_from: Option<u32>,
 }
 
 impl Model {
-    pub fn world(&self) -> World {
-        (self.world_id, self.track_id).try_into().unwrap()
+    /// новая игра без игроков; `id` и даты создания назначаются при вставке
+    pub fn new(
+        owner_id: u32,
+        world: World,
+        game_type: GameType,
+        laps: u32,
+        seeds: u32,
+        duration: u32,
+        players_cnt: u32,
+    ) -> Self {
+        Self {
+            id: 0,
+            created_at: crate::now(),
+            updated_at: crate::now(),
+            owner_id,
+            world_id: world.world_id().into(),
+            track_id: world.track_id().into(),
+            rnd: ::rand::random(),
+            game_type,
+            laps,
+            seeds,
+            duration,
+            is_express: true,
+            players_cnt,
+            finished_at: None,
+            cancelled_at: None,
+            version: 0,
+            spectator_pid: None,
+            is_private: false,
+            invite_token: None,
+            starts_at: None,
+            fill_with_robots: false,
+            paused_at: None,
+            step_started_at: None,
+            imported_from: None,
+        }
+    }
+
+    /// мир и трасса игры, если такие идентификаторы вообще бывают
+    pub fn world(&self) -> Option<World> {
+        (self.world_id, self.track_id).try_into().ok()
     }
 }
 
@@ -52,6 +119,8 @@ pub enum Relation {
     // Player,
     #[sea_orm(has_many = "super::turn::Entity")]
     Turn,
+    #[sea_orm(has_many = "super::invite::Entity")]
+    Invite,
 }
 
 impl Related<super::user::Entity> for Entity {
@@ -72,6 +141,12 @@ impl Related<super::turn::Entity> for Entity {
     }
 }
 
+impl Related<super::invite::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Invite.def()
+    }
+}
+
 #[async_trait::async_trait]
 impl ActiveModelBehavior for ActiveModel {
     async fn before_save<C>(mut self, _db: &C, insert: bool) -> Result<Self, DbErr>
@@ -94,6 +169,28 @@ impl ActiveModelBehavior for ActiveModel {
             }
         }
 
+        {
+            if let (ActiveValue::Set(world_id), ActiveValue::Set(track_id)) =
+                (&self.world_id, &self.track_id)
+            {
+                match World::try_from((*world_id, *track_id)) {
+                    Err(_) => {
+                        ::log::warn!(
+                            "reject save a game with incorrect track: {}/{}",
+                            world_id,
+                            track_id
+                        );
+                        return reject();
+                    }
+                    // каталог трасс не сверен с клиентом, поэтому трасса вне его не отклоняется
+                    Ok(world) if !world.is_available() => {
+                        ::log::warn!("save a game with unknown track: {}/{}", world_id, track_id);
+                    }
+                    Ok(_) => {}
+                }
+            }
+        }
+
         {
             if let ActiveValue::Set(players_cnt) = self.players_cnt {
                 if !(2..=5).contains(&players_cnt) {
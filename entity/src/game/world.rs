@@ -51,6 +51,81 @@ impl World {
     pub fn available_world_ids() -> Range<u32> {
         0..13
     }
+
+    /// Количество трасс в мире `world_id`.
+    ///
+    /// Числа не читаются из ресурсов клиента: их в репозитории нет. Это список
+    /// трасс, которые сервер предлагает для гонок, составленный вручную по мирам
+    /// из [`World`] и пока не сверенный с клиентом. Служебные миры (интерфейс,
+    /// заставки) трасс не содержат. Игру на трассе вне списка `before_save`
+    /// сохраняет, лишь отметив её в логе.
+    pub fn tracks_cnt(world_id: u32) -> u32 {
+        match world_id {
+            0..=4 => 4,   // Mountain, Water, Forest, Town, Lava
+            5 | 6 => 2,   // Dolly, Mechanic
+            7..=9 => 0,   // Interface, Watch, Vid
+            10..=12 => 1, // Forests, Waters, Mounts
+            _ => 0,
+        }
+    }
+
+    /// все миры, в которых можно проводить гонки
+    pub fn catalog() -> Vec<WorldInfo> {
+        Self::available_world_ids()
+            .filter(|&world_id| Self::tracks_cnt(world_id) > 0)
+            .map(|world_id| WorldInfo {
+                world_id,
+                name: World::try_from((world_id, 0)).unwrap().name(),
+                tracks_cnt: Self::tracks_cnt(world_id),
+            })
+            .collect()
+    }
+
+    /// случайная трасса среди всех трасс каталога
+    pub fn random() -> Self {
+        use ::rand::seq::IteratorRandom;
+
+        Self::catalog()
+            .into_iter()
+            .flat_map(|w| (0..w.tracks_cnt).map(move |track| (w.world_id, track)))
+            .choose(&mut ::rand::thread_rng())
+            .and_then(|id| id.try_into().ok())
+            .unwrap()
+    }
+
+    /// есть ли такая трасса в каталоге
+    pub fn is_available(self) -> bool {
+        let (world_id, track_id) = self.id();
+        u32::from(track_id) < Self::tracks_cnt(world_id.into())
+    }
+
+    pub fn name(self) -> &'static str {
+        use World::*;
+
+        match self {
+            Mountain(_) => "Mountain",
+            Water(_) => "Water",
+            Forest(_) => "Forest",
+            Town(_) => "Town",
+            Lava(_) => "Lava",
+            Dolly(_) => "Dolly",
+            Mechanic(_) => "Mechanic",
+            Interface(_) => "Interface",
+            Watch(_) => "Watch",
+            Vid(_) => "Vid",
+            Forests(_) => "Forests",
+            Waters(_) => "Waters",
+            Mounts(_) => "Mounts",
+        }
+    }
+}
+
+/// описание мира для каталога трасс
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct WorldInfo {
+    pub world_id: u32,
+    pub name: &'static str,
+    pub tracks_cnt: u32,
 }
 
 impl TryFrom<(u32, u32)> for World {
@@ -0,0 +1,62 @@
+use super::*;
+
+use crate::car::Loadout;
+
+/// Приглашение пользователя в набирающую игроков игру.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "invite")]
+pub struct Model {
+    #[sea_orm(primary_key, unique)]
+    pub id: u32,
+    pub game_id: u32,
+    pub user_id: u32,
+    /// комплектация мехоса, с которой приглашённый войдёт в игру;
+    /// если не задана, используется комплектация пользователя по умолчанию
+    pub prop_pers: Option<u32>,
+    pub prop_car: Option<u32>,
+    pub prop_fwheel: Option<u32>,
+    pub prop_bwheel: Option<u32>,
+    #[sea_orm(default_expr = "now()", not_null)]
+    pub created_at: ChronoDateTime,
+}
+
+impl Model {
+    pub fn loadout(&self) -> Option<Loadout> {
+        Some(Loadout {
+            pers: self.prop_pers?,
+            car: self.prop_car?,
+            fwheel: self.prop_fwheel?,
+            bwheel: self.prop_bwheel?,
+        })
+    }
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::game::Entity",
+        from = "Column::GameId",
+        to = "super::game::Column::Id"
+    )]
+    Game,
+    #[sea_orm(
+        belongs_to = "super::user::Entity",
+        from = "Column::UserId",
+        to = "super::user::Column::Id"
+    )]
+    User,
+}
+
+impl Related<super::game::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Game.def()
+    }
+}
+
+impl Related<super::user::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::User.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -5,10 +5,19 @@ use ::serde::{Deserialize, Serialize};
 
 pub mod prelude;
 
+pub mod car;
 pub mod game;
+pub mod invite;
+pub mod notification;
 // pub mod player;
+pub mod tournament;
+pub mod tournament_game;
+pub mod tournament_participant;
 pub mod turn;
 pub mod user;
+pub mod vote;
+pub mod webhook;
+pub mod webhook_delivery;
 
 fn now() -> ::chrono::NaiveDateTime {
     ::chrono::Utc::now().naive_utc()
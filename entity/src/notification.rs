@@ -0,0 +1,74 @@
+use super::*;
+
+/// Уведомление пользователя о событии в игре.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "notification")]
+pub struct Model {
+    #[sea_orm(primary_key, unique)]
+    pub id: u32,
+    pub user_id: u32,
+    pub game_id: u32,
+    pub kind: NotificationKind,
+    /// шаг, к которому относится уведомление о ходе, `0` для остальных
+    #[sea_orm(default_value = "0")]
+    pub step_number: u32,
+    /// `None`, пока пользователь не прочитал уведомление
+    pub read_at: Option<ChronoDateTime>,
+    #[sea_orm(default_expr = "now()", not_null)]
+    pub created_at: ChronoDateTime,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, EnumIter, DeriveActiveEnum)]
+#[sea_orm(rs_type = "i32", db_type = "Integer")]
+pub enum NotificationKind {
+    /// все сходили, открылся следующий шаг
+    TurnStarted = 1,
+    GameStarted = 2,
+    GameFinished = 3,
+    /// приглашение в реванш
+    Invited = 4,
+    /// время на ход в игре по переписке подходит к концу
+    DeadlineNear = 5,
+}
+
+impl NotificationKind {
+    pub fn text(self) -> &'static str {
+        match self {
+            Self::TurnStarted => "your turn has started",
+            Self::GameStarted => "the game has begun",
+            Self::GameFinished => "the game is over",
+            Self::Invited => "you were invited to a game",
+            Self::DeadlineNear => "your turn is about to run out",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::game::Entity",
+        from = "Column::GameId",
+        to = "super::game::Column::Id"
+    )]
+    Game,
+    #[sea_orm(
+        belongs_to = "super::user::Entity",
+        from = "Column::UserId",
+        to = "super::user::Column::Id"
+    )]
+    User,
+}
+
+impl Related<super::game::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Game.def()
+    }
+}
+
+impl Related<super::user::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::User.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -1,6 +1,14 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.12.10
 
 pub use super::game::Entity as Game;
+pub use super::invite::Entity as Invite;
+pub use super::notification::Entity as Notification;
 // pub use super::player::Entity as Player;
+pub use super::tournament::Entity as Tournament;
+pub use super::tournament_game::Entity as TournamentGame;
+pub use super::tournament_participant::Entity as TournamentParticipant;
 pub use super::turn::Entity as Turn;
 pub use super::user::Entity as User;
+pub use super::vote::Entity as Vote;
+pub use super::webhook::Entity as Webhook;
+pub use super::webhook_delivery::Entity as WebhookDelivery;
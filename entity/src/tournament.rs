@@ -0,0 +1,132 @@
+use super::*;
+
+use crate::game::{GameType, World};
+
+/// Турнир: серия заездов по турам между зарегистрированными участниками.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "tournament")]
+pub struct Model {
+    #[sea_orm(primary_key, unique)]
+    pub id: u32,
+    #[sea_orm(default_expr = "now()", not_null)]
+    pub created_at: ChronoDateTime,
+    pub owner_id: u32,
+    pub name: String,
+    pub format: TournamentFormat,
+    /// сколько игроков в одном заезде
+    #[sea_orm(default_value = "2")]
+    pub players_per_race: u32,
+    /// число туров швейцарской системы, для остальных форматов считается при старте
+    #[sea_orm(default_value = "0")]
+    pub rounds: u32,
+    /// трассы туров по кругу
+    pub tracks: TrackRotation,
+    #[sea_orm(default_value = "1")]
+    pub game_type: GameType,
+    #[sea_orm(default_value = "1")]
+    pub laps: u32,
+    #[sea_orm(default_value = "10")]
+    pub seeds: u32,
+    #[sea_orm(default_value = "100")]
+    pub duration: u32,
+    /// идущий тур, `0` до старта
+    #[sea_orm(default_value = "0")]
+    pub current_round: u32,
+    pub started_at: Option<ChronoDateTime>,
+    pub finished_at: Option<ChronoDateTime>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, EnumIter, DeriveActiveEnum)]
+#[sea_orm(rs_type = "i32", db_type = "Integer")]
+pub enum TournamentFormat {
+    /// каждый с каждым, по два игрока в заезде
+    RoundRobin = 1,
+    /// в каждом туре встречаются участники с близким числом очков
+    Swiss = 2,
+    /// дальше проходит только победитель заезда
+    Elimination = 3,
+}
+
+impl TournamentFormat {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::RoundRobin => "round robin",
+            Self::Swiss => "swiss",
+            Self::Elimination => "elimination",
+        }
+    }
+}
+
+/// Трассы туров в виде пар `(world_id, track_id)`: тур `n` едет по трассе `(n - 1) % len`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, FromJsonQueryResult)]
+pub struct TrackRotation(pub Vec<(u32, u32)>);
+
+impl TrackRotation {
+    /// разбирает список вида `0:0, 1:2`; все трассы должны быть доступны
+    pub fn parse(s: &str) -> Option<Self> {
+        let tracks = s
+            .split(',')
+            .map(|track| {
+                let (world_id, track_id) = track.trim().split_once(':')?;
+                let world = World::try_from((world_id.parse().ok()?, track_id.parse().ok()?))
+                    .ok()
+                    .filter(|w| w.is_available())?;
+
+                Some((world.world_id().into(), world.track_id().into()))
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        (!tracks.is_empty()).then_some(Self(tracks))
+    }
+
+    pub fn world(&self, round: u32) -> World {
+        let idx = (round.max(1) - 1) as usize % self.0.len();
+        self.0[idx].try_into().unwrap()
+    }
+}
+
+impl std::fmt::Display for TrackRotation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let tracks = self
+            .0
+            .iter()
+            .map(|(world_id, track_id)| format!("{}:{}", world_id, track_id))
+            .collect::<Vec<_>>();
+
+        f.write_str(&tracks.join(", "))
+    }
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::user::Entity",
+        from = "Column::OwnerId",
+        to = "super::user::Column::Id"
+    )]
+    User,
+    #[sea_orm(has_many = "super::tournament_participant::Entity")]
+    Participant,
+    #[sea_orm(has_many = "super::tournament_game::Entity")]
+    Game,
+}
+
+impl Related<super::user::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::User.def()
+    }
+}
+
+impl Related<super::tournament_participant::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Participant.def()
+    }
+}
+
+impl Related<super::tournament_game::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Game.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,45 @@
+use super::*;
+
+/// Участник турнира.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "tournament_participant")]
+pub struct Model {
+    #[sea_orm(primary_key, unique)]
+    pub id: u32,
+    pub tournament_id: u32,
+    pub user_id: u32,
+    /// тур, в котором участник выбыл из турнира на выбывание
+    pub eliminated_in: Option<u32>,
+    #[sea_orm(default_expr = "now()", not_null)]
+    pub created_at: ChronoDateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::tournament::Entity",
+        from = "Column::TournamentId",
+        to = "super::tournament::Column::Id"
+    )]
+    Tournament,
+    #[sea_orm(
+        belongs_to = "super::user::Entity",
+        from = "Column::UserId",
+        to = "super::user::Column::Id"
+    )]
+    User,
+}
+
+impl Related<super::tournament::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Tournament.def()
+    }
+}
+
+impl Related<super::user::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::User.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -1,7 +1,12 @@
-use std::ops::{Deref, DerefMut};
+use std::{
+    borrow::Cow,
+    ops::{Deref, DerefMut},
+};
 
 use super::*;
 
+use crate::car::Loadout;
+
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, Serialize, Deserialize)]
 #[sea_orm(table_name = "turn")]
 pub struct Model {
@@ -51,12 +56,70 @@ pub struct Model {
     /// т.е. все события с такими же `self.game_id` и `self.step_number`
     #[sea_orm(default_value = false, not_null)]
     pub is_received: bool,
+    /// место в игре занято роботом, который пропускает каждый ход
+    #[sea_orm(default_value = false, not_null)]
+    pub is_robot: bool,
+    /// игрок сдался, дальше за него ходит сервер
+    #[sea_orm(default_value = false, not_null)]
+    pub is_resigned: bool,
+    /// имя игрока из загруженной записи игры, в которой все места принадлежат загрузившему
+    pub nickname: Option<String>,
     #[sea_orm(default_expr = "now()", not_null)]
     pub created_at: ::chrono::NaiveDateTime,
     #[sea_orm(default_expr = "now()", not_null)]
     pub updated_at: ::chrono::NaiveDateTime,
 }
 
+impl Model {
+    /// ход первого шага для игрока, только что вошедшего в игру
+    pub fn new(game_id: u32, user_id: u32, player_number: u32, loadout: Loadout) -> Self {
+        Self {
+            id: 0,
+            game_id,
+            user_id,
+            player_number,
+            step_number: 1,
+            is_finished: false,
+            rank: 0,
+            move_time: 0,
+            move_steps: 0,
+            bottles_cnt: 0,
+            total_seeds_cnt: 0,
+            arcanes_cnt: 0,
+            destroys_cnt: 0,
+            user_seeds_cnt: 0,
+            seeds: None,
+            prop_pers: loadout.pers,
+            prop_car: loadout.car,
+            prop_fwheel: loadout.fwheel,
+            prop_bwheel: loadout.bwheel,
+            is_received: false,
+            is_robot: false,
+            is_resigned: false,
+            nickname: None,
+            created_at: crate::now(),
+            updated_at: crate::now(),
+        }
+    }
+
+    /// имя, под которым игрок показывается в игре
+    pub fn nickname<'a>(&'a self, user: &'a super::user::Model) -> Cow<'a, str> {
+        match &self.nickname {
+            Some(nickname) => Cow::Borrowed(nickname.as_str()),
+            None => user.login(),
+        }
+    }
+
+    pub fn loadout(&self) -> Loadout {
+        Loadout {
+            pers: self.prop_pers,
+            car: self.prop_car,
+            fwheel: self.prop_fwheel,
+            bwheel: self.prop_bwheel,
+        }
+    }
+}
+
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
     #[sea_orm(
@@ -2,6 +2,8 @@ use std::borrow::Cow;
 
 use super::*;
 
+use crate::car::{Component, Loadout};
+
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, Serialize, Deserialize)]
 #[sea_orm(table_name = "user")]
 pub struct Model {
@@ -11,6 +13,15 @@ pub struct Model {
     pub login: Option<String>,
     #[sea_orm(default_value = "0")]
     pub is_blocked: UserBlocked,
+    /// комплектация мехоса по умолчанию, подставляется при входе в игру
+    #[sea_orm(default_value = "1", not_null)]
+    pub prop_pers: u32,
+    #[sea_orm(default_value = "1", not_null)]
+    pub prop_car: u32,
+    #[sea_orm(default_value = "1", not_null)]
+    pub prop_fwheel: u32,
+    #[sea_orm(default_value = "1", not_null)]
+    pub prop_bwheel: u32,
     #[sea_orm(default_expr = "now()", not_null)]
     pub created_at: ::chrono::NaiveDateTime,
     #[sea_orm(default_expr = "now()", not_null)]
@@ -24,6 +35,15 @@ impl Model {
             .map(|l| Cow::Borrowed(l.as_str()))
             .unwrap_or(Cow::Owned(format!(r#"№{}"#, self.steam_id)))
     }
+
+    pub fn loadout(&self) -> Loadout {
+        Loadout {
+            pers: self.prop_pers,
+            car: self.prop_car,
+            fwheel: self.prop_fwheel,
+            bwheel: self.prop_bwheel,
+        }
+    }
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -48,10 +68,31 @@ impl Related<super::turn::Entity> for Entity {
 
 #[async_trait::async_trait]
 impl ActiveModelBehavior for ActiveModel {
-    async fn before_save<C>(mut self, _db: &C, _insert: bool) -> Result<Self, DbErr>
+    async fn before_save<C>(mut self, _db: &C, insert: bool) -> Result<Self, DbErr>
     where
         C: ConnectionTrait,
     {
+        for (component, id) in [
+            (Component::Pers, &self.prop_pers),
+            (Component::Car, &self.prop_car),
+            (Component::Wheel, &self.prop_fwheel),
+            (Component::Wheel, &self.prop_bwheel),
+        ] {
+            if let ActiveValue::Set(id) = id {
+                if !component.is_available(*id) {
+                    ::log::warn!(
+                        "reject save a user with incorrect {}: {}",
+                        component.name(),
+                        id
+                    );
+                    return match insert {
+                        true => Err(DbErr::RecordNotInserted),
+                        false => Err(DbErr::RecordNotUpdated),
+                    };
+                }
+            }
+        }
+
         self.updated_at = ActiveValue::NotSet;
 
         Ok(self)
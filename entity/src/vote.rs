@@ -0,0 +1,47 @@
+use super::*;
+
+/// Голос участника идущей игры за паузу или за её снятие.
+///
+/// Голоса действуют, пока игра не перешла в запрошенное состояние, после этого они удаляются.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "vote")]
+pub struct Model {
+    #[sea_orm(primary_key, unique)]
+    pub id: u32,
+    pub game_id: u32,
+    pub user_id: u32,
+    /// `true` — за паузу, `false` — за продолжение игры
+    pub pause: bool,
+    #[sea_orm(default_expr = "now()", not_null)]
+    pub created_at: ChronoDateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::game::Entity",
+        from = "Column::GameId",
+        to = "super::game::Column::Id"
+    )]
+    Game,
+    #[sea_orm(
+        belongs_to = "super::user::Entity",
+        from = "Column::UserId",
+        to = "super::user::Column::Id"
+    )]
+    User,
+}
+
+impl Related<super::game::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Game.def()
+    }
+}
+
+impl Related<super::user::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::User.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
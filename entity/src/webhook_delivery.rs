@@ -0,0 +1,47 @@
+use super::*;
+
+/// Одна отправка события на вебхук вместе с итогом последней попытки.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "webhook_delivery")]
+pub struct Model {
+    #[sea_orm(primary_key, unique)]
+    pub id: u32,
+    pub webhook_id: u32,
+    pub game_id: u32,
+    /// завершённый шаг для `step_completed`, `0` для остальных событий
+    #[sea_orm(default_value = "0")]
+    pub step_number: u32,
+    /// имя события, как в SSE-потоке
+    pub event: String,
+    /// JSON, который отправляется в теле запроса
+    pub payload: String,
+    #[sea_orm(default_value = "0")]
+    pub attempts: u32,
+    /// HTTP-код ответа на последнюю попытку
+    pub status_code: Option<u32>,
+    /// ошибка последней попытки
+    pub error: Option<String>,
+    /// когда пробовать снова; `None`, если доставлено или попытки кончились
+    pub next_attempt_at: Option<ChronoDateTime>,
+    pub delivered_at: Option<ChronoDateTime>,
+    #[sea_orm(default_expr = "now()", not_null)]
+    pub created_at: ChronoDateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::webhook::Entity",
+        from = "Column::WebhookId",
+        to = "super::webhook::Column::Id"
+    )]
+    Webhook,
+}
+
+impl Related<super::webhook::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Webhook.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -3,6 +3,22 @@ pub use ::sea_orm_migration::prelude::*;
 mod m20220101_000001_create_user;
 mod m20240113_134047_create_game;
 mod m20240113_140000_create_turn;
+mod m20240203_120000_add_game_finished_at;
+mod m20240210_120000_add_gamelist_indexes;
+mod m20240217_120000_add_user_loadout;
+mod m20240224_120000_add_game_cancelled_at;
+mod m20240302_120000_add_game_version;
+mod m20240309_120000_add_game_spectator_pid;
+mod m20240316_120000_create_replay;
+mod m20240323_120000_create_invite;
+mod m20240330_120000_add_game_private;
+mod m20240406_120000_create_tournament;
+mod m20240413_120000_add_game_schedule;
+mod m20240420_120000_add_turn_resigned;
+mod m20240427_120000_create_vote;
+mod m20240504_120000_create_notification;
+mod m20240511_120000_create_webhook;
+mod m20240518_120000_replay_as_game;
 
 pub struct Migrator;
 
@@ -13,6 +29,22 @@ impl MigratorTrait for Migrator {
             Box::new(m20220101_000001_create_user::Migration),
             Box::new(m20240113_134047_create_game::Migration),
             Box::new(m20240113_140000_create_turn::Migration),
+            Box::new(m20240203_120000_add_game_finished_at::Migration),
+            Box::new(m20240210_120000_add_gamelist_indexes::Migration),
+            Box::new(m20240217_120000_add_user_loadout::Migration),
+            Box::new(m20240224_120000_add_game_cancelled_at::Migration),
+            Box::new(m20240302_120000_add_game_version::Migration),
+            Box::new(m20240309_120000_add_game_spectator_pid::Migration),
+            Box::new(m20240316_120000_create_replay::Migration),
+            Box::new(m20240323_120000_create_invite::Migration),
+            Box::new(m20240330_120000_add_game_private::Migration),
+            Box::new(m20240406_120000_create_tournament::Migration),
+            Box::new(m20240413_120000_add_game_schedule::Migration),
+            Box::new(m20240420_120000_add_turn_resigned::Migration),
+            Box::new(m20240427_120000_create_vote::Migration),
+            Box::new(m20240504_120000_create_notification::Migration),
+            Box::new(m20240511_120000_create_webhook::Migration),
+            Box::new(m20240518_120000_replay_as_game::Migration),
         ]
     }
 }
@@ -0,0 +1,91 @@
+use super::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_game-created_at")
+                    .table(Game::Table)
+                    .col(Game::CreatedAt)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_game-owner_id")
+                    .table(Game::Table)
+                    .col(Game::OwnerId)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_game-world_id-track_id")
+                    .table(Game::Table)
+                    .col(Game::WorldId)
+                    .col(Game::TrackId)
+                    .to_owned(),
+            )
+            .await?;
+
+        // список игр считает зарегистрированных игроков по ходам с `step_number = 1`
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_turn-step_number-game_id")
+                    .table(Turn::Table)
+                    .col(Turn::StepNumber)
+                    .col(Turn::GameId)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for (name, table) in [
+            ("idx_game-created_at", Game::Table),
+            ("idx_game-owner_id", Game::Table),
+            ("idx_game-world_id-track_id", Game::Table),
+        ] {
+            manager
+                .drop_index(Index::drop().name(name).table(table).to_owned())
+                .await?;
+        }
+
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx_turn-step_number-game_id")
+                    .table(Turn::Table)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Game {
+    Table,
+    CreatedAt,
+    OwnerId,
+    WorldId,
+    TrackId,
+}
+
+#[derive(DeriveIden)]
+enum Turn {
+    Table,
+    GameId,
+    StepNumber,
+}
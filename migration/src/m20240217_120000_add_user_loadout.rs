@@ -0,0 +1,57 @@
+use super::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // sqlite не умеет добавлять несколько колонок одним запросом
+        for col in [
+            User::PropPers,
+            User::PropCar,
+            User::PropFwheel,
+            User::PropBwheel,
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(User::Table)
+                        .add_column(ColumnDef::new(col).integer().not_null().default(1))
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for col in [
+            User::PropPers,
+            User::PropCar,
+            User::PropFwheel,
+            User::PropBwheel,
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(User::Table)
+                        .drop_column(col)
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    PropPers,
+    PropCar,
+    PropFwheel,
+    PropBwheel,
+}
@@ -0,0 +1,97 @@
+use super::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Invite::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Invite::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .unique_key()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Invite::GameId).integer().not_null())
+                    .col(ColumnDef::new(Invite::UserId).integer().not_null())
+                    .col(ColumnDef::new(Invite::PropPers).integer().null())
+                    .col(ColumnDef::new(Invite::PropCar).integer().null())
+                    .col(ColumnDef::new(Invite::PropFwheel).integer().null())
+                    .col(ColumnDef::new(Invite::PropBwheel).integer().null())
+                    .col(
+                        ColumnDef::new(Invite::CreatedAt)
+                            .date_time()
+                            .default(Expr::current_timestamp())
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("FK_invite_game_id")
+                            .from(Invite::Table, Invite::GameId)
+                            .to(Game::Table, Game::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Restrict),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("FK_invite_user_id")
+                            .from(Invite::Table, Invite::UserId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Restrict),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_invite-game_id-user_id")
+                    .table(Invite::Table)
+                    .col(Invite::GameId)
+                    .col(Invite::UserId)
+                    .unique()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Invite::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum Game {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum Invite {
+    Table,
+    Id,
+    GameId,
+    UserId,
+    PropPers,
+    PropCar,
+    PropFwheel,
+    PropBwheel,
+    CreatedAt,
+}
@@ -0,0 +1,276 @@
+use super::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Tournament::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Tournament::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .unique_key()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(Tournament::CreatedAt)
+                            .date_time()
+                            .default(Expr::current_timestamp())
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(Tournament::OwnerId).integer().not_null())
+                    .col(ColumnDef::new(Tournament::Name).string().not_null())
+                    .col(ColumnDef::new(Tournament::Format).integer().not_null())
+                    .col(
+                        ColumnDef::new(Tournament::PlayersPerRace)
+                            .integer()
+                            .not_null()
+                            .default(2),
+                    )
+                    .col(
+                        ColumnDef::new(Tournament::Rounds)
+                            .integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .col(ColumnDef::new(Tournament::Tracks).json().not_null())
+                    .col(
+                        ColumnDef::new(Tournament::GameType)
+                            .integer()
+                            .not_null()
+                            .default(1),
+                    )
+                    .col(
+                        ColumnDef::new(Tournament::Laps)
+                            .integer()
+                            .not_null()
+                            .default(1),
+                    )
+                    .col(
+                        ColumnDef::new(Tournament::Seeds)
+                            .integer()
+                            .not_null()
+                            .default(10),
+                    )
+                    .col(
+                        ColumnDef::new(Tournament::Duration)
+                            .integer()
+                            .not_null()
+                            .default(100),
+                    )
+                    .col(
+                        ColumnDef::new(Tournament::CurrentRound)
+                            .integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .col(ColumnDef::new(Tournament::StartedAt).date_time().null())
+                    .col(ColumnDef::new(Tournament::FinishedAt).date_time().null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("FK_tournament_owner_id")
+                            .from(Tournament::Table, Tournament::OwnerId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Restrict),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(TournamentParticipant::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(TournamentParticipant::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .unique_key()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(TournamentParticipant::TournamentId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(TournamentParticipant::UserId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(TournamentParticipant::EliminatedIn)
+                            .integer()
+                            .null(),
+                    )
+                    .col(
+                        ColumnDef::new(TournamentParticipant::CreatedAt)
+                            .date_time()
+                            .default(Expr::current_timestamp())
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("FK_tournament_participant_tournament_id")
+                            .from(
+                                TournamentParticipant::Table,
+                                TournamentParticipant::TournamentId,
+                            )
+                            .to(Tournament::Table, Tournament::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Restrict),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("FK_tournament_participant_user_id")
+                            .from(TournamentParticipant::Table, TournamentParticipant::UserId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Restrict),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_tournament_participant-tournament_id-user_id")
+                    .table(TournamentParticipant::Table)
+                    .col(TournamentParticipant::TournamentId)
+                    .col(TournamentParticipant::UserId)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(TournamentGame::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(TournamentGame::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .unique_key()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(TournamentGame::TournamentId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(TournamentGame::Round).integer().not_null())
+                    .col(
+                        ColumnDef::new(TournamentGame::GameId)
+                            .integer()
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("FK_tournament_game_tournament_id")
+                            .from(TournamentGame::Table, TournamentGame::TournamentId)
+                            .to(Tournament::Table, Tournament::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Restrict),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("FK_tournament_game_game_id")
+                            .from(TournamentGame::Table, TournamentGame::GameId)
+                            .to(Game::Table, Game::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Restrict),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_tournament_game-tournament_id-round")
+                    .table(TournamentGame::Table)
+                    .col(TournamentGame::TournamentId)
+                    .col(TournamentGame::Round)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(TournamentGame::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(TournamentParticipant::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(Tournament::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum Game {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum Tournament {
+    Table,
+    Id,
+    CreatedAt,
+    OwnerId,
+    Name,
+    Format,
+    PlayersPerRace,
+    Rounds,
+    Tracks,
+    GameType,
+    Laps,
+    Seeds,
+    Duration,
+    CurrentRound,
+    StartedAt,
+    FinishedAt,
+}
+
+#[derive(DeriveIden)]
+enum TournamentParticipant {
+    Table,
+    Id,
+    TournamentId,
+    UserId,
+    EliminatedIn,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum TournamentGame {
+    Table,
+    Id,
+    TournamentId,
+    Round,
+    GameId,
+}
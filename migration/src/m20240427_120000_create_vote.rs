@@ -0,0 +1,130 @@
+use super::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Vote::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Vote::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .unique_key()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Vote::GameId).integer().not_null())
+                    .col(ColumnDef::new(Vote::UserId).integer().not_null())
+                    .col(ColumnDef::new(Vote::Pause).boolean().not_null())
+                    .col(
+                        ColumnDef::new(Vote::CreatedAt)
+                            .date_time()
+                            .default(Expr::current_timestamp())
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("FK_vote_game_id")
+                            .from(Vote::Table, Vote::GameId)
+                            .to(Game::Table, Game::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Restrict),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("FK_vote_user_id")
+                            .from(Vote::Table, Vote::UserId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Restrict),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_vote-game_id-user_id")
+                    .table(Vote::Table)
+                    .col(Vote::GameId)
+                    .col(Vote::UserId)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        // sqlite не умеет добавлять несколько столбцов одним запросом
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Game::Table)
+                    .add_column(ColumnDef::new(Game::PausedAt).date_time().null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Game::Table)
+                    .add_column(ColumnDef::new(Game::StepStartedAt).date_time().null())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Game::Table)
+                    .drop_column(Game::StepStartedAt)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Game::Table)
+                    .drop_column(Game::PausedAt)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(Vote::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum Game {
+    Table,
+    Id,
+    PausedAt,
+    StepStartedAt,
+}
+
+#[derive(DeriveIden)]
+enum Vote {
+    Table,
+    Id,
+    GameId,
+    UserId,
+    Pause,
+    CreatedAt,
+}
@@ -0,0 +1,158 @@
+use super::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Webhook::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Webhook::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .unique_key()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Webhook::OwnerId).integer().not_null())
+                    .col(ColumnDef::new(Webhook::Url).string().not_null())
+                    .col(ColumnDef::new(Webhook::Secret).string().not_null())
+                    .col(
+                        ColumnDef::new(Webhook::CreatedAt)
+                            .date_time()
+                            .default(Expr::current_timestamp())
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("FK_webhook_owner_id")
+                            .from(Webhook::Table, Webhook::OwnerId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Restrict),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(WebhookDelivery::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(WebhookDelivery::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .unique_key()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(WebhookDelivery::WebhookId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(WebhookDelivery::GameId).integer().not_null())
+                    .col(
+                        ColumnDef::new(WebhookDelivery::StepNumber)
+                            .integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .col(ColumnDef::new(WebhookDelivery::Event).string().not_null())
+                    .col(ColumnDef::new(WebhookDelivery::Payload).text().not_null())
+                    .col(
+                        ColumnDef::new(WebhookDelivery::Attempts)
+                            .integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .col(ColumnDef::new(WebhookDelivery::StatusCode).integer().null())
+                    .col(ColumnDef::new(WebhookDelivery::Error).string().null())
+                    .col(
+                        ColumnDef::new(WebhookDelivery::NextAttemptAt)
+                            .date_time()
+                            .null(),
+                    )
+                    .col(
+                        ColumnDef::new(WebhookDelivery::DeliveredAt)
+                            .date_time()
+                            .null(),
+                    )
+                    .col(
+                        ColumnDef::new(WebhookDelivery::CreatedAt)
+                            .date_time()
+                            .default(Expr::current_timestamp())
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("FK_webhook_delivery_webhook_id")
+                            .from(WebhookDelivery::Table, WebhookDelivery::WebhookId)
+                            .to(Webhook::Table, Webhook::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Restrict),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_webhook_delivery-next_attempt_at")
+                    .table(WebhookDelivery::Table)
+                    .col(WebhookDelivery::NextAttemptAt)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(WebhookDelivery::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(Webhook::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum Webhook {
+    Table,
+    Id,
+    OwnerId,
+    Url,
+    Secret,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum WebhookDelivery {
+    Table,
+    Id,
+    WebhookId,
+    GameId,
+    StepNumber,
+    Event,
+    Payload,
+    Attempts,
+    StatusCode,
+    Error,
+    NextAttemptAt,
+    DeliveredAt,
+    CreatedAt,
+}
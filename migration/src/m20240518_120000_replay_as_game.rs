@@ -0,0 +1,115 @@
+use super::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // загруженные записи теперь хранятся завершёнными играми,
+        // записи из отдельной таблицы нужно загрузить заново
+        manager
+            .drop_table(Table::drop().table(Replay::Table).if_exists().to_owned())
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Game::Table)
+                    .add_column(ColumnDef::new(Game::ImportedFrom).integer().null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Turn::Table)
+                    .add_column(ColumnDef::new(Turn::Nickname).string().null())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Turn::Table)
+                    .drop_column(Turn::Nickname)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Game::Table)
+                    .drop_column(Game::ImportedFrom)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(Replay::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Replay::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .unique_key()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Replay::OwnerId).integer().not_null())
+                    .col(ColumnDef::new(Replay::SourceGameId).integer().not_null())
+                    .col(ColumnDef::new(Replay::Data).text().not_null())
+                    .col(
+                        ColumnDef::new(Replay::CreatedAt)
+                            .date_time()
+                            .default(Expr::current_timestamp())
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("FK_replay_owner_id")
+                            .from(Replay::Table, Replay::OwnerId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Restrict),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum Game {
+    Table,
+    ImportedFrom,
+}
+
+#[derive(DeriveIden)]
+enum Turn {
+    Table,
+    Nickname,
+}
+
+#[derive(DeriveIden)]
+enum Replay {
+    Table,
+    Id,
+    OwnerId,
+    SourceGameId,
+    Data,
+    CreatedAt,
+}
@@ -0,0 +1,231 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, RwLock,
+    },
+};
+
+use ::sea_orm::DbConn;
+use ::serde::Serialize;
+
+use crate::manager::{GameManager, GameManagerError, GameStatus, GameStorage};
+
+/// Кэш состояния активных (набирающих игроков и идущих) игр.
+///
+/// Кэш не сквозной: при промахе игра читается из базы и кладётся в кэш,
+/// а изменения пишутся в базу мимо него и только сбрасывают запись.
+/// Общий для всех воркеров: клоны ссылаются на одно и то же хранилище.
+/// Любое изменение игры должно заканчиваться вызовом [`GameCache::invalidate`].
+#[derive(Debug, Default, Clone)]
+pub struct GameCache {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    slots: RwLock<HashMap<u32, Slot>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    invalidations: AtomicU64,
+    queries: AtomicU64,
+}
+
+#[derive(Debug, Default)]
+struct Slot {
+    /// Растёт при каждой инвалидации. Снимок, загруженный из базы до инвалидации,
+    /// попадает в кэш только если поколение за время загрузки не изменилось.
+    generation: u64,
+    entry: Option<Entry>,
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    game: entity::game::Model,
+    turns: Vec<(entity::turn::Model, entity::user::Model)>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub invalidations: u64,
+    /// игр в кэше сейчас
+    pub entries: usize,
+    pub hit_rate: f64,
+    /// запросы к базе, которые кэш сделал при промахах
+    pub queries: u64,
+}
+
+impl GameCache {
+    /// Загружает игру из кэша, а при промахе из базы с сохранением в кэш.
+    pub async fn load_game<'db>(
+        &self,
+        db: &'db DbConn,
+        gmid: u32,
+    ) -> Result<GameManager<'db>, GameManagerError> {
+        if let Some(Entry { game, turns }) = self.get(gmid) {
+            self.inner.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(GameManager::from_parts(db, game, turns));
+        }
+
+        self.inner.misses.fetch_add(1, Ordering::Relaxed);
+
+        let generation = self.generation(gmid);
+
+        self.inner.queries.fetch_add(1, Ordering::Relaxed);
+        let game = db
+            .find_game(gmid)
+            .await?
+            .ok_or(GameManagerError::GameNotFound(gmid))?;
+
+        self.inner.queries.fetch_add(1, Ordering::Relaxed);
+        let turns = db.find_turns(gmid).await?;
+
+        let gm = GameManager::from_parts(db, game, turns);
+        if matches!(gm.status(), GameStatus::Open | GameStatus::Started) {
+            self.put(gmid, generation, &gm.game, &gm.turns);
+        }
+
+        Ok(gm)
+    }
+
+    /// сбрасывает закэшированное состояние игры после её изменения
+    pub fn invalidate(&self, gmid: u32) {
+        let mut slots = self.inner.slots.write().unwrap();
+        let slot = slots.entry(gmid).or_default();
+        slot.generation += 1;
+        slot.entry = None;
+
+        self.inner.invalidations.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let hits = self.inner.hits.load(Ordering::Relaxed);
+        let misses = self.inner.misses.load(Ordering::Relaxed);
+        let entries = self
+            .inner
+            .slots
+            .read()
+            .unwrap()
+            .values()
+            .filter(|s| s.entry.is_some())
+            .count();
+
+        CacheStats {
+            hits,
+            misses,
+            invalidations: self.inner.invalidations.load(Ordering::Relaxed),
+            entries,
+            hit_rate: match hits + misses {
+                0 => 0.0,
+                total => hits as f64 / total as f64,
+            },
+            queries: self.inner.queries.load(Ordering::Relaxed),
+        }
+    }
+
+    fn get(&self, gmid: u32) -> Option<Entry> {
+        self.inner
+            .slots
+            .read()
+            .unwrap()
+            .get(&gmid)
+            .and_then(|s| s.entry.clone())
+    }
+
+    fn generation(&self, gmid: u32) -> u64 {
+        self.inner
+            .slots
+            .read()
+            .unwrap()
+            .get(&gmid)
+            .map_or(0, |s| s.generation)
+    }
+
+    fn put(
+        &self,
+        gmid: u32,
+        generation: u64,
+        game: &entity::game::Model,
+        turns: &[(entity::turn::Model, entity::user::Model)],
+    ) {
+        let mut slots = self.inner.slots.write().unwrap();
+        let slot = slots.entry(gmid).or_default();
+
+        if slot.generation == generation {
+            slot.entry = Some(Entry {
+                game: game.clone(),
+                turns: turns.to_vec(),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn game() -> entity::game::Model {
+        let now = ::chrono::Utc::now().naive_utc();
+
+        entity::game::Model {
+            id: 1,
+            owner_id: 1,
+            world_id: 0,
+            track_id: 0,
+            rnd: 123,
+            game_type: entity::game::GameType::Winner,
+            laps: 1,
+            seeds: 10,
+            duration: 100,
+            is_express: true,
+            players_cnt: 2,
+            finished_at: None,
+            cancelled_at: None,
+            version: 0,
+            spectator_pid: None,
+            is_private: false,
+            invite_token: None,
+            starts_at: None,
+            fill_with_robots: false,
+            paused_at: None,
+            step_started_at: None,
+            imported_from: None,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    #[test]
+    fn stale_snapshot_is_not_cached() {
+        let cache = GameCache::default();
+
+        // снимок загружался из базы, пока игру меняли
+        let generation = cache.generation(1);
+        cache.invalidate(1);
+        cache.put(1, generation, &game(), &[]);
+        assert!(cache.get(1).is_none());
+
+        let generation = cache.generation(1);
+        cache.put(1, generation, &game(), &[]);
+        assert!(cache.get(1).is_some());
+
+        cache.invalidate(1);
+        assert!(cache.get(1).is_none());
+        assert_eq!(cache.stats().invalidations, 2);
+        assert_eq!(cache.stats().entries, 0);
+    }
+
+    #[test]
+    fn clones_share_entries() {
+        let cache = GameCache::default();
+        let other = cache.clone();
+
+        cache.put(1, 0, &game(), &[]);
+        assert!(other.get(1).is_some());
+
+        other.invalidate(1);
+        assert!(cache.get(1).is_none());
+    }
+}
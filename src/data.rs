@@ -13,7 +13,7 @@ use ::enum_primitive_derive::Primitive;
 use ::log::{info, warn};
 use ::num_traits::FromPrimitive;
 use ::serde::{Deserialize, Serialize};
-use entity::game::GameType;
+use entity::{car::Loadout, game::GameType};
 
 #[derive(Debug, Primitive, Clone, Copy, Deserialize, Serialize, Eq, PartialEq)]
 pub enum Language {
@@ -69,6 +69,15 @@ pub struct Player {
 }
 
 impl Player {
+    pub fn loadout(&self) -> Loadout {
+        Loadout {
+            pers: self.pers_car_comp_id,
+            car: self.front_car_comp_id,
+            fwheel: self.fwheel_car_comp_id,
+            bwheel: self.bwheel_car_comp_id,
+        }
+    }
+
     pub fn new(uid: u32, nickname: &str) -> Self {
         Self {
             uid,
@@ -276,8 +285,19 @@ impl KdlabCodec for Packet {
         //  * turn - один ход игрока, в котром могут содержаться несколько действий игрока (seeds)
 
         let mut steps = vec![];
-        for _ in 0..steps_cnt {
-            steps.append(&mut PlayerTurnInfo::from_raw(&mut iter)?);
+        if t_type == OG_GAME_PACKET {
+            // в пакетах сервера в заголовке указано число ходов игроков (turns), а не ходов игры
+            while steps.len() < steps_cnt {
+                let mut turns = PlayerTurnInfo::from_raw(&mut iter)?;
+                if turns.is_empty() {
+                    None?
+                }
+                steps.append(&mut turns);
+            }
+        } else {
+            for _ in 0..steps_cnt {
+                steps.append(&mut PlayerTurnInfo::from_raw(&mut iter)?);
+            }
         }
 
         let undecoded = iter.collect::<Vec<_>>().join(";");
@@ -356,6 +376,25 @@ pub struct PlayerTurnInfo {
     pub seeds: String,
 }
 
+impl From<&entity::turn::Model> for PlayerTurnInfo {
+    fn from(t: &entity::turn::Model) -> Self {
+        Self {
+            step_number: t.step_number,
+            player_id: t.player_number,
+            is_finished: t.is_finished,
+            rank: t.rank,
+            move_time: t.move_time,
+            move_steps: t.move_steps,
+            bottles_cnt: t.bottles_cnt,
+            total_seeds_cnt: t.total_seeds_cnt,
+            arcanes_cnt: t.arcanes_cnt,
+            destroys_cnt: t.destroys_cnt,
+            user_seeds_cnt: t.user_seeds_cnt,
+            seeds: t.seeds.clone().unwrap_or_default(),
+        }
+    }
+}
+
 impl PlayerTurnInfo {
     fn from_raw<'a>(mut iter: impl Iterator<Item = &'a str>) -> Option<Vec<Self>> {
         let step_number = next(&mut iter)?;
@@ -383,6 +422,34 @@ impl PlayerTurnInfo {
     }
 }
 
+/// Одно семя из строки ходов игрока.
+///
+/// В строке семена записаны подряд группами по четыре числа: `x#y#тип#-1`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Seed {
+    pub x: i32,
+    pub y: i32,
+    pub kind: i32,
+}
+
+impl Seed {
+    /// разбирает все семена строки, неполные и некорректные группы пропускаются
+    pub fn parse_all(seeds: &str) -> Vec<Self> {
+        let values = seeds.split('#').collect::<Vec<_>>();
+
+        values
+            .chunks_exact(4)
+            .filter_map(|group| {
+                Some(Self {
+                    x: group[0].parse().ok()?,
+                    y: group[1].parse().ok()?,
+                    kind: group[2].parse().ok()?,
+                })
+            })
+            .collect()
+    }
+}
+
 struct YesNo(bool);
 
 impl Display for YesNo {
@@ -400,10 +467,22 @@ fn next<'a, F: FromStr>(mut iter: impl Iterator<Item = &'a str>) -> Option<F> {
 
 #[cfg(test)]
 mod tests {
-    use crate::data::{GameType, Language, PacketType, Player, PlayerTurnInfo};
+    use crate::data::{GameType, Language, PacketType, Player, PlayerTurnInfo, Seed};
 
     use super::{KdlabCodec, Packet, UrlProperty};
 
+    #[test]
+    fn packet_bothcode_type1_turns_2() {
+        let input = "KDLAB;104;1;1;0;0;1;password;0;0;12711;A;1;100;10;2;2;2;Y;;0;;;0;player;1;1;1;1;N;1;player2;1;1;1;1;N;2;2;0;N;0;21;0;16;14;0;1;2;165#741#51#-1#465#427#51#-1;1;N;1;21;0;20;12;0;1;4;177#1100#48#-1#204#967#48#-1#214#647#51#-1#379#433#51#-1;BITRIX";
+        let p = Packet::decode(&input).unwrap();
+        assert_eq!(p.t_type, PacketType::OG_GAME_PACKET);
+        assert_eq!(p.players.len(), 2);
+        assert_eq!(p.steps.len(), 2);
+        assert!(p.steps.iter().all(|s| s.step_number == 2));
+        assert_eq!(p.steps[1].seeds, "177#1100#48#-1#204#967#48#-1#214#647#51#-1#379#433#51#-1");
+        assert_eq!(p.encode(), input);
+    }
+
     #[test]
     fn packet_bothcode_type2_players_2() {
         let input = "KDLAB;104;2;1;0;0;1;password;0;0;47792;A;3;100;10;0;2;0;Y;;0;;;0;player;1;1;1;1;N;1;player2;1;1;1;1;N;BITRIX";
@@ -500,4 +579,21 @@ mod tests {
         // TODO: ts-server encode that:
         // assert_eq!(packet.encode(), "KDLAB;104;7;0;0;0;0;password;0;0;0;A;5;200;10;0;2;0;Y;;0;;;1;1;0;N;0;0;0;0;0;0;0;4;661#348#50#-1#1181#291#51#-1#1616#423#51#-1#1879#702#51#-1;BITRIX");
     }
+
+    #[test]
+    fn seeds_parse_all() {
+        let seeds = Seed::parse_all("661#348#50#-1#1105#3#102#-1");
+        assert_eq!(
+            seeds,
+            vec![
+                Seed { x: 661, y: 348, kind: 50 },
+                Seed { x: 1105, y: 3, kind: 102 },
+            ]
+        );
+
+        assert!(Seed::parse_all("").is_empty());
+        // неполная последняя группа
+        assert_eq!(Seed::parse_all("661#348#50#-1#1105#3").len(), 1);
+        assert_eq!(Seed::parse_all("661#x#50#-1#1105#3#102#-1").len(), 1);
+    }
 }
@@ -0,0 +1,76 @@
+use ::serde::Serialize;
+use ::tokio::sync::broadcast;
+
+use crate::manager::GameStatus;
+
+/// сколько событий может накопиться у отстающего подписчика
+const CAPACITY: usize = 256;
+
+/// Изменение в игре, о котором уведомляются ожидающие его запросы и страницы.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum GameEvent {
+    /// создана новая игра
+    GameCreated { game_id: u32 },
+    /// игрок записал свой ход
+    TurnSaved {
+        game_id: u32,
+        player_number: u32,
+        step_number: u32,
+    },
+    PlayerJoined { game_id: u32, user_id: u32 },
+    /// игрок вышел сам или был исключён владельцем
+    PlayerLeft { game_id: u32, user_id: u32 },
+    StatusChanged { game_id: u32, status: GameStatus },
+    /// игроки поставили игру на паузу или сняли её
+    PauseChanged { game_id: u32, paused: bool },
+}
+
+impl GameEvent {
+    pub fn game_id(&self) -> u32 {
+        match self {
+            Self::GameCreated { game_id }
+            | Self::TurnSaved { game_id, .. }
+            | Self::PlayerJoined { game_id, .. }
+            | Self::PlayerLeft { game_id, .. }
+            | Self::StatusChanged { game_id, .. }
+            | Self::PauseChanged { game_id, .. } => *game_id,
+        }
+    }
+
+    /// имя события в SSE-потоке, совпадает с полем `type`
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::GameCreated { .. } => "game_created",
+            Self::TurnSaved { .. } => "turn_saved",
+            Self::PlayerJoined { .. } => "player_joined",
+            Self::PlayerLeft { .. } => "player_left",
+            Self::StatusChanged { .. } => "status_changed",
+            Self::PauseChanged { .. } => "pause_changed",
+        }
+    }
+}
+
+/// Рассылка событий всем воркерам.
+#[derive(Debug, Clone)]
+pub struct Events {
+    tx: broadcast::Sender<GameEvent>,
+}
+
+impl Default for Events {
+    fn default() -> Self {
+        let (tx, _) = broadcast::channel(CAPACITY);
+        Self { tx }
+    }
+}
+
+impl Events {
+    pub fn publish(&self, event: GameEvent) {
+        // ошибка означает лишь то, что сейчас никто не подписан
+        let _ = self.tx.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<GameEvent> {
+        self.tx.subscribe()
+    }
+}
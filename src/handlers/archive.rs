@@ -1,15 +1,179 @@
+use ::chrono::{NaiveDate, NaiveDateTime};
+use ::sea_orm::{ItemsAndPagesNumber, PaginatorTrait, QueryOrder, QueryTrait};
+
 use super::*;
 
+use crate::manager::GameManagerError;
+use entity::game::GameType;
+
+/// количество игр на одной странице архива
+const PER_PAGE: u64 = 20;
+
 pub fn config(cfg: &mut web::ServiceConfig) {
-    cfg.service(
-        web::resource("").route(web::get().to(get)),
-    );
+    cfg.service(web::resource("").route(web::get().to(get)));
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ArchiveQuery {
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    page: Option<u64>,
+    /// id пользователя, участвовавшего в игре
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    player: Option<u32>,
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    world: Option<u32>,
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    track: Option<u32>,
+    /// `W` или `A`
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    game_type: Option<GameType>,
+    /// игры, завершённые не раньше этой даты
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    from: Option<NaiveDate>,
+    /// игры, завершённые не позже этой даты
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    to: Option<NaiveDate>,
+}
+
+impl ArchiveQuery {
+    /// строка запроса с текущими фильтрами, но без номера страницы
+    fn filters(&self) -> String {
+        let mut params = vec![];
+
+        if let Some(player) = self.player {
+            params.push(format!("player={player}"));
+        }
+        if let Some(world) = self.world {
+            params.push(format!("world={world}"));
+        }
+        if let Some(track) = self.track {
+            params.push(format!("track={track}"));
+        }
+        if let Some(game_type) = self.game_type {
+            params.push(format!("game_type={game_type}"));
+        }
+        if let Some(from) = self.from {
+            params.push(format!("from={from}"));
+        }
+        if let Some(to) = self.to {
+            params.push(format!("to={to}"));
+        }
+
+        params.join("&")
+    }
 }
 
 #[derive(Template)]
 #[template(path = "archive.html")]
-struct Archive {}
+struct Archive {
+    app: AppTpl,
+    query: ArchiveQuery,
+    filters: String,
+    games: Vec<ArchiveEntry>,
+    page: u64,
+    pages: u64,
+    total: u64,
+}
+
+struct ArchiveEntry {
+    game: entity::game::Model,
+    /// ходы первого шага вместе с игроками: в загруженных играх имена берутся из ходов
+    players: Vec<(entity::turn::Model, user::Model)>,
+}
+
+impl ArchiveEntry {
+    fn finished_at(&self) -> NaiveDateTime {
+        self.game.finished_at.unwrap_or(self.game.updated_at)
+    }
+}
+
+async fn get(
+    reg: Data<Registry>,
+    app: AppTpl,
+    Query(query): Query<ArchiveQuery>,
+) -> ::aw::Result<impl Responder> {
+    use entity::game::Column;
+
+    let mut select = entity::game::Entity::find()
+        .filter(Column::FinishedAt.is_not_null())
+        .filter(Column::IsPrivate.eq(false));
+
+    if let Some(player) = query.player {
+        select = select.filter(
+            Column::Id.in_subquery(
+                entity::turn::Entity::find()
+                    .select_only()
+                    .column(entity::turn::Column::GameId)
+                    .filter(entity::turn::Column::UserId.eq(player))
+                    .filter(entity::turn::Column::StepNumber.eq(1))
+                    .into_query(),
+            ),
+        );
+    }
+    if let Some(world) = query.world {
+        select = select.filter(Column::WorldId.eq(world));
+    }
+    if let Some(track) = query.track {
+        select = select.filter(Column::TrackId.eq(track));
+    }
+    if let Some(game_type) = query.game_type {
+        select = select.filter(Column::GameType.eq(game_type));
+    }
+    if let Some(from) = query.from {
+        select = select.filter(Column::FinishedAt.gte(from.and_hms_opt(0, 0, 0).unwrap()));
+    }
+    if let Some(to) = query.to {
+        select = select.filter(Column::FinishedAt.lte(to.and_hms_opt(23, 59, 59).unwrap()));
+    }
+
+    let paginator = select
+        .order_by_desc(Column::FinishedAt)
+        .order_by_desc(Column::Id)
+        .paginate(&reg.db, PER_PAGE);
+
+    let ItemsAndPagesNumber {
+        number_of_items: total,
+        number_of_pages: pages,
+    } = paginator
+        .num_items_and_pages()
+        .await
+        .map_err(GameManagerError::DbErr)?;
+
+    let page = query.page.unwrap_or(1).clamp(1, pages.max(1));
+
+    let games = paginator
+        .fetch_page(page - 1)
+        .await
+        .map_err(GameManagerError::DbErr)?;
+
+    let players = entity::turn::Entity::find()
+        .filter(entity::turn::Column::GameId.is_in(games.iter().map(|g| g.id)))
+        .filter(entity::turn::Column::StepNumber.eq(1))
+        .order_by_asc(entity::turn::Column::PlayerNumber)
+        .find_also_related(user::Entity)
+        .all(&reg.db)
+        .await
+        .map_err(GameManagerError::DbErr)?;
+
+    let games = games
+        .into_iter()
+        .map(|game| ArchiveEntry {
+            players: players
+                .iter()
+                .filter(|(t, _)| t.game_id == game.id)
+                .filter_map(|(t, u)| Some((t.clone(), u.clone()?)))
+                .collect(),
+            game,
+        })
+        .collect();
 
-async fn get() -> impl Responder {
-    Archive {}
+    Ok(Archive {
+        app,
+        filters: query.filters(),
+        query,
+        games,
+        page,
+        pages,
+        total,
+    })
 }
@@ -1,17 +1,25 @@
 use std::borrow::Cow;
 
+use ::sea_orm::{ConnectionTrait, PaginatorTrait, TransactionTrait};
+
 use super::*;
 
 use crate::{
-    manager::{GameManager, GameManagerError},
+    events::GameEvent,
+    manager::{GameManager, GameManagerError, GameStatus},
     middleware::Authenticated,
+    notifications, pause,
+    scheduler::ROBOT_STEAM_ID,
 };
 
-use entity::game::GameType;
+use entity::{car::Loadout, game::GameType, notification::NotificationKind};
 use migration::{Expr, IntoCondition};
 
 mod list;
 mod new;
+mod replay;
+mod sse;
+mod svg;
 mod view;
 
 pub fn config(cfg: &mut web::ServiceConfig) {
@@ -21,61 +29,514 @@ pub fn config(cfg: &mut web::ServiceConfig) {
             .route(web::post().to(new::post)),
     );
 
+    cfg.service(web::resource("events").route(web::get().to(sse::lobby)));
+
     cfg.service(
         web::resource("{game_id}")
             .route(web::get().to(view::handler))
             .route(web::post().to(HttpResponse::NotImplemented)),
     );
 
+    cfg.service(web::resource("{game_id}/events").route(web::get().to(sse::game)));
+    cfg.service(web::resource("{game_id}/replay.{format}").route(web::get().to(replay::export)));
+    cfg.service(web::resource("{game_id}/steps.svg").route(web::get().to(svg::steps)));
+    cfg.service(web::resource("{game_id}/steps/{step}.svg").route(web::get().to(svg::step)));
     cfg.service(web::resource("{game_id}/join").route(web::post().to(join)));
+    cfg.service(web::resource("{game_id}/leave").route(web::post().to(leave)));
+    cfg.service(web::resource("{game_id}/resign").route(web::post().to(resign)));
+    cfg.service(web::resource("{game_id}/pause").route(web::post().to(vote_pause)));
+    cfg.service(web::resource("{game_id}/kick/{user_id}").route(web::post().to(kick)));
+    cfg.service(web::resource("{game_id}/start").route(web::post().to(start)));
+    cfg.service(web::resource("{game_id}/cancel").route(web::post().to(cancel)));
+    cfg.service(web::resource("{game_id}/spectators").route(web::post().to(spectators)));
+    cfg.service(web::resource("{game_id}/rematch").route(web::post().to(rematch)));
+    cfg.service(web::resource("{game_id}/invite").route(web::post().to(invite_link)));
+    cfg.service(web::resource("{game_id}/invites").route(web::post().to(invite_player)));
 
     cfg.service(web::resource("").route(web::get().to(list::handler)));
 }
 
+/// секрет ссылки-приглашения в приватную игру
+#[derive(Debug, Deserialize)]
+struct InviteQuery {
+    token: Option<String>,
+}
+
+/// Проверяет, что игра видна пользователю `me`, пришедшему по ссылке с секретом `token`.
+///
+/// Приватную игру видят только её участники, приглашённые в неё
+/// и те, у кого есть действующая ссылка-приглашение. Для остальных
+/// её нет: ошибка та же, что и для несуществующей игры.
+pub(super) async fn check_visible<C: ConnectionTrait>(
+    db: &C,
+    game: &entity::game::Model,
+    me: Option<&user::Model>,
+    token: Option<&str>,
+) -> Result<(), GameManagerError> {
+    if !game.is_private || (token.is_some() && game.invite_token.as_deref() == token) {
+        return Ok(());
+    }
+
+    let Some(me) = me else {
+        Err(GameManagerError::GameNotFound(game.id))?
+    };
+    if me.id == game.owner_id {
+        return Ok(());
+    }
+
+    let joined = entity::turn::Entity::find()
+        .filter(entity::turn::Column::GameId.eq(game.id))
+        .filter(entity::turn::Column::UserId.eq(me.id))
+        .count(db)
+        .await?;
+    let invited = entity::invite::Entity::find()
+        .filter(entity::invite::Column::GameId.eq(game.id))
+        .filter(entity::invite::Column::UserId.eq(me.id))
+        .count(db)
+        .await?;
+
+    match joined + invited {
+        0 => Err(GameManagerError::GameNotFound(game.id)),
+        _ => Ok(()),
+    }
+}
+
 async fn join(
     reg: Data<Registry>,
-    app: AppTpl,
+    me: Option<Authenticated>,
     path: Path<u32>,
+    Query(query): Query<InviteQuery>,
     req: HttpRequest,
 ) -> ::aw::Result<impl Responder> {
     let game_id = path.into_inner();
 
-    if app.me.is_none() {
+    let Some(me) = me.as_deref() else {
         return Ok(Redirect::to(format!("/games/{}", game_id))
             .see_other()
             .respond_to(&req)
             .map_into_boxed_body());
+    };
+
+    let txn = reg.db.begin().await.map_err(GameManagerError::DbErr)?;
+    let mut manager = GameManager::lock_game(&txn, game_id).await?;
+
+    // приглашённый в реванш входит с мехосом из прошлой игры, если его сохранили
+    let invite = entity::invite::Entity::find()
+        .filter(entity::invite::Column::GameId.eq(game_id))
+        .filter(entity::invite::Column::UserId.eq(me.id))
+        .one(&txn)
+        .await
+        .map_err(GameManagerError::DbErr)?;
+    let loadout = invite
+        .as_ref()
+        .and_then(|i| i.loadout())
+        .unwrap_or_else(|| me.loadout());
+
+    // приглашённым в реванш ссылка не нужна
+    if invite.is_none() {
+        manager.check_invite(query.token.as_deref())?;
     }
 
-    let manager = GameManager::load_game(&reg.db, game_id).await?;
+    match manager.join_with(me, loadout).await {
+        Ok(()) => {
+            if let Some(invite) = invite {
+                invite.delete(&txn).await.map_err(GameManagerError::DbErr)?;
+            }
 
-    let is_already_joined = manager
-        .turns
-        .iter()
-        .find(|(_, u)| u.id == app.me.as_ref().unwrap().id)
-        .is_some();
-    let game_is_full = manager.turns.len() >= manager.game.players_cnt as usize;
-    if is_already_joined || game_is_full {
-        return Ok(Redirect::to(format!("/games/{}", game_id))
-            .see_other()
-            .respond_to(&req)
-            .map_into_boxed_body());
+            let status = manager.status();
+            txn.commit().await.map_err(GameManagerError::DbErr)?;
+
+            reg.notify(GameEvent::PlayerJoined {
+                game_id,
+                user_id: me.id,
+            });
+            if status == GameStatus::Started {
+                reg.notify(GameEvent::StatusChanged { game_id, status });
+            }
+        }
+        // повторное нажатие или заполненная игра: просто возвращаем на страницу игры
+        Err(GameManagerError::AlreadyJoined(_) | GameManagerError::GameNotOpen(_)) => {}
+        Err(e) => Err(e)?,
     }
 
-    let _turn = {
-        let mut p = <entity::turn::ActiveModel as ::sea_orm::ActiveModelTrait>::default();
-        p.user_id = ActiveValue::Set(app.me.as_ref().unwrap().id);
-        p.game_id = ActiveValue::Set(manager.game.id);
-        p.player_number = ActiveValue::Set(manager.turns.len() as u32);
-        p.step_number = ActiveValue::Set(1);
-        p
+    Ok(Redirect::to(format!("/games/{}", game_id))
+        .see_other()
+        .respond_to(&req)
+        .map_into_boxed_body())
+}
+
+async fn leave(
+    reg: Data<Registry>,
+    path: Path<u32>,
+    req: HttpRequest,
+    Authenticated(me): Authenticated,
+) -> ::aw::Result<impl Responder> {
+    let game_id = path.into_inner();
+
+    let txn = reg.db.begin().await.map_err(GameManagerError::DbErr)?;
+    let mut manager = GameManager::lock_game(&txn, game_id).await?;
+    manager.leave(me.id).await?;
+    txn.commit().await.map_err(GameManagerError::DbErr)?;
+    reg.notify(GameEvent::PlayerLeft {
+        game_id,
+        user_id: me.id,
+    });
+
+    Ok(Redirect::to(format!("/games/{}", game_id))
+        .see_other()
+        .respond_to(&req)
+        .map_into_boxed_body())
+}
+
+/// игрок сдаётся в идущей игре, остальные продолжают без него
+async fn resign(
+    reg: Data<Registry>,
+    path: Path<u32>,
+    req: HttpRequest,
+    Authenticated(me): Authenticated,
+) -> ::aw::Result<impl Responder> {
+    let game_id = path.into_inner();
+
+    let txn = reg.db.begin().await.map_err(GameManagerError::DbErr)?;
+    let mut manager = GameManager::lock_game(&txn, game_id).await?;
+    let step_number = manager.move_cnt() + 1;
+
+    match manager.resign(me.id).await {
+        Ok(()) => {
+            let player_number = manager
+                .turns
+                .iter()
+                .find(|(t, u)| u.id == me.id && !t.is_robot)
+                .map(|(t, _)| t.player_number)
+                .unwrap_or_default();
+            let status = manager.status();
+            txn.commit().await.map_err(GameManagerError::DbErr)?;
+
+            // ожидающие хода остальные игроки получают пустой ход сдавшегося
+            reg.notify(GameEvent::TurnSaved {
+                game_id,
+                player_number,
+                step_number,
+            });
+            if status != GameStatus::Started {
+                reg.notify(GameEvent::StatusChanged { game_id, status });
+            }
+        }
+        // повторное нажатие
+        Err(GameManagerError::PlayerResigned(_)) => {}
+        Err(e) => Err(e)?,
+    }
+
+    Ok(Redirect::to(format!("/games/{}", game_id))
+        .see_other()
+        .respond_to(&req)
+        .map_into_boxed_body())
+}
+
+#[derive(Debug, Deserialize)]
+struct FormPause {
+    /// `true` — голос за паузу, `false` — за продолжение игры
+    pause: bool,
+}
+
+/// игрок голосует за паузу в идущей игре или за её снятие
+async fn vote_pause(
+    reg: Data<Registry>,
+    path: Path<u32>,
+    form: Form<FormPause>,
+    req: HttpRequest,
+    Authenticated(me): Authenticated,
+) -> ::aw::Result<impl Responder> {
+    let game_id = path.into_inner();
+
+    let txn = reg.db.begin().await.map_err(GameManagerError::DbErr)?;
+    let mut manager = GameManager::lock_game(&txn, game_id).await?;
+    let changed = pause::vote(&txn, &mut manager, me.id, form.pause).await?;
+    txn.commit().await.map_err(GameManagerError::DbErr)?;
+
+    if changed {
+        reg.notify(GameEvent::PauseChanged {
+            game_id,
+            paused: form.pause,
+        });
+    }
+
+    Ok(Redirect::to(format!("/games/{}", game_id))
+        .see_other()
+        .respond_to(&req)
+        .map_into_boxed_body())
+}
+
+async fn kick(
+    reg: Data<Registry>,
+    path: Path<(u32, u32)>,
+    req: HttpRequest,
+    Authenticated(me): Authenticated,
+) -> ::aw::Result<HttpResponse> {
+    let (game_id, user_id) = path.into_inner();
+
+    let txn = reg.db.begin().await.map_err(GameManagerError::DbErr)?;
+    let mut manager = GameManager::lock_game(&txn, game_id).await?;
+    if manager.game.owner_id != me.id {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+
+    manager.leave(user_id).await?;
+    txn.commit().await.map_err(GameManagerError::DbErr)?;
+    reg.notify(GameEvent::PlayerLeft { game_id, user_id });
+
+    Ok(Redirect::to(format!("/games/{}", game_id))
+        .see_other()
+        .respond_to(&req)
+        .map_into_boxed_body())
+}
+
+/// владелец начинает набирающую игроков игру с теми, кто уже вошёл
+async fn start(
+    reg: Data<Registry>,
+    path: Path<u32>,
+    req: HttpRequest,
+    Authenticated(me): Authenticated,
+) -> ::aw::Result<HttpResponse> {
+    let game_id = path.into_inner();
+
+    let txn = reg.db.begin().await.map_err(GameManagerError::DbErr)?;
+    let mut manager = GameManager::lock_game(&txn, game_id).await?;
+    if manager.game.owner_id != me.id {
+        return Ok(HttpResponse::Forbidden().finish());
     }
-    .insert(&reg.db)
-    .await
-    .map_err(GameManagerError::DbErr)?;
 
-    return Ok(Redirect::to(format!("/games/{}", game_id))
+    manager.start().await?;
+    txn.commit().await.map_err(GameManagerError::DbErr)?;
+    reg.notify(GameEvent::StatusChanged {
+        game_id,
+        status: GameStatus::Started,
+    });
+
+    Ok(Redirect::to(format!("/games/{}", game_id))
+        .see_other()
+        .respond_to(&req)
+        .map_into_boxed_body())
+}
+
+async fn cancel(
+    reg: Data<Registry>,
+    path: Path<u32>,
+    req: HttpRequest,
+    Authenticated(me): Authenticated,
+) -> ::aw::Result<HttpResponse> {
+    let game_id = path.into_inner();
+
+    let txn = reg.db.begin().await.map_err(GameManagerError::DbErr)?;
+    let mut manager = GameManager::lock_game(&txn, game_id).await?;
+    if manager.game.owner_id != me.id {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+
+    manager.cancel().await?;
+    txn.commit().await.map_err(GameManagerError::DbErr)?;
+    reg.notify(GameEvent::StatusChanged {
+        game_id,
+        status: GameStatus::Cancelled,
+    });
+
+    Ok(Redirect::to(format!("/games/{}", game_id))
+        .see_other()
+        .respond_to(&req)
+        .map_into_boxed_body())
+}
+
+#[derive(Debug, Deserialize)]
+struct FormSpectators {
+    enabled: bool,
+}
+
+/// владелец открывает игру для зрителей или закрывает её
+async fn spectators(
+    reg: Data<Registry>,
+    path: Path<u32>,
+    form: Form<FormSpectators>,
+    req: HttpRequest,
+    Authenticated(me): Authenticated,
+) -> ::aw::Result<HttpResponse> {
+    let game_id = path.into_inner();
+
+    let txn = reg.db.begin().await.map_err(GameManagerError::DbErr)?;
+    let mut manager = GameManager::lock_game(&txn, game_id).await?;
+    if manager.game.owner_id != me.id {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+
+    manager.set_spectators(form.enabled).await?;
+    txn.commit().await.map_err(GameManagerError::DbErr)?;
+    reg.games.invalidate(game_id);
+
+    Ok(Redirect::to(format!("/games/{}", game_id))
+        .see_other()
+        .respond_to(&req)
+        .map_into_boxed_body())
+}
+
+#[derive(Debug, Deserialize)]
+struct FormRematch {
+    /// `on`, если участники войдут в новую игру с теми же мехосами
+    keep_loadouts: Option<String>,
+}
+
+/// Участник завершённой игры создаёт новую с теми же настройками
+/// и приглашает в неё остальных участников.
+async fn rematch(
+    reg: Data<Registry>,
+    path: Path<u32>,
+    form: Form<FormRematch>,
+    req: HttpRequest,
+    Authenticated(me): Authenticated,
+) -> ::aw::Result<HttpResponse> {
+    let game_id = path.into_inner();
+    let keep_loadouts = form.keep_loadouts.is_some();
+
+    let finished = GameManager::load_game(&reg.db, game_id).await?;
+    let participants = finished.last_loadouts();
+    let Some(&(_, my_loadout)) = participants.iter().find(|(u, _)| u.id == me.id) else {
+        return Ok(HttpResponse::Forbidden().finish());
+    };
+
+    let game = finished.rematch(me.id)?;
+    let loadout = match keep_loadouts {
+        true => my_loadout,
+        false => me.loadout(),
+    };
+
+    let txn = reg.db.begin().await.map_err(GameManagerError::DbErr)?;
+    let game = GameManager::create(&txn, game, &me, loadout).await?.game;
+
+    for (user, loadout) in participants.iter().filter(|(u, _)| u.id != me.id) {
+        let loadout = keep_loadouts.then_some(*loadout);
+        invite_user(&txn, game.id, user.id, loadout)
+            .await
+            .map_err(GameManagerError::DbErr)?;
+    }
+
+    txn.commit().await.map_err(GameManagerError::DbErr)?;
+    reg.notify(GameEvent::GameCreated { game_id: game.id });
+
+    Ok(Redirect::to(format!("/games/{}", game.id))
+        .see_other()
+        .respond_to(&req)
+        .map_into_boxed_body())
+}
+
+/// Приглашает пользователя в игру и уведомляет его об этом.
+///
+/// С `loadout` приглашённый войдёт в игру на этом мехосе, без него — на своём.
+async fn invite_user<C: ConnectionTrait>(
+    db: &C,
+    game_id: u32,
+    user_id: u32,
+    loadout: Option<Loadout>,
+) -> Result<(), DbErr> {
+    use ActiveValue::*;
+
+    entity::invite::ActiveModel {
+        game_id: Set(game_id),
+        user_id: Set(user_id),
+        prop_pers: Set(loadout.map(|l| l.pers)),
+        prop_car: Set(loadout.map(|l| l.car)),
+        prop_fwheel: Set(loadout.map(|l| l.fwheel)),
+        prop_bwheel: Set(loadout.map(|l| l.bwheel)),
+        ..Default::default()
+    }
+    .insert(db)
+    .await?;
+
+    notifications::push(db, &[user_id], game_id, NotificationKind::Invited, 0).await
+}
+
+#[derive(Debug, Deserialize)]
+struct FormInvitePlayer {
+    user_id: u32,
+}
+
+/// владелец набирающей игроков игры приглашает в неё пользователя
+async fn invite_player(
+    reg: Data<Registry>,
+    path: Path<u32>,
+    form: Form<FormInvitePlayer>,
+    req: HttpRequest,
+    Authenticated(me): Authenticated,
+) -> ::aw::Result<HttpResponse> {
+    let game_id = path.into_inner();
+    let user_id = form.user_id;
+
+    let txn = reg.db.begin().await.map_err(GameManagerError::DbErr)?;
+    let manager = GameManager::lock_game(&txn, game_id).await?;
+    if manager.game.owner_id != me.id {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    if manager.status() != GameStatus::Open {
+        Err(GameManagerError::GameNotOpen(game_id))?
+    }
+    if manager.turns.iter().any(|(_, u)| u.id == user_id) {
+        Err(GameManagerError::AlreadyJoined(user_id))?
+    }
+
+    // за роботов никто не играет сам, приглашать их незачем
+    let user = user::Entity::find_by_id(user_id)
+        .filter(user::Column::SteamId.ne(ROBOT_STEAM_ID))
+        .one(&txn)
+        .await
+        .map_err(GameManagerError::DbErr)?;
+    if user.is_none() {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+
+    let invited = entity::invite::Entity::find()
+        .filter(entity::invite::Column::GameId.eq(game_id))
+        .filter(entity::invite::Column::UserId.eq(user_id))
+        .count(&txn)
+        .await
+        .map_err(GameManagerError::DbErr)?;
+    if invited == 0 {
+        invite_user(&txn, game_id, user_id, None)
+            .await
+            .map_err(GameManagerError::DbErr)?;
+    }
+    txn.commit().await.map_err(GameManagerError::DbErr)?;
+
+    Ok(Redirect::to(format!("/games/{}", game_id))
+        .see_other()
+        .respond_to(&req)
+        .map_into_boxed_body())
+}
+
+#[derive(Debug, Deserialize)]
+struct FormInviteLink {
+    /// `false` отзывает ссылку, `true` выдаёт новую
+    enabled: bool,
+}
+
+/// владелец приватной игры выдаёт новую ссылку-приглашение или отзывает текущую
+async fn invite_link(
+    reg: Data<Registry>,
+    path: Path<u32>,
+    form: Form<FormInviteLink>,
+    req: HttpRequest,
+    Authenticated(me): Authenticated,
+) -> ::aw::Result<HttpResponse> {
+    let game_id = path.into_inner();
+
+    let txn = reg.db.begin().await.map_err(GameManagerError::DbErr)?;
+    let mut manager = GameManager::lock_game(&txn, game_id).await?;
+    if manager.game.owner_id != me.id {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+
+    manager.set_invite_token(form.enabled).await?;
+    txn.commit().await.map_err(GameManagerError::DbErr)?;
+    reg.games.invalidate(game_id);
+
+    Ok(Redirect::to(format!("/games/{}", game_id))
         .see_other()
         .respond_to(&req)
-        .map_into_boxed_body());
+        .map_into_boxed_body())
 }
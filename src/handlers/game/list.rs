@@ -1,10 +1,21 @@
+use ::sea_orm::{
+    sea_query::Alias, Condition, ItemsAndPagesNumber, Order, PaginatorTrait, QueryOrder,
+};
+
 use super::*;
 
+/// количество игр на одной странице списка
+const PER_PAGE: u64 = 25;
+
 #[derive(Template)]
 #[template(path = "games/list.html")]
 struct GamelistView {
     app: AppTpl,
+    query: GamelistQuery,
+    filters: String,
     games: Vec<GameDataView>,
+    page: u64,
+    pages: u64,
 }
 
 #[derive(FromQueryResult)]
@@ -27,13 +38,147 @@ struct GameDataView {
     login: Option<String>,
     /// steam_id владельца игры
     steam_id: i32,
+    finished_at: Option<::chrono::NaiveDateTime>,
+    paused_at: Option<::chrono::NaiveDateTime>,
+    starts_at: Option<::chrono::NaiveDateTime>,
     created_at: ::chrono::NaiveDateTime,
     updated_at: ::chrono::NaiveDateTime,
 }
 
-pub(super) async fn handler(reg: Data<Registry>, app: AppTpl) -> ::aw::Result<impl Responder> {
-    let games = entity::game::Entity::find()
-        .column_as(entity::game::Column::Id.count(), "players_registered")
+impl GameDataView {
+    fn status(&self) -> &'static str {
+        if self.finished_at.is_some() {
+            "finished"
+        } else if self.starts_at > Some(::chrono::Utc::now().naive_utc()) {
+            // до назначенного времени набор открыт, даже если все места заняты
+            "open"
+        } else if self.players_registered < self.players_cnt {
+            "open"
+        } else if self.paused_at.is_some() {
+            "paused"
+        } else {
+            "in progress"
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ListStatus {
+    /// есть свободные слоты или не наступило время начала
+    Open,
+    /// все слоты заняты и время начала наступило, игра идёт
+    Started,
+    Finished,
+}
+
+impl FromStr for ListStatus {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "open" => Self::Open,
+            "started" => Self::Started,
+            "finished" => Self::Finished,
+            _ => Err(())?,
+        })
+    }
+}
+
+impl std::fmt::Display for ListStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Open => "open",
+            Self::Started => "started",
+            Self::Finished => "finished",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum ListSort {
+    #[default]
+    Newest,
+    Oldest,
+    /// сначала игры с наибольшим числом зарегистрированных игроков
+    Players,
+}
+
+impl FromStr for ListSort {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "newest" => Self::Newest,
+            "oldest" => Self::Oldest,
+            "players" => Self::Players,
+            _ => Err(())?,
+        })
+    }
+}
+
+impl std::fmt::Display for ListSort {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Newest => "newest",
+            Self::Oldest => "oldest",
+            Self::Players => "players",
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub(super) struct GamelistQuery {
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    page: Option<u64>,
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    status: Option<ListStatus>,
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    world: Option<u32>,
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    track: Option<u32>,
+    /// id владельца игры
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    owner: Option<u32>,
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    sort: Option<ListSort>,
+}
+
+impl GamelistQuery {
+    /// строка запроса с текущими фильтрами, но без номера страницы
+    fn filters(&self) -> String {
+        let mut params = vec![];
+
+        if let Some(status) = self.status {
+            params.push(format!("status={status}"));
+        }
+        if let Some(world) = self.world {
+            params.push(format!("world={world}"));
+        }
+        if let Some(track) = self.track {
+            params.push(format!("track={track}"));
+        }
+        if let Some(owner) = self.owner {
+            params.push(format!("owner={owner}"));
+        }
+        if let Some(sort) = self.sort {
+            params.push(format!("sort={sort}"));
+        }
+
+        params.join("&")
+    }
+}
+
+pub(super) async fn handler(
+    reg: Data<Registry>,
+    app: AppTpl,
+    Query(query): Query<GamelistQuery>,
+) -> ::aw::Result<impl Responder> {
+    use entity::game::Column;
+
+    let players_registered = Expr::col((entity::turn::Entity, entity::turn::Column::Id)).count();
+
+    let mut select = entity::game::Entity::find()
+        .column_as(players_registered.clone(), "players_registered")
         .columns([entity::user::Column::Login, entity::user::Column::SteamId])
         .join(
             JoinType::Join,
@@ -46,11 +191,87 @@ pub(super) async fn handler(reg: Data<Registry>, app: AppTpl) -> ::aw::Result<im
                 }),
         )
         .join(JoinType::Join, entity::game::Relation::User.def())
-        .group_by(entity::game::Column::Id)
+        .filter(Column::CancelledAt.is_null())
+        .filter(Column::IsPrivate.eq(false))
+        .group_by(Column::Id);
+
+    // условия на отдельные строки `game` сужают выборку до группировки,
+    // условие на число игроков приходится проверять уже после неё
+    let now = ::chrono::Utc::now().naive_utc();
+    match query.status {
+        Some(ListStatus::Open) => {
+            // до назначенного времени набор открыт, даже если все места заняты
+            select = select.filter(Column::FinishedAt.is_null()).having(
+                Condition::any()
+                    .add(Expr::col((entity::game::Entity, Column::StartsAt)).gt(now))
+                    .add(
+                        Expr::expr(players_registered)
+                            .lt(Expr::col((entity::game::Entity, Column::PlayersCnt))),
+                    ),
+            );
+        }
+        Some(ListStatus::Started) => {
+            select = select
+                .filter(Column::FinishedAt.is_null())
+                .filter(
+                    Condition::any()
+                        .add(Column::StartsAt.is_null())
+                        .add(Column::StartsAt.lte(now)),
+                )
+                .having(
+                    Expr::expr(players_registered)
+                        .gte(Expr::col((entity::game::Entity, Column::PlayersCnt))),
+                );
+        }
+        Some(ListStatus::Finished) => {
+            select = select.filter(Column::FinishedAt.is_not_null());
+        }
+        None => {}
+    }
+    if let Some(world) = query.world {
+        select = select.filter(Column::WorldId.eq(world));
+    }
+    if let Some(track) = query.track {
+        select = select.filter(Column::TrackId.eq(track));
+    }
+    if let Some(owner) = query.owner {
+        select = select.filter(Column::OwnerId.eq(owner));
+    }
+
+    select = match query.sort.unwrap_or_default() {
+        ListSort::Newest => select.order_by_desc(Column::CreatedAt),
+        ListSort::Oldest => select.order_by_asc(Column::CreatedAt),
+        ListSort::Players => select
+            .order_by(Expr::col(Alias::new("players_registered")), Order::Desc)
+            .order_by_desc(Column::CreatedAt),
+    }
+    .order_by_desc(Column::Id);
+
+    let paginator = select
         .into_model::<GameDataView>()
-        .all(&reg.db)
+        .paginate(&reg.db, PER_PAGE);
+
+    let ItemsAndPagesNumber {
+        number_of_pages: pages,
+        ..
+    } = paginator
+        .num_items_and_pages()
+        .await
+        .map_err(GameManagerError::DbErr)?;
+
+    let page = query.page.unwrap_or(1).clamp(1, pages.max(1));
+
+    let games = paginator
+        .fetch_page(page - 1)
         .await
         .map_err(GameManagerError::DbErr)?;
 
-    Ok(GamelistView { app, games })
+    Ok(GamelistView {
+        app,
+        filters: query.filters(),
+        query,
+        games,
+        page,
+        pages,
+    })
 }
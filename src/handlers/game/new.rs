@@ -1,25 +1,95 @@
 use super::*;
 
+use crate::manager::new_invite_token;
+use entity::{
+    car::Loadout,
+    game::{World, WorldInfo},
+};
+
+/// формат поля `datetime-local`
+const STARTS_AT_FORMAT: &str = "%Y-%m-%dT%H:%M";
+
+/// насколько далеко вперёд можно назначить начало игры
+const MAX_SCHEDULE_DAYS: i64 = 30;
+
+/// самый долгий ход в игре по переписке, в часах
+const MAX_TURN_HOURS: u32 = 24 * 14;
+
 #[derive(Template)]
 #[template(path = "./games/new.html")]
 struct GameNew {
     app: AppTpl,
+    worlds: Vec<WorldInfo>,
     error: Vec<Cow<'static, str>>,
 }
 
+impl GameNew {
+    fn new(app: AppTpl, error: Vec<Cow<'static, str>>) -> Self {
+        Self {
+            app,
+            worlds: World::catalog(),
+            error,
+        }
+    }
+}
+
 pub(super) async fn get(app: AppTpl) -> impl Responder {
-    GameNew { app, error: vec![] }
+    GameNew::new(app, vec![])
+}
+
+/// выбранная в форме трасса: `random` или `{world_id}:{track_id}`
+#[derive(Debug, Clone, Copy)]
+enum TrackChoice {
+    Random,
+    Track(World),
+}
+
+impl FromStr for TrackChoice {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "random" {
+            return Ok(Self::Random);
+        }
+
+        let (world_id, track_id) = s.split_once(':').ok_or(())?;
+        let world_id = world_id.parse::<u32>().map_err(|_| ())?;
+        let track_id = track_id.parse::<u32>().map_err(|_| ())?;
+
+        match World::try_from((world_id, track_id)) {
+            Ok(world) if world.is_available() => Ok(Self::Track(world)),
+            _ => Err(()),
+        }
+    }
+}
+
+impl TrackChoice {
+    fn world(self) -> World {
+        match self {
+            Self::Random => World::random(),
+            Self::Track(world) => world,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
 pub(super) struct FormGameNew {
-    track_id: u32,
+    track: String,
     game_type: GameType,
     laps: u32,
     seeds: u32,
+    /// шагов гонки в экспресс-игре или часов на ход в игре по переписке
     duration: u32,
-    // is_express: bool,
+    /// `on` для экспресс-игры, иначе игра идёт по переписке
+    is_express: Option<String>,
     players_cnt: u32,
+    /// `on`, если игра приватная
+    is_private: Option<String>,
+    /// время начала по UTC в формате [`STARTS_AT_FORMAT`]
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    starts_at: Option<String>,
+    /// `on`, если к началу свободные места займут роботы
+    fill_with_robots: Option<String>,
 }
 
 pub(super) async fn post(
@@ -29,10 +99,13 @@ pub(super) async fn post(
     Authenticated(user): Authenticated,
     app: AppTpl,
 ) -> impl Responder {
-    use ActiveValue::*;
-
     let mut error = vec![];
 
+    let track = form.track.parse::<TrackChoice>();
+    if track.is_err() {
+        error.push(Cow::Borrowed("`track` must be chosen from the list"));
+    }
+
     if form.laps > 50 {
         error.push(Cow::Borrowed("`laps` must be less than 50"));
     }
@@ -41,56 +114,73 @@ pub(super) async fn post(
         error.push(Cow::Borrowed("`seeds` must be less than 1000"));
     }
 
-    if form.duration < 10 || form.duration > 34000 {
+    let is_express = form.is_express.is_some();
+    if is_express && (form.duration < 10 || form.duration > 34000) {
         error.push(Cow::Borrowed("`duration` must be between 10 and 34000"));
     }
+    if !is_express && (form.duration < 1 || form.duration > MAX_TURN_HOURS) {
+        error.push(Cow::Borrowed(
+            "`duration` of a correspondence game must be between 1 and 336 hours",
+        ));
+    }
 
     if form.players_cnt < 2 || form.players_cnt > 5 {
         error.push(Cow::Borrowed("`players` must be between 2 and 5"));
     }
 
-    if !error.is_empty() {
-        return GameNew { app, error }.respond_to(&req);
-    }
-
-    let game = {
-        let mut g = <entity::game::ActiveModel as ::sea_orm::ActiveModelTrait>::default();
-
-        g.is_express = Set(true);
-        g.owner_id = Set(user.id);
-        g.world_id = Set(0); // TODO: set correct world
-        g.track_id = Set(0); // TODO: set correct track
-        g.game_type = Set(form.game_type);
-        g.laps = Set(form.laps);
-        g.seeds = Set(form.seeds);
-        g.duration = Set(form.duration);
-        g.is_express = Set(true);
-        g.players_cnt = Set(form.players_cnt);
-        g
-    };
-
-    let game = match game.insert(&reg.db).await {
-        Ok(g) => g,
+    let starts_at = form.starts_at.as_deref().map(|s| {
+        ::chrono::NaiveDateTime::parse_from_str(s, STARTS_AT_FORMAT)
+            .map_err(|_| "`starts at` must be a date and time")
+            .and_then(|t| {
+                let now = ::chrono::Utc::now().naive_utc();
+                if t <= now {
+                    Err("`starts at` must be in the future")
+                } else if t > now + ::chrono::Duration::days(MAX_SCHEDULE_DAYS) {
+                    Err("`starts at` must be within 30 days")
+                } else {
+                    Ok(t)
+                }
+            })
+    });
+    let starts_at = match starts_at.transpose() {
+        Ok(t) => t,
         Err(e) => {
-            error.push(Cow::Owned(e.to_string()));
-            return GameNew { app, error }.respond_to(&req);
+            error.push(Cow::Borrowed(e));
+            None
         }
     };
 
-    let turn = {
-        let mut t = <entity::turn::ActiveModel as ::sea_orm::ActiveModelTrait>::default();
-        t.game_id = Set(game.id);
-        t.user_id = Set(user.id);
-        t.player_number = Set(0);
-        t.step_number = Set(1);
-        t
+    if form.fill_with_robots.is_some() && starts_at.is_none() {
+        error.push(Cow::Borrowed(
+            "`starts at` is required to fill the game with robots",
+        ));
+    }
+
+    let world = match track {
+        Ok(track) if error.is_empty() => track.world(),
+        _ => return GameNew::new(app, error).respond_to(&req),
     };
 
-    let _turn = match turn.insert(&reg.db).await {
-        Ok(t) => t,
+    let mut game = entity::game::Model::new(
+        user.id,
+        world,
+        form.game_type,
+        form.laps,
+        form.seeds,
+        form.duration,
+        form.players_cnt,
+    );
+    game.is_express = is_express;
+    game.is_private = form.is_private.is_some();
+    game.invite_token = game.is_private.then(new_invite_token);
+    game.starts_at = starts_at;
+    game.fill_with_robots = form.fill_with_robots.is_some();
+
+    let game = match create_game(&reg, game, &user, user.loadout()).await {
+        Ok(g) => g,
         Err(e) => {
             error.push(Cow::Owned(e.to_string()));
-            return GameNew { app, error }.respond_to(&req);
+            return GameNew::new(app, error).respond_to(&req);
         }
     };
 
@@ -99,3 +189,19 @@ pub(super) async fn post(
         .respond_to(&req)
         .map_into_boxed_body()
 }
+
+/// создаёт игру вместе с ходом владельца и сообщает о ней в лобби
+async fn create_game(
+    reg: &Registry,
+    game: entity::game::Model,
+    owner: &entity::user::Model,
+    loadout: Loadout,
+) -> Result<entity::game::Model, GameManagerError> {
+    let txn = reg.db.begin().await?;
+    let game = GameManager::create(&txn, game, owner, loadout).await?.game;
+    txn.commit().await?;
+
+    reg.notify(GameEvent::GameCreated { game_id: game.id });
+
+    Ok(game)
+}
@@ -0,0 +1,52 @@
+use super::*;
+
+use crate::replay::{Replay, ReplayError};
+
+impl ResponseError for ReplayError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ReplayError::GameNotFinished(_) => StatusCode::CONFLICT,
+            ReplayError::Manager(e) => e.status_code(),
+            _ => StatusCode::BAD_REQUEST,
+        }
+    }
+}
+
+/// в каком виде выгрузить запись игры
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(super) enum ReplayFormat {
+    /// `OG_GAME_PACKET` по строке на ход
+    Txt,
+    Json,
+}
+
+pub(super) async fn export(
+    reg: Data<Registry>,
+    me: Option<Authenticated>,
+    path: Path<(u32, ReplayFormat)>,
+    Query(query): Query<InviteQuery>,
+) -> ::aw::Result<HttpResponse> {
+    let (game_id, format) = path.into_inner();
+
+    let gm = GameManager::load_game(&reg.db, game_id).await?;
+    check_visible(&reg.db, &gm.game, me.as_deref(), query.token.as_deref()).await?;
+    let replay = Replay::from_game(&gm)?;
+
+    let (body, content_type, ext) = match format {
+        ReplayFormat::Txt => (replay.to_text(), "text/plain; charset=utf-8", "txt"),
+        ReplayFormat::Json => (
+            ::serde_json::to_string_pretty(&replay).map_err(ReplayError::Json)?,
+            "application/json",
+            "json",
+        ),
+    };
+
+    Ok(HttpResponse::Ok()
+        .insert_header((header::CONTENT_TYPE, content_type))
+        .insert_header((
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"game-{}.replay.{}\"", game_id, ext),
+        ))
+        .body(body))
+}
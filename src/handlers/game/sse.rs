@@ -0,0 +1,71 @@
+use std::time::Duration;
+
+use ::actix_web_lab::sse;
+use ::futures::{stream, StreamExt};
+use ::tokio::sync::broadcast::error::RecvError;
+
+use super::*;
+
+/// пустые сообщения не дают прокси закрыть долгое соединение
+const KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+/// события всех публичных игр, для страницы списка игр
+pub(super) async fn lobby(reg: Data<Registry>) -> impl Responder {
+    stream_events(reg, None)
+}
+
+/// события одной игры, для её страницы
+pub(super) async fn game(
+    reg: Data<Registry>,
+    me: Option<Authenticated>,
+    path: Path<u32>,
+    Query(query): Query<InviteQuery>,
+) -> ::aw::Result<impl Responder> {
+    let game_id = path.into_inner();
+
+    let manager = reg.games.load_game(&reg.db, game_id).await?;
+    check_visible(
+        &reg.db,
+        &manager.game,
+        me.as_deref(),
+        query.token.as_deref(),
+    )
+    .await?;
+
+    Ok(stream_events(reg, Some(game_id)))
+}
+
+fn stream_events(reg: Data<Registry>, game_id: Option<u32>) -> impl Responder {
+    let rx = reg.events.subscribe();
+    let events = stream::unfold(rx, |mut rx| async move {
+        loop {
+            match rx.recv().await {
+                Ok(event) => return Some((event, rx)),
+                // пропущенные события не страшны: страница всё равно перечитает состояние целиком
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    })
+    .filter(move |event| {
+        let reg = reg.clone();
+        let event_game_id = event.game_id();
+        async move {
+            match game_id {
+                Some(id) => event_game_id == id,
+                // приватные игры не попадают в общий список
+                None => reg
+                    .games
+                    .load_game(&reg.db, event_game_id)
+                    .await
+                    .is_ok_and(|gm| !gm.game.is_private),
+            }
+        }
+    })
+    .map(|event| {
+        let data = ::serde_json::to_string(&event).unwrap();
+        sse::Event::Data(sse::Data::new(data).event(event.name()))
+    });
+
+    sse::Sse::from_infallible_stream(events).with_keep_alive(KEEP_ALIVE)
+}
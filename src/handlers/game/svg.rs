@@ -0,0 +1,192 @@
+use super::*;
+
+/// отступ от крайних семян до края картинки, с запасом на радиус кружка
+const MARGIN: i32 = 64;
+/// высота строки в легенде с именами игроков
+const LEGEND_ROW: i32 = 48;
+/// ширина, в которую помещаются имена игроков
+const LEGEND_WIDTH: i32 = 480;
+/// цвета игроков по их номерам
+const PALETTE: [&str; 5] = ["#d62728", "#1f77b4", "#2ca02c", "#ff7f0e", "#9467bd"];
+
+#[derive(Template)]
+#[template(path = "games/steps.svg")]
+struct StepsSvg {
+    view: ViewBox,
+    players: Vec<SvgPlayer>,
+    steps: Vec<SvgStep>,
+}
+
+impl StepsSvg {
+    /// верх строки игрока в легенде
+    fn legend_y(&self, number: u32) -> i32 {
+        self.view.y + LEGEND_ROW * (number as i32 + 1)
+    }
+}
+
+struct SvgPlayer {
+    number: u32,
+    nickname: String,
+    color: &'static str,
+}
+
+struct SvgStep {
+    number: u32,
+    /// на общей картинке ранние ходы бледнее поздних
+    opacity: f32,
+    turns: Vec<SvgTurn>,
+}
+
+struct SvgTurn {
+    color: &'static str,
+    seeds: Vec<Seed>,
+    /// семена в порядке расстановки, для `<polyline>`
+    points: String,
+}
+
+/// Видимая область картинки: все показанные семена и легенда в её левом верхнем углу.
+struct ViewBox {
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+}
+
+impl ViewBox {
+    fn new<'a>(seeds: impl Iterator<Item = &'a Seed>, players_cnt: usize) -> Self {
+        let (min_x, min_y, max_x, max_y) = seeds.fold(
+            (i32::MAX, i32::MAX, i32::MIN, i32::MIN),
+            |(min_x, min_y, max_x, max_y), s| {
+                (
+                    min_x.min(s.x),
+                    min_y.min(s.y),
+                    max_x.max(s.x),
+                    max_y.max(s.y),
+                )
+            },
+        );
+        // семян нет: остаётся только легенда
+        let (min_x, min_y, max_x, max_y) = match min_x <= max_x {
+            true => (min_x, min_y, max_x, max_y),
+            false => (0, 0, 0, 0),
+        };
+
+        Self {
+            x: min_x - MARGIN,
+            y: min_y - MARGIN,
+            width: (max_x - min_x + 2 * MARGIN).max(LEGEND_WIDTH),
+            height: (max_y - min_y + 2 * MARGIN).max(LEGEND_ROW * (players_cnt as i32 + 1)),
+        }
+    }
+}
+
+/// все завершённые ходы игры на одной картинке
+pub(super) async fn steps(
+    reg: Data<Registry>,
+    me: Option<Authenticated>,
+    path: Path<u32>,
+    Query(query): Query<InviteQuery>,
+) -> ::aw::Result<HttpResponse> {
+    render(&reg, me.as_deref(), &query, path.into_inner(), None).await
+}
+
+/// один завершённый ход игры
+pub(super) async fn step(
+    reg: Data<Registry>,
+    me: Option<Authenticated>,
+    path: Path<(u32, u32)>,
+    Query(query): Query<InviteQuery>,
+) -> ::aw::Result<HttpResponse> {
+    let (game_id, step_number) = path.into_inner();
+    render(&reg, me.as_deref(), &query, game_id, Some(step_number)).await
+}
+
+async fn render(
+    reg: &Registry,
+    me: Option<&user::Model>,
+    query: &InviteQuery,
+    game_id: u32,
+    step_number: Option<u32>,
+) -> ::aw::Result<HttpResponse> {
+    let gm = reg.games.load_game(&reg.db, game_id).await?;
+    check_visible(&reg.db, &gm.game, me, query.token.as_deref()).await?;
+
+    let mut players = gm
+        .turns
+        .iter()
+        .filter(|(t, _)| t.step_number == 1)
+        .map(|(t, u)| SvgPlayer {
+            number: t.player_number,
+            nickname: t.nickname(u).to_string(),
+            color: PALETTE[t.player_number as usize % PALETTE.len()],
+        })
+        .collect::<Vec<_>>();
+    players.sort_by_key(|p| p.number);
+
+    // показываются только ходы, сделанные всеми игроками: открытый ход ещё можно поменять
+    let completed = (1..)
+        .take_while(|&n| {
+            gm.turns
+                .iter()
+                .filter(|(t, _)| t.step_number == n && t.seeds.is_some())
+                .count()
+                >= gm.game.players_cnt as usize
+        })
+        .count() as u32;
+
+    let numbers = match step_number {
+        Some(n) if n == 0 || n > completed => return Ok(HttpResponse::NotFound().finish()),
+        Some(n) => n..=n,
+        None => 1..=completed,
+    };
+
+    let steps = numbers
+        .map(|number| {
+            let mut turns = gm
+                .turns
+                .iter()
+                .map(|(t, _)| t)
+                .filter(|t| t.step_number == number)
+                .collect::<Vec<_>>();
+            turns.sort_by_key(|t| t.player_number);
+
+            SvgStep {
+                number,
+                opacity: match step_number {
+                    Some(_) => 1.0,
+                    None => 0.25 + 0.75 * number as f32 / completed as f32,
+                },
+                turns: turns
+                    .into_iter()
+                    .map(|t| {
+                        let seeds = Seed::parse_all(t.seeds.as_deref().unwrap_or_default());
+                        let points = seeds
+                            .iter()
+                            .map(|s| format!("{},{}", s.x, s.y))
+                            .collect::<Vec<_>>()
+                            .join(" ");
+
+                        SvgTurn {
+                            color: PALETTE[t.player_number as usize % PALETTE.len()],
+                            seeds,
+                            points,
+                        }
+                    })
+                    .collect(),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let view = ViewBox::new(
+        steps.iter().flat_map(|s| &s.turns).flat_map(|t| &t.seeds),
+        players.len(),
+    );
+
+    Ok(StepsSvg {
+        view,
+        players,
+        steps,
+    }
+    .to_response()
+    .map_into_boxed_body())
+}
@@ -1,9 +1,15 @@
 use super::*;
 
+use crate::{
+    manager::MIN_PLAYERS,
+    pause::{self, Tally},
+};
+
 pub(super) async fn handler(
     reg: Data<Registry>,
     app: AppTpl,
     path: Path<u32>,
+    Query(query): Query<InviteQuery>,
 ) -> ::aw::Result<impl Responder> {
     let game_id = path.into_inner();
 
@@ -23,9 +29,41 @@ pub(super) async fn handler(
 
     let players = game.find_linked(GameToUsers).all(&reg.db).await.unwrap();
 
-    let is_available_join = !players
+    let my_id = app.me.as_ref().map(|u| u.id);
+    let is_joined = players
         .iter()
-        .any(|u| matches!(app.me.as_ref().map(|u| u.id), Some(user_id) if user_id == u.id));
+        .any(|u| matches!(my_id, Some(user_id) if user_id == u.id));
+    let is_owner = my_id == Some(game.owner_id);
+    // состав игроков можно менять только пока идёт набор
+    let is_full = players.len() >= game.players_cnt as usize;
+    let is_scheduled = game.starts_at > Some(::chrono::Utc::now().naive_utc());
+    let is_open =
+        game.cancelled_at.is_none() && game.finished_at.is_none() && (is_scheduled || !is_full);
+    let is_available_join = is_open && !is_joined && !is_full;
+    let can_start = is_open && is_owner && players.len() >= MIN_PLAYERS;
+
+    let invited = game
+        .find_related(entity::invite::Entity)
+        .find_also_related(entity::user::Entity)
+        .all(&reg.db)
+        .await
+        .unwrap()
+        .into_iter()
+        .filter_map(|(_, user)| user)
+        .collect::<Vec<_>>();
+
+    let token = query
+        .token
+        .filter(|t| game.invite_token.as_deref() == Some(t.as_str()));
+    match check_visible(&reg.db, &game, app.me.as_ref(), token.as_deref()).await {
+        Ok(()) => {}
+        Err(GameManagerError::GameNotFound(_)) => {
+            return Ok(
+                HttpResponse::NotFound().body(GameView::not_found(app, game_id).render().unwrap())
+            );
+        }
+        Err(e) => Err(e)?,
+    }
 
     let turns = game
         .find_related(entity::turn::Entity)
@@ -34,6 +72,42 @@ pub(super) async fn handler(
         .unwrap();
     let last_step = turns.iter().map(|t| t.step_number).max().unwrap();
 
+    // все места загруженной игры принадлежат загрузившему, игроки известны только по именам
+    let mut nicknames = turns
+        .iter()
+        .filter(|t| t.step_number == 1)
+        .filter_map(|t| Some((t.player_number, t.nickname.clone()?)))
+        .collect::<Vec<_>>();
+    nicknames.sort();
+
+    let resigned = turns
+        .iter()
+        .filter(|t| t.is_resigned)
+        .map(|t| t.user_id)
+        .collect::<Vec<_>>();
+    let is_running = !is_open && game.cancelled_at.is_none() && game.finished_at.is_none();
+    let can_resign =
+        is_running && is_joined && !matches!(my_id, Some(user_id) if resigned.contains(&user_id));
+
+    // голосовать за паузу и ждать ходов можно, только пока игра идёт
+    let (pause, waiting, time_left) = match is_running {
+        true => {
+            let manager = reg.games.load_game(&reg.db, game_id).await?;
+            let tally = pause::tally(&reg.db, &manager)
+                .await
+                .map_err(GameManagerError::DbErr)?;
+            let time_left = manager
+                .time_left(::chrono::Utc::now().naive_utc())
+                .map(format_time_left);
+            (Some(tally), manager.waiting_players(), time_left)
+        }
+        false => (None, vec![], None),
+    };
+    let can_vote_pause = can_resign
+        && pause
+            .as_ref()
+            .is_some_and(|t| !matches!(my_id, Some(user_id) if t.voters.contains(&user_id)));
+
     let mut steps = vec![];
 
     for i in 1..=last_step {
@@ -49,8 +123,22 @@ pub(super) async fn handler(
     Ok(GameView {
         app,
         game_id,
+        is_finished: game.finished_at.is_some(),
+        is_open,
+        is_owner,
+        is_joined,
+        can_start,
+        can_resign,
+        pause,
+        can_vote_pause,
+        waiting,
+        time_left,
         data: Some(GameViewData { game, owner }),
         players: players.as_ref(),
+        nicknames: nicknames.into_iter().map(|(_, n)| n).collect(),
+        invited,
+        resigned,
+        token,
         is_available_join,
         steps,
     }
@@ -63,8 +151,33 @@ pub(super) async fn handler(
 struct GameView<'a> {
     app: AppTpl,
     game_id: u32,
+    /// история ходов завершённой игры доступна всем
+    is_finished: bool,
+    /// игра набирает игроков
+    is_open: bool,
+    is_owner: bool,
+    is_joined: bool,
+    /// владелец может начать игру, не дожидаясь остальных игроков
+    can_start: bool,
+    /// участник может сдаться в идущей игре
+    can_resign: bool,
+    /// голоса за паузу или её снятие в идущей игре
+    pause: Option<Tally>,
+    can_vote_pause: bool,
+    /// id игроков, чей ход ещё не сделан в открытом шаге
+    waiting: Vec<u32>,
+    /// сколько осталось на ход в игре по переписке
+    time_left: Option<String>,
     data: Option<GameViewData>,
     players: &'a [entity::user::Model],
+    /// имена игроков загруженной из записи игры
+    nicknames: Vec<String>,
+    /// приглашённые в реванш, но ещё не вошедшие в игру
+    invited: Vec<entity::user::Model>,
+    /// id сдавшихся игроков
+    resigned: Vec<u32>,
+    /// действующий секрет ссылки-приглашения, по которой открыта страница
+    token: Option<String>,
     is_available_join: bool,
     steps: Vec<Vec<entity::turn::Model>>,
 }
@@ -74,12 +187,53 @@ impl GameView<'_> {
         Self {
             app,
             game_id,
+            is_finished: false,
+            is_open: false,
+            is_owner: false,
+            is_joined: false,
+            can_start: false,
+            can_resign: false,
+            pause: None,
+            can_vote_pause: false,
+            waiting: vec![],
+            time_left: None,
             data: None,
             players: &[],
+            nicknames: vec![],
+            invited: vec![],
+            resigned: vec![],
+            token: None,
             is_available_join: false,
             steps: Default::default(),
         }
     }
+
+    fn is_resigned(&self, user_id: u32) -> bool {
+        self.resigned.contains(&user_id)
+    }
+
+    fn is_waiting(&self, user_id: u32) -> bool {
+        self.waiting.contains(&user_id)
+    }
+
+    fn is_me(&self, user_id: u32) -> bool {
+        self.app.me.as_ref().is_some_and(|me| me.id == user_id)
+    }
+}
+
+/// оставшееся время на ход: `2d 3h`, `5h 07m` или `12m`
+fn format_time_left(left: ::chrono::Duration) -> String {
+    let (days, hours, minutes) = (
+        left.num_days(),
+        left.num_hours() % 24,
+        left.num_minutes() % 60,
+    );
+
+    match (days, hours) {
+        (0, 0) => format!("{minutes}m"),
+        (0, _) => format!("{hours}h {minutes:02}m"),
+        _ => format!("{days}d {hours}h"),
+    }
 }
 
 struct GameViewData {
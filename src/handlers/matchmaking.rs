@@ -0,0 +1,140 @@
+use std::borrow::Cow;
+
+use super::*;
+
+use crate::{
+    matchmaking::{self, Preferences, QueueStatus, Ticket},
+    middleware::Authenticated,
+};
+use entity::game::GameType;
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("")
+            .route(web::get().to(index))
+            .route(web::post().to(enqueue)),
+    );
+    cfg.service(web::resource("leave").route(web::post().to(leave)));
+}
+
+#[derive(Template)]
+#[template(path = "matchmaking.html")]
+struct MatchmakingView {
+    app: AppTpl,
+    status: QueueStatus,
+    rating: Option<u32>,
+    error: Vec<Cow<'static, str>>,
+}
+
+impl MatchmakingView {
+    async fn new(reg: &Registry, app: AppTpl, error: Vec<Cow<'static, str>>) -> Self {
+        let (status, rating) = match app.me.as_ref() {
+            Some(me) => (
+                reg.matchmaking.status(me.id),
+                matchmaking::rating(&reg.db, me.id).await.ok(),
+            ),
+            None => (QueueStatus::Idle, None),
+        };
+
+        Self {
+            app,
+            status,
+            rating,
+            error,
+        }
+    }
+}
+
+async fn index(reg: Data<Registry>, app: AppTpl) -> impl Responder {
+    MatchmakingView::new(&reg, app, vec![]).await
+}
+
+#[derive(Debug, Deserialize)]
+struct FormEnqueue {
+    players_cnt: u32,
+    game_type: GameType,
+    laps: u32,
+    seeds: u32,
+    duration: u32,
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    rating_range: Option<u32>,
+}
+
+async fn enqueue(
+    reg: Data<Registry>,
+    req: HttpRequest,
+    form: Form<FormEnqueue>,
+    Authenticated(user): Authenticated,
+    app: AppTpl,
+) -> impl Responder {
+    let mut error = vec![];
+
+    if form.players_cnt < 2 || form.players_cnt > 5 {
+        error.push(Cow::Borrowed("`players` must be between 2 and 5"));
+    }
+
+    if form.laps > 50 {
+        error.push(Cow::Borrowed("`laps` must be less than 50"));
+    }
+
+    if form.seeds > 1000 {
+        error.push(Cow::Borrowed("`seeds` must be less than 1000"));
+    }
+
+    if form.duration < 10 || form.duration > 34000 {
+        error.push(Cow::Borrowed("`duration` must be between 10 and 34000"));
+    }
+
+    let rating = match matchmaking::rating(&reg.db, user.id).await {
+        Ok(rating) => rating,
+        Err(e) => {
+            error.push(Cow::Owned(e.to_string()));
+            0
+        }
+    };
+
+    if !error.is_empty() {
+        return MatchmakingView::new(&reg, app, error)
+            .await
+            .respond_to(&req);
+    }
+
+    let prefs = Preferences {
+        players_cnt: form.players_cnt,
+        game_type: form.game_type,
+        laps: form.laps,
+        seeds: form.seeds,
+        duration: form.duration,
+        rating_range: form.rating_range,
+    };
+
+    match matchmaking::enqueue(&reg, Ticket::new((*user).clone(), prefs, rating)).await {
+        Ok(Some(game_id)) => Redirect::to(format!("/games/{}", game_id))
+            .see_other()
+            .respond_to(&req)
+            .map_into_boxed_body(),
+        Ok(None) => Redirect::to("/matchmaking")
+            .see_other()
+            .respond_to(&req)
+            .map_into_boxed_body(),
+        Err(e) => {
+            let error = vec![Cow::Owned(e.to_string())];
+            MatchmakingView::new(&reg, app, error)
+                .await
+                .respond_to(&req)
+        }
+    }
+}
+
+async fn leave(
+    reg: Data<Registry>,
+    req: HttpRequest,
+    Authenticated(user): Authenticated,
+) -> impl Responder {
+    reg.matchmaking.leave(user.id);
+
+    Redirect::to("/matchmaking")
+        .see_other()
+        .respond_to(&req)
+        .map_into_boxed_body()
+}
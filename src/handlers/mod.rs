@@ -1,4 +1,4 @@
-use std::future::Ready;
+use std::str::FromStr;
 
 use ::log::warn;
 
@@ -10,12 +10,13 @@ use ::aw::{
     web::{self, Data, Either, Form, Path, Query, Redirect},
     FromRequest, HttpMessage, HttpRequest, HttpResponse, Responder, ResponseError,
 };
+use ::futures::future::LocalBoxFuture;
 use ::log::error;
 use ::sea_orm::{
     ActiveModelTrait, ActiveValue, ColumnTrait, DbErr, EntityTrait, FromQueryResult, JoinType,
     ModelTrait, QueryFilter, QuerySelect, RelationTrait,
 };
-use ::serde::{Deserialize, Serialize};
+use ::serde::{Deserialize, Deserializer, Serialize};
 
 use crate::state::*;
 use crate::{
@@ -24,22 +25,34 @@ use crate::{
 };
 use entity::*;
 
-// mod archive;
+mod archive;
 mod auth;
 mod game;
 mod index;
+mod matchmaking;
+mod notifications;
 mod rating;
+mod replays;
 mod samogonki;
+mod stats;
+mod tournaments;
 mod users;
+mod webhooks;
 
 pub fn config(cfg: &mut web::ServiceConfig) {
     cfg.service(web::resource("/").route(web::get().to(index::get)));
-    // cfg.service(web::scope("/archive").configure(archive::config));
+    cfg.service(web::scope("/archive").configure(archive::config));
     cfg.service(web::scope("/games").configure(game::config));
     cfg.service(web::scope("/game-on-line/default.asp").configure(samogonki::config));
     cfg.service(web::scope("/auth").configure(auth::config));
     cfg.service(web::scope("/users").configure(users::config));
+    cfg.service(web::scope("/matchmaking").configure(matchmaking::config));
+    cfg.service(web::scope("/notifications").configure(notifications::config));
     cfg.service(web::scope("/rating").configure(rating::config));
+    cfg.service(web::scope("/replays").configure(replays::config));
+    cfg.service(web::scope("/stats").configure(stats::config));
+    cfg.service(web::scope("/tournaments").configure(tournaments::config));
+    cfg.service(web::scope("/webhooks").configure(webhooks::config));
 }
 
 /// общая информация которая будет передана шаблонам для рендеринга
@@ -47,19 +60,31 @@ pub fn config(cfg: &mut web::ServiceConfig) {
 pub struct AppTpl {
     /// текущий юзер, чей запрос обрабатывается
     pub me: Option<user::Model>,
+    /// непрочитанные уведомления, показываются рядом с колокольчиком
+    pub unread: u64,
 }
 
 impl FromRequest for AppTpl {
     type Error = MiddlewareError;
-    type Future = Ready<Result<Self, Self::Error>>;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
 
     fn from_request(req: &HttpRequest, _payload: &mut ::aw::dev::Payload) -> Self::Future {
-        std::future::ready({
-            let user = match req.extensions().get::<MaybeAuthenticated>().cloned() {
-                Some(MaybeAuthenticated(user)) => user,
-                _ => None,
+        let user = match req.extensions().get::<MaybeAuthenticated>().cloned() {
+            Some(MaybeAuthenticated(user)) => user,
+            _ => None,
+        };
+        let reg = req.app_data::<Data<Registry>>().cloned();
+
+        // колокольчик есть только на страницах, поэтому непрочитанные
+        // считаются здесь, а не в middleware на каждый запрос
+        Box::pin(async move {
+            let unread = match (&user, reg) {
+                (Some(user), Some(reg)) => crate::notifications::unread(&reg.db, user.id)
+                    .await
+                    .unwrap_or(0),
+                _ => 0,
             };
-            Ok(Self { me: user })
+            Ok(Self { me: user, unread })
         })
     }
 }
@@ -70,3 +95,19 @@ pub struct ErrorTpl {
     pub app: AppTpl,
     pub status_code: u16,
 }
+
+/// пустые поля html-форм (`?world=&track=1`) считаются неуказанными
+fn empty_string_as_none<'de, D, T>(de: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: std::fmt::Debug,
+{
+    match Option::<String>::deserialize(de)?.as_deref().map(str::trim) {
+        None | Some("") => Ok(None),
+        Some(s) => s
+            .parse()
+            .map(Some)
+            .map_err(|e| ::serde::de::Error::custom(format!("{e:?}"))),
+    }
+}
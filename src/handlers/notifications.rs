@@ -0,0 +1,72 @@
+use super::*;
+
+use crate::{middleware::Authenticated, notifications};
+use entity::notification::Model as Notification;
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("").route(web::get().to(index)));
+    cfg.service(web::resource("read").route(web::post().to(read_all)));
+    cfg.service(web::resource("{id}/read").route(web::post().to(read)));
+}
+
+#[derive(Template)]
+#[template(path = "notifications.html")]
+struct NotificationsView {
+    app: AppTpl,
+    notifications: Vec<Notification>,
+}
+
+async fn index(
+    reg: Data<Registry>,
+    app: AppTpl,
+    Authenticated(me): Authenticated,
+) -> ::aw::Result<impl Responder> {
+    let notifications = notifications::list(&reg.db, me.id)
+        .await
+        .map_err(::aw::error::ErrorServiceUnavailable)?;
+
+    Ok(NotificationsView { app, notifications })
+}
+
+async fn read_all(
+    reg: Data<Registry>,
+    req: HttpRequest,
+    Authenticated(me): Authenticated,
+) -> ::aw::Result<HttpResponse> {
+    notifications::mark_read(&reg.db, me.id, None)
+        .await
+        .map_err(::aw::error::ErrorServiceUnavailable)?;
+
+    Ok(Redirect::to("/notifications")
+        .see_other()
+        .respond_to(&req)
+        .map_into_boxed_body())
+}
+
+/// отмечает уведомление прочитанным и переходит к его игре
+async fn read(
+    reg: Data<Registry>,
+    path: Path<u32>,
+    req: HttpRequest,
+    Authenticated(me): Authenticated,
+) -> ::aw::Result<HttpResponse> {
+    let id = path.into_inner();
+
+    let Some(notification) = entity::notification::Entity::find_by_id(id)
+        .filter(entity::notification::Column::UserId.eq(me.id))
+        .one(&reg.db)
+        .await
+        .map_err(::aw::error::ErrorServiceUnavailable)?
+    else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+
+    notifications::mark_read(&reg.db, me.id, Some(id))
+        .await
+        .map_err(::aw::error::ErrorServiceUnavailable)?;
+
+    Ok(Redirect::to(format!("/games/{}", notification.game_id))
+        .see_other()
+        .respond_to(&req)
+        .map_into_boxed_body())
+}
@@ -0,0 +1,65 @@
+use std::borrow::Cow;
+
+use ::sea_orm::TransactionTrait;
+
+use super::*;
+
+use crate::{
+    manager::GameManagerError,
+    middleware::Authenticated,
+    replay::{Replay, ReplayError},
+};
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("import")
+            .route(web::get().to(import_get))
+            .route(web::post().to(import_post)),
+    );
+}
+
+#[derive(Template)]
+#[template(path = "replays/import.html")]
+struct ReplayImport {
+    app: AppTpl,
+    error: Vec<Cow<'static, str>>,
+}
+
+async fn import_get(app: AppTpl) -> impl Responder {
+    ReplayImport { app, error: vec![] }
+}
+
+#[derive(Debug, Deserialize)]
+struct FormReplayImport {
+    /// запись в текстовом или JSON-виде
+    replay: String,
+}
+
+async fn import_post(
+    reg: Data<Registry>,
+    req: HttpRequest,
+    form: Form<FormReplayImport>,
+    Authenticated(user): Authenticated,
+    app: AppTpl,
+) -> impl Responder {
+    let import = async {
+        let replay = Replay::parse(&form.replay)?;
+
+        let txn = reg.db.begin().await.map_err(GameManagerError::DbErr)?;
+        let game = replay.import(&txn, user.id).await?;
+        txn.commit().await.map_err(GameManagerError::DbErr)?;
+
+        Ok::<_, ReplayError>(game)
+    };
+
+    match import.await {
+        Ok(game) => Redirect::to(format!("/games/{}", game.id))
+            .see_other()
+            .respond_to(&req)
+            .map_into_boxed_body(),
+        Err(e) => {
+            let error = vec![Cow::Owned(e.to_string())];
+            ReplayImport { app, error }.respond_to(&req)
+        }
+    }
+}
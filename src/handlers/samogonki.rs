@@ -1,6 +1,24 @@
 use super::*;
 
-use crate::manager::{GameManager, GameManagerError};
+use ::sea_orm::{DatabaseTransaction, TransactionTrait};
+use ::tokio::{
+    sync::broadcast,
+    time::{timeout_at, Instant},
+};
+
+use super::game::check_visible;
+use crate::{
+    events::GameEvent,
+    manager::{GameManager, GameManagerError, StepOutcome},
+    middleware::Authenticated,
+};
+
+/// Заголовок ответа на `OG_REFRESH_PACKET`, пока игра стоит на паузе.
+///
+/// В пакете KDLAB нет поля для состояния игры, а смысл существующих полей
+/// менять нельзя: их разбирает клиент игры. Поэтому сам пакет на паузе
+/// обычный ответ ожидания, а о паузе говорит заголовок.
+pub const PAUSED_HEADER: &str = "X-Samogonki-Paused";
 
 pub fn config(cfg: &mut web::ServiceConfig) {
     cfg.service(
@@ -18,8 +36,21 @@ impl ResponseError for GameManagerError {
             GameManagerError::DbErr(_) => StatusCode::SERVICE_UNAVAILABLE,
             GameManagerError::GameNotActive(_) => StatusCode::NOT_ACCEPTABLE,
             GameManagerError::IncorrectStepNumber => StatusCode::NOT_ACCEPTABLE,
+            GameManagerError::StepClosed(_) => StatusCode::CONFLICT,
             GameManagerError::IncorrectIncomeSteps => StatusCode::NOT_ACCEPTABLE,
             GameManagerError::IncorrectIncomePlayers => StatusCode::NOT_ACCEPTABLE,
+            GameManagerError::GameNotFinished(_) => StatusCode::CONFLICT,
+            GameManagerError::GameNotOpen(_) => StatusCode::CONFLICT,
+            GameManagerError::NotEnoughPlayers(_) => StatusCode::CONFLICT,
+            GameManagerError::InviteRequired(_) => StatusCode::FORBIDDEN,
+            GameManagerError::InvalidInviteToken(_) => StatusCode::FORBIDDEN,
+            GameManagerError::AlreadyJoined(_) => StatusCode::CONFLICT,
+            GameManagerError::NotJoined(_) => StatusCode::NOT_FOUND,
+            GameManagerError::OwnerCannotLeave => StatusCode::CONFLICT,
+            GameManagerError::PlayerResigned(_) => StatusCode::FORBIDDEN,
+            GameManagerError::SpectatorReadOnly(_) => StatusCode::FORBIDDEN,
+            GameManagerError::ImportedReadOnly(_) => StatusCode::FORBIDDEN,
+            GameManagerError::IncorrectCarComponent(..) => StatusCode::NOT_ACCEPTABLE,
         }
     }
 
@@ -27,7 +58,7 @@ impl ResponseError for GameManagerError {
         let mut res = HttpResponse::new(self.status_code());
         let h_value = header::HeaderValue::from_static("text/html; charset=utf-8");
         res.headers_mut().insert(header::CONTENT_TYPE, h_value);
-        res.set_body(BoxBody::new(format!("Error occurred: {:?}", self)))
+        res.set_body(BoxBody::new(format!("Error occurred: {self}")))
     }
 }
 
@@ -37,15 +68,51 @@ struct ParamGameInfo {
     player_id: u32,
     #[serde(alias = "ID")]
     game_id: u32,
+    /// секрет ссылки-приглашения, если игра приватная
+    #[serde(alias = "TOKEN")]
+    token: Option<String>,
     // #[serde(alias = "API_KEY")]
     // api_key: String,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+struct ParamToken {
+    /// секрет ссылки-приглашения, если игра приватная
+    #[serde(alias = "TOKEN")]
+    token: Option<String>,
+}
+
+/// Проверяет, что игрок `pid` может видеть игру `game_id`.
+///
+/// pid зрителя сам служит секретом, а номера игроков легко перебрать,
+/// поэтому для остальных нужен токен приглашения или участие в игре.
+async fn check_player(
+    reg: &Registry,
+    game_id: u32,
+    pid: u32,
+    me: Option<&user::Model>,
+    token: Option<&str>,
+) -> Result<(), GameManagerError> {
+    let gm = reg.games.load_game(&reg.db, game_id).await?;
+    if gm.game.spectator_pid != Some(pid) {
+        check_visible(&reg.db, &gm.game, me, token).await?;
+    }
+
+    Ok(())
+}
+
 async fn get(
     reg: Data<Registry>,
-    Query(ParamGameInfo { player_id, game_id }): Query<ParamGameInfo>,
+    me: Option<Authenticated>,
+    Query(ParamGameInfo {
+        player_id,
+        game_id,
+        token,
+    }): Query<ParamGameInfo>,
 ) -> ::aw::Result<impl Responder, GameManagerError> {
-    let mut gm = GameManager::load_game(&reg.db, game_id).await?;
+    check_player(&reg, game_id, player_id, me.as_deref(), token.as_deref()).await?;
+
+    let mut gm = reg.games.load_game(&reg.db, game_id).await?;
     gm.set_pid(player_id)?;
 
     let packet = gm.get_info(PacketType::OG_CONTROL_PACKET);
@@ -53,30 +120,47 @@ async fn get(
     Ok(KdlabNetObject(packet))
 }
 
-async fn post(reg: Data<Registry>, packet: ::aw::Result<Packet>) -> ::aw::Result<impl Responder> {
+async fn post(
+    reg: Data<Registry>,
+    me: Option<Authenticated>,
+    Query(ParamToken { token }): Query<ParamToken>,
+    packet: ::aw::Result<Packet>,
+) -> ::aw::Result<impl Responder> {
     let mut p = packet?;
-
-    let mut gm = GameManager::load_game(&reg.db, p.gmid).await?;
-    gm.set_pid(p.packet_owner_pid)?;
+    check_player(
+        &reg,
+        p.gmid,
+        p.packet_owner_pid,
+        me.as_deref(),
+        token.as_deref(),
+    )
+    .await?;
 
     match p.t_type {
-        PacketType::OG_CONTROL_PACKET => {
-            gm.apply_results(&mut p).await?;
-        }
-        PacketType::OG_SEEDS_PACKET => {
-            return match gm.apply_step(&mut p).await {
-                Ok(_) => Ok(Either::Left("OK:KDLAB")),
-                Err(GameManagerError::IncorrectIncomeSteps) => {
-                    Ok(Either::Left("NEXT_MOVE")) // it's wrong
-                }
-                Err(e) => Err(e)?,
-            };
+        PacketType::OG_CONTROL_PACKET | PacketType::OG_SEEDS_PACKET => {
+            let txn = reg.db.begin().await.map_err(GameManagerError::DbErr)?;
+            let (reply, events) = apply_move(&txn, &mut p).await?;
+            txn.commit().await.map_err(GameManagerError::DbErr)?;
+
+            reg.games.invalidate(p.gmid);
+            for event in events {
+                reg.notify(event);
+            }
+
+            return Ok(Either::Left(reply));
         }
         PacketType::OG_REFRESH_PACKET => {
-            let packet = gm.get_refresh_packet(&p).await?;
-            return Ok(Either::Right(KdlabNetObject(packet)));
+            let (packet, is_paused) = refresh(&reg, &p).await?;
+            let mut answer = KdlabNetObject(packet).customize();
+            if is_paused {
+                answer = answer.insert_header((PAUSED_HEADER, "1"));
+            }
+            return Ok(Either::Right(answer));
         }
         t => {
+            let mut gm = reg.games.load_game(&reg.db, p.gmid).await?;
+            gm.set_pid(p.packet_owner_pid)?;
+
             warn!("unimplemented OG packet: {:?}", t);
             warn!("packet IN: {:#?}", &p);
         }
@@ -84,3 +168,98 @@ async fn post(reg: Data<Registry>, packet: ::aw::Result<Packet>) -> ::aw::Result
 
     Ok(Either::Left("OK:KDLAB"))
 }
+
+/// Записывает ход игрока или результаты обсчёта хода.
+///
+/// Все проверки и изменения выполняются в одной транзакции поверх захваченной игры,
+/// при ошибке транзакция откатывается целиком.
+async fn apply_move(
+    txn: &DatabaseTransaction,
+    p: &mut Packet,
+) -> Result<(&'static str, Vec<GameEvent>), GameManagerError> {
+    let mut gm = GameManager::lock_game(txn, p.gmid).await?;
+    gm.set_pid(p.packet_owner_pid)?;
+
+    let status = gm.status();
+    let mut events = vec![];
+
+    let reply = if p.t_type == PacketType::OG_CONTROL_PACKET {
+        gm.apply_results(p).await?;
+        "OK:KDLAB"
+    } else {
+        let step_number = gm.move_cnt() + 1;
+
+        match gm.apply_step(p).await {
+            Ok(StepOutcome::Duplicate) => "OK:KDLAB",
+            Ok(StepOutcome::Inserted | StepOutcome::Updated) => {
+                // ход мог закрыть шаг: роботы и сдавшиеся сразу ходят в следующем
+                gm.auto_moves().await?;
+                events.push(GameEvent::TurnSaved {
+                    game_id: p.gmid,
+                    player_number: p.packet_owner_pid,
+                    step_number,
+                });
+                "OK:KDLAB"
+            }
+            // ход уже закрыт другим содержимым: клиенту нужно забрать результаты и перейти к следующему
+            Err(GameManagerError::StepClosed(_)) => "NEXT_MOVE",
+            Err(e) => Err(e)?,
+        }
+    };
+
+    if gm.status() != status {
+        events.push(GameEvent::StatusChanged {
+            game_id: p.gmid,
+            status: gm.status(),
+        });
+    }
+
+    Ok((reply, events))
+}
+
+/// Ответ на `OG_REFRESH_PACKET` и стоит ли игра на паузе.
+///
+/// В режиме long-polling запрос ждёт, пока ход не сделают все игроки
+/// или не истечёт `Registry::refresh_long_poll`. На паузе игрок получает
+/// обычный ответ ожидания, пока паузу не снимут.
+async fn refresh(reg: &Registry, p: &Packet) -> Result<(Packet, bool), GameManagerError> {
+    let deadline = reg.refresh_long_poll.map(|d| Instant::now() + d);
+    // подписка оформляется до проверки, чтобы не пропустить ход, записанный между ними
+    let mut events = reg.events.subscribe();
+
+    loop {
+        let mut gm = reg.games.load_game(&reg.db, p.gmid).await?;
+        gm.set_pid(p.packet_owner_pid)?;
+
+        let packet = gm.get_refresh_packet(p).await?;
+        if packet.t_type == PacketType::OG_GAME_PACKET {
+            // ход доставлен игроку, отметка об этом записана в базу
+            reg.games.invalidate(p.gmid);
+            return Ok((packet, false));
+        }
+
+        let Some(deadline) = deadline else {
+            return Ok((packet, gm.is_paused()));
+        };
+
+        if timeout_at(deadline, turn_saved(&mut events, p.gmid))
+            .await
+            .is_err()
+        {
+            return Ok((packet, gm.is_paused()));
+        }
+    }
+}
+
+/// ждёт записи любого хода в игре `game_id` или паузы в ней
+async fn turn_saved(events: &mut broadcast::Receiver<GameEvent>, game_id: u32) {
+    loop {
+        match events.recv().await {
+            Ok(GameEvent::TurnSaved { game_id: id, .. }) if id == game_id => return,
+            Ok(GameEvent::PauseChanged { game_id: id, .. }) if id == game_id => return,
+            Ok(_) => continue,
+            // пропущенные события могли касаться этой игры: пусть состояние перечитают
+            Err(_) => return,
+        }
+    }
+}
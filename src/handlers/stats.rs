@@ -0,0 +1,10 @@
+use super::*;
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("cache").route(web::get().to(cache)));
+}
+
+/// статистика кэша активных игр
+async fn cache(reg: Data<Registry>) -> impl Responder {
+    web::Json(reg.games.stats())
+}
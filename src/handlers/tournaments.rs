@@ -0,0 +1,345 @@
+use std::{borrow::Cow, collections::HashMap};
+
+use ::sea_orm::QueryOrder;
+
+use super::*;
+
+use crate::{
+    manager::GameStatus,
+    middleware::Authenticated,
+    tournament::{self, Race, Standing, TournamentError},
+};
+use entity::{
+    game::{GameType, World, WorldInfo},
+    tournament::{TournamentFormat, TrackRotation},
+};
+
+impl ResponseError for TournamentError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            TournamentError::NotFound(_) => StatusCode::NOT_FOUND,
+            TournamentError::AlreadyStarted(_)
+            | TournamentError::NotEnoughParticipants(_)
+            | TournamentError::AlreadyJoined(_) => StatusCode::CONFLICT,
+            TournamentError::Manager(e) => e.status_code(),
+            TournamentError::DbErr(_) => StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("new")
+            .route(web::get().to(new_get))
+            .route(web::post().to(new_post)),
+    );
+    cfg.service(web::resource("{tournament_id}").route(web::get().to(view)));
+    cfg.service(web::resource("{tournament_id}/join").route(web::post().to(join)));
+    cfg.service(web::resource("{tournament_id}/start").route(web::post().to(start)));
+    cfg.service(web::resource("").route(web::get().to(list)));
+}
+
+#[derive(Template)]
+#[template(path = "tournaments/list.html")]
+struct TournamentList {
+    app: AppTpl,
+    tournaments: Vec<(entity::tournament::Model, user::Model)>,
+}
+
+async fn list(reg: Data<Registry>, app: AppTpl) -> ::aw::Result<HttpResponse> {
+    let tournaments = entity::tournament::Entity::find()
+        .find_also_related(user::Entity)
+        .order_by_desc(entity::tournament::Column::Id)
+        .all(&reg.db)
+        .await
+        .map_err(::aw::error::ErrorServiceUnavailable)?
+        .into_iter()
+        .filter_map(|(t, owner)| Some((t, owner?)))
+        .collect();
+
+    Ok(TournamentList { app, tournaments }
+        .to_response()
+        .map_into_boxed_body())
+}
+
+#[derive(Template)]
+#[template(path = "tournaments/new.html")]
+struct TournamentNew {
+    app: AppTpl,
+    worlds: Vec<WorldInfo>,
+    error: Vec<Cow<'static, str>>,
+}
+
+impl TournamentNew {
+    fn new(app: AppTpl, error: Vec<Cow<'static, str>>) -> Self {
+        Self {
+            app,
+            worlds: World::catalog(),
+            error,
+        }
+    }
+}
+
+async fn new_get(app: AppTpl) -> impl Responder {
+    TournamentNew::new(app, vec![])
+}
+
+#[derive(Debug, Deserialize)]
+struct FormTournamentNew {
+    name: String,
+    format: TournamentFormat,
+    players_per_race: u32,
+    /// число туров, только для швейцарской системы
+    rounds: u32,
+    /// трассы туров: `world:track` через запятую
+    tracks: String,
+    game_type: GameType,
+    laps: u32,
+    seeds: u32,
+    duration: u32,
+}
+
+async fn new_post(
+    reg: Data<Registry>,
+    req: HttpRequest,
+    form: Form<FormTournamentNew>,
+    Authenticated(user): Authenticated,
+    app: AppTpl,
+) -> impl Responder {
+    use ActiveValue::*;
+
+    let mut error = vec![];
+
+    let name = form.name.trim();
+    if name.is_empty() || name.chars().count() > 64 {
+        error.push(Cow::Borrowed("`name` must be from 1 to 64 characters"));
+    }
+
+    let tracks = TrackRotation::parse(&form.tracks);
+    if tracks.is_none() {
+        error.push(Cow::Borrowed(
+            "`tracks` must be a comma separated list of `world:track`",
+        ));
+    }
+
+    // в круговой системе каждый встречается с каждым один на один
+    let players_per_race = match form.format {
+        TournamentFormat::RoundRobin => 2,
+        _ => form.players_per_race,
+    };
+    if !(2..=5).contains(&players_per_race) {
+        error.push(Cow::Borrowed("`players per race` must be between 2 and 5"));
+    }
+
+    if form.format == TournamentFormat::Swiss && !(1..=20).contains(&form.rounds) {
+        error.push(Cow::Borrowed("`rounds` must be between 1 and 20"));
+    }
+
+    if form.laps > 50 {
+        error.push(Cow::Borrowed("`laps` must be less than 50"));
+    }
+
+    if form.seeds > 1000 {
+        error.push(Cow::Borrowed("`seeds` must be less than 1000"));
+    }
+
+    if form.duration < 10 || form.duration > 34000 {
+        error.push(Cow::Borrowed("`duration` must be between 10 and 34000"));
+    }
+
+    let tracks = match tracks {
+        Some(tracks) if error.is_empty() => tracks,
+        _ => return TournamentNew::new(app, error).respond_to(&req),
+    };
+
+    let model = entity::tournament::ActiveModel {
+        owner_id: Set(user.id),
+        name: Set(name.to_owned()),
+        format: Set(form.format),
+        players_per_race: Set(players_per_race),
+        rounds: Set(form.rounds),
+        tracks: Set(tracks),
+        game_type: Set(form.game_type),
+        laps: Set(form.laps),
+        seeds: Set(form.seeds),
+        duration: Set(form.duration),
+        ..Default::default()
+    };
+
+    match model.insert(&reg.db).await {
+        Ok(model) => Redirect::to(format!("/tournaments/{}", model.id))
+            .see_other()
+            .respond_to(&req)
+            .map_into_boxed_body(),
+        Err(e) => {
+            error.push(Cow::Owned(e.to_string()));
+            TournamentNew::new(app, error).respond_to(&req)
+        }
+    }
+}
+
+#[derive(Template)]
+#[template(path = "tournaments/view.html")]
+struct TournamentView {
+    app: AppTpl,
+    tournament: entity::tournament::Model,
+    owner: user::Model,
+    /// участники в порядке регистрации и тур, в котором они выбыли
+    participants: Vec<(user::Model, Option<u32>)>,
+    standings: Vec<Standing>,
+    /// заезды, сгруппированные по турам
+    rounds: Vec<(u32, Vec<Race>)>,
+    users: HashMap<u32, user::Model>,
+}
+
+impl TournamentView {
+    fn login(&self, user_id: u32) -> Cow<'_, str> {
+        match self.users.get(&user_id) {
+            Some(user) => user.login(),
+            None => Cow::Owned(format!("#{user_id}")),
+        }
+    }
+
+    fn status(&self) -> String {
+        let t = &self.tournament;
+        if t.finished_at.is_some() {
+            String::from("finished")
+        } else if t.started_at.is_some() {
+            format!("round {} of {}", t.current_round, t.rounds)
+        } else {
+            String::from("registration")
+        }
+    }
+
+    fn is_joined(&self) -> bool {
+        self.app
+            .me
+            .as_ref()
+            .is_some_and(|me| self.users.contains_key(&me.id))
+    }
+
+    fn is_owner(&self) -> bool {
+        self.app
+            .me
+            .as_ref()
+            .is_some_and(|me| me.id == self.tournament.owner_id)
+    }
+
+    /// победитель закончившегося турнира
+    fn winner(&self) -> Option<u32> {
+        self.tournament.finished_at?;
+
+        match self.tournament.format {
+            TournamentFormat::Elimination => self
+                .participants
+                .iter()
+                .find(|(_, eliminated_in)| eliminated_in.is_none())
+                .map(|(user, _)| user.id),
+            _ => self.standings.first().map(|s| s.user_id),
+        }
+    }
+}
+
+async fn view(reg: Data<Registry>, app: AppTpl, path: Path<u32>) -> ::aw::Result<HttpResponse> {
+    let tournament_id = path.into_inner();
+
+    let Some((tournament, Some(owner))) = entity::tournament::Entity::find_by_id(tournament_id)
+        .find_also_related(user::Entity)
+        .one(&reg.db)
+        .await
+        .map_err(::aw::error::ErrorServiceUnavailable)?
+    else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+
+    let participants = entity::tournament_participant::Entity::find()
+        .filter(entity::tournament_participant::Column::TournamentId.eq(tournament_id))
+        .order_by_asc(entity::tournament_participant::Column::Id)
+        .find_also_related(user::Entity)
+        .all(&reg.db)
+        .await
+        .map_err(::aw::error::ErrorServiceUnavailable)?
+        .into_iter()
+        .filter_map(|(p, user)| Some((user?, p.eliminated_in)))
+        .collect::<Vec<_>>();
+
+    let seeds = participants.iter().map(|(u, _)| u.id).collect::<Vec<_>>();
+    let races = tournament::races(&reg.db, tournament_id).await?;
+    let standings = tournament::standings(&seeds, &races);
+
+    let mut rounds = Vec::<(u32, Vec<Race>)>::new();
+    for race in races {
+        match rounds.last_mut() {
+            Some((round, races)) if *round == race.round => races.push(race),
+            _ => rounds.push((race.round, vec![race])),
+        }
+    }
+
+    let users = participants
+        .iter()
+        .map(|(u, _)| (u.id, u.clone()))
+        .collect();
+
+    Ok(TournamentView {
+        app,
+        tournament,
+        owner,
+        participants,
+        standings,
+        rounds,
+        users,
+    }
+    .to_response()
+    .map_into_boxed_body())
+}
+
+async fn join(
+    reg: Data<Registry>,
+    path: Path<u32>,
+    req: HttpRequest,
+    Authenticated(me): Authenticated,
+) -> ::aw::Result<HttpResponse> {
+    let tournament_id = path.into_inner();
+
+    let tournament = entity::tournament::Entity::find_by_id(tournament_id)
+        .one(&reg.db)
+        .await
+        .map_err(TournamentError::DbErr)?
+        .ok_or(TournamentError::NotFound(tournament_id))?;
+
+    match tournament::join(&reg.db, &tournament, me.id).await {
+        // повторное нажатие: просто возвращаем на страницу турнира
+        Ok(()) | Err(TournamentError::AlreadyJoined(_)) => {}
+        Err(e) => Err(e)?,
+    }
+
+    Ok(Redirect::to(format!("/tournaments/{}", tournament_id))
+        .see_other()
+        .respond_to(&req)
+        .map_into_boxed_body())
+}
+
+async fn start(
+    reg: Data<Registry>,
+    path: Path<u32>,
+    req: HttpRequest,
+    Authenticated(me): Authenticated,
+) -> ::aw::Result<HttpResponse> {
+    let tournament_id = path.into_inner();
+
+    let tournament = entity::tournament::Entity::find_by_id(tournament_id)
+        .one(&reg.db)
+        .await
+        .map_err(TournamentError::DbErr)?
+        .ok_or(TournamentError::NotFound(tournament_id))?;
+    if tournament.owner_id != me.id {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+
+    tournament::start(&reg, tournament_id).await?;
+
+    Ok(Redirect::to(format!("/tournaments/{}", tournament_id))
+        .see_other()
+        .respond_to(&req)
+        .map_into_boxed_body())
+}
@@ -1,11 +1,18 @@
+use ::aw::error::ErrorInternalServerError;
+use ::sea_orm::IntoActiveModel;
+
 use super::*;
 
+use crate::{middleware::Authenticated, scheduler::ROBOT_STEAM_ID};
+use entity::car::{Component, Loadout};
+
 pub fn config(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::scope("")
             .route("", web::get().to(list))
             .route("register", web::get().to(test_register))
-            .route("{user_id}", web::get().to(view)),
+            .route("{user_id}", web::get().to(view))
+            .route("{user_id}/loadout", web::post().to(loadout)),
     );
 }
 
@@ -17,7 +24,12 @@ struct ListView {
 }
 
 async fn list(reg: Data<Registry>, app: AppTpl) -> ::aw::Result<impl Responder> {
-    let users = user::Entity::find().all(&reg.db).await.unwrap();
+    // пользователь `robot` только играет за роботов, показывать его незачем
+    let users = user::Entity::find()
+        .filter(user::Column::SteamId.ne(ROBOT_STEAM_ID))
+        .all(&reg.db)
+        .await
+        .unwrap();
 
     Ok(ListView { app, users }.to_response())
 }
@@ -27,6 +39,27 @@ async fn list(reg: Data<Registry>, app: AppTpl) -> ::aw::Result<impl Responder>
 struct UserView {
     app: AppTpl,
     user: user::Model,
+    error: Option<String>,
+    pers_ids: Vec<u32>,
+    car_ids: Vec<u32>,
+    wheel_ids: Vec<u32>,
+}
+
+impl UserView {
+    fn new(app: AppTpl, user: user::Model, error: Option<String>) -> Self {
+        Self {
+            app,
+            user,
+            error,
+            pers_ids: Component::Pers.ids().to_vec(),
+            car_ids: Component::Car.ids().to_vec(),
+            wheel_ids: Component::Wheel.ids().to_vec(),
+        }
+    }
+
+    fn is_me(&self) -> bool {
+        matches!(&self.app.me, Some(me) if me.id == self.user.id)
+    }
 }
 
 async fn view(
@@ -42,7 +75,7 @@ async fn view(
         .unwrap();
 
     match user {
-        Some(user) => UserView { app, user }.to_response(),
+        Some(user) => UserView::new(app, user, None).to_response(),
         None => ::aw::web::Redirect::to("/")
             .using_status_code(StatusCode::SEE_OTHER)
             .respond_to(&req)
@@ -50,6 +83,66 @@ async fn view(
     }
 }
 
+#[derive(Debug, Deserialize)]
+struct FormLoadout {
+    prop_pers: u32,
+    prop_car: u32,
+    prop_fwheel: u32,
+    prop_bwheel: u32,
+}
+
+impl FormLoadout {
+    fn loadout(&self) -> Loadout {
+        Loadout {
+            pers: self.prop_pers,
+            car: self.prop_car,
+            fwheel: self.prop_fwheel,
+            bwheel: self.prop_bwheel,
+        }
+    }
+}
+
+/// сохранить комплектацию мехоса по умолчанию
+async fn loadout(
+    reg: Data<Registry>,
+    app: AppTpl,
+    req: HttpRequest,
+    path: web::Path<u32>,
+    Authenticated(me): Authenticated,
+    form: Form<FormLoadout>,
+) -> ::aw::Result<HttpResponse> {
+    let user_id = path.into_inner();
+
+    if me.id != user_id {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+
+    if let Err((component, id)) = form.loadout().validate() {
+        let mut user = (*me).clone();
+        user.prop_pers = form.prop_pers;
+        user.prop_car = form.prop_car;
+        user.prop_fwheel = form.prop_fwheel;
+        user.prop_bwheel = form.prop_bwheel;
+
+        let error = format!("unknown {} component: {}", component.name(), id);
+        return Ok(UserView::new(app, user, Some(error)).to_response());
+    }
+
+    let mut user = (*me).clone().into_active_model();
+    user.prop_pers = ActiveValue::Set(form.prop_pers);
+    user.prop_car = ActiveValue::Set(form.prop_car);
+    user.prop_fwheel = ActiveValue::Set(form.prop_fwheel);
+    user.prop_bwheel = ActiveValue::Set(form.prop_bwheel);
+    user.update(&reg.db)
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+    Ok(Redirect::to(format!("/users/{}", user_id))
+        .see_other()
+        .respond_to(&req)
+        .map_into_boxed_body())
+}
+
 #[cfg(debug_assertions)]
 async fn test_register(
     reg: Data<Registry>,
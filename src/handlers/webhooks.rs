@@ -0,0 +1,110 @@
+use super::*;
+
+use crate::{
+    middleware::Authenticated,
+    webhooks::{self, WebhookError, EVENT_HEADER, SIGNATURE_HEADER},
+};
+use entity::{webhook, webhook_delivery};
+
+impl ResponseError for WebhookError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            WebhookError::NotFound(_) => StatusCode::NOT_FOUND,
+            WebhookError::InvalidUrl(_) => StatusCode::BAD_REQUEST,
+            WebhookError::ForbiddenTarget(_) => StatusCode::BAD_REQUEST,
+            WebhookError::TooMany(_) => StatusCode::CONFLICT,
+            WebhookError::DbErr(_) => StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("")
+            .route(web::get().to(index))
+            .route(web::post().to(create)),
+    );
+    cfg.service(web::resource("{id}/delete").route(web::post().to(delete)));
+}
+
+#[derive(Template)]
+#[template(path = "webhooks.html")]
+struct WebhooksView {
+    app: AppTpl,
+    hooks: Vec<webhook::Model>,
+    deliveries: Vec<webhook_delivery::Model>,
+    signature_header: &'static str,
+    event_header: &'static str,
+    error: Option<String>,
+}
+
+impl WebhooksView {
+    async fn new(
+        reg: &Registry,
+        app: AppTpl,
+        owner_id: u32,
+        error: Option<String>,
+    ) -> Result<Self, WebhookError> {
+        let (hooks, deliveries) = webhooks::list(&reg.db, owner_id).await?;
+
+        Ok(Self {
+            app,
+            hooks,
+            deliveries,
+            signature_header: SIGNATURE_HEADER,
+            event_header: EVENT_HEADER,
+            error,
+        })
+    }
+}
+
+async fn index(
+    reg: Data<Registry>,
+    app: AppTpl,
+    Authenticated(me): Authenticated,
+) -> ::aw::Result<impl Responder> {
+    Ok(WebhooksView::new(&reg, app, me.id, None).await?)
+}
+
+#[derive(Debug, Deserialize)]
+struct FormCreate {
+    url: String,
+}
+
+async fn create(
+    reg: Data<Registry>,
+    app: AppTpl,
+    req: HttpRequest,
+    form: Form<FormCreate>,
+    Authenticated(me): Authenticated,
+) -> ::aw::Result<HttpResponse> {
+    match webhooks::create(&reg.db, me.id, &form.url, reg.webhook_targets).await {
+        Ok(_) => Ok(Redirect::to("/webhooks")
+            .see_other()
+            .respond_to(&req)
+            .map_into_boxed_body()),
+        Err(
+            e @ (WebhookError::InvalidUrl(_)
+            | WebhookError::ForbiddenTarget(_)
+            | WebhookError::TooMany(_)),
+        ) => {
+            let view = WebhooksView::new(&reg, app, me.id, Some(e.to_string())).await?;
+            Ok(view.to_response())
+        }
+        Err(e) => Err(e)?,
+    }
+}
+
+async fn delete(
+    reg: Data<Registry>,
+    path: Path<u32>,
+    req: HttpRequest,
+    Authenticated(me): Authenticated,
+) -> ::aw::Result<HttpResponse> {
+    webhooks::delete(&reg.db, me.id, path.into_inner()).await?;
+
+    Ok(Redirect::to("/webhooks")
+        .see_other()
+        .respond_to(&req)
+        .map_into_boxed_body())
+}
@@ -1,5 +1,7 @@
 extern crate actix_web as aw;
 
+use std::time::Duration;
+
 use ::actix_session::{config::BrowserSession, storage::CookieSessionStore, SessionMiddleware};
 use ::actix_web_lab::middleware::from_fn;
 use ::aw::{cookie::SameSite, web::Data, HttpServer};
@@ -13,11 +15,20 @@ use ::sea_orm::{Database, DbErr};
 use migration::{Migrator, MigratorTrait};
 
 pub mod api;
+pub mod cache;
 pub mod data;
+pub mod events;
 pub mod handlers;
 pub mod manager;
+pub mod matchmaking;
 pub mod middleware;
+pub mod notifications;
+pub mod pause;
+pub mod replay;
+pub mod scheduler;
 pub mod state;
+pub mod tournament;
+pub mod webhooks;
 use state::*;
 
 macro_rules! app {
@@ -61,10 +72,26 @@ async fn main() -> Result<(), StartUpError> {
         .find(|(k, _)| k == "STEAM_API_KEY")
         .map(|(_, v)| &*v.leak());
 
+    let refresh_long_poll = std::env::vars()
+        .find(|(k, _)| k == "REFRESH_LONG_POLL_SECS")
+        .and_then(|(_, v)| v.parse().ok())
+        .map(Duration::from_secs);
+
     let db = Database::connect(database_url).await?;
     Migrator::up(&db, None).await?;
 
-    let registry = Data::new(Registry { steam_key, db });
+    let registry = Data::new(Registry {
+        steam_key,
+        db,
+        refresh_long_poll,
+        ..Default::default()
+    });
+
+    // туры турниров закрываются по событиям о завершении игр
+    actix_web::rt::spawn(tournament::run(Registry::clone(&registry)));
+    actix_web::rt::spawn(scheduler::run(Registry::clone(&registry)));
+    actix_web::rt::spawn(notifications::run(Registry::clone(&registry)));
+    actix_web::rt::spawn(webhooks::run(Registry::clone(&registry)));
 
     let srv = HttpServer::new(move || {
         app!()
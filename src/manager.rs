@@ -1,8 +1,15 @@
-use ::sea_orm::{
-    ActiveModelTrait, ActiveValue, DbConn, DbErr, EntityTrait, IntoActiveModel, ModelTrait,
-};
+use ::rand::Rng;
+use ::sea_orm::{DbConn, DbErr};
+use ::serde::Serialize;
 
 use crate::data::{Language, Packet, PacketType, Player, PlayerTurnInfo};
+use entity::{
+    car::{Component, Loadout},
+    game::GameType,
+};
+
+mod storage;
+pub use storage::*;
 
 #[derive(Debug, ::thiserror::Error)]
 pub enum GameManagerError {
@@ -14,15 +21,70 @@ pub enum GameManagerError {
     IncorrectPlayerId(u32),
     #[error("Incorrect step number")]
     IncorrectStepNumber,
+    #[error("Step `{0}` is already closed")]
+    StepClosed(u32),
     #[error("Incorrect income steps")]
     IncorrectIncomeSteps,
     #[error("Incorrect income players")]
     IncorrectIncomePlayers,
+    #[error("Game `{0}` is not finished yet")]
+    GameNotFinished(u32),
+    #[error("Game `{0}` is not open for changes")]
+    GameNotOpen(u32),
+    #[error("Game `{0}` needs at least two players to start")]
+    NotEnoughPlayers(u32),
+    #[error("Game `{0}` is private, an invite link is required to join it")]
+    InviteRequired(u32),
+    #[error("Invite link to game `{0}` is invalid or was revoked")]
+    InvalidInviteToken(u32),
+    #[error("User `{0}` already joined this game")]
+    AlreadyJoined(u32),
+    #[error("User `{0}` not joined this game")]
+    NotJoined(u32),
+    #[error("Game owner can't leave the game, cancel it instead")]
+    OwnerCannotLeave,
+    #[error("Player with pid=`{0}` has resigned from this game")]
+    PlayerResigned(u32),
+    #[error("Spectator pid=`{0}` can't change this game")]
+    SpectatorReadOnly(u32),
+    #[error("Game `{0}` is imported from a replay and can't be changed")]
+    ImportedReadOnly(u32),
+    #[error("Unknown {} component: `{1}`", .0.name())]
+    IncorrectCarComponent(Component, u32),
     #[error("DbErr: `{0}`")]
     DbErr(#[from] DbErr),
 }
 
+/// проверяет, что все детали мехоса есть в каталоге
+pub fn validate_loadout(loadout: Loadout) -> Result<(), GameManagerError> {
+    loadout
+        .validate()
+        .map_err(|(component, id)| GameManagerError::IncorrectCarComponent(component, id))
+}
+
+/// отмечает в логе детали мехоса, которых нет в каталоге
+///
+/// Каталог не сверен с ресурсами клиента, поэтому ходы с такими деталями
+/// не отклоняются.
+pub fn warn_unknown_components(loadout: Loadout) {
+    if let Err((component, id)) = loadout.validate() {
+        ::log::warn!("unknown {} component: {}", component.name(), id);
+    }
+}
+
+/// чем закончилась запись хода игрока
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepOutcome {
+    /// ход записан впервые
+    Inserted,
+    /// ход ещё открыт, и игрок его изменил
+    Updated,
+    /// такой же ход уже был записан ранее
+    Duplicate,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum GameStatus {
     Open,
     Started,
@@ -30,40 +92,103 @@ pub enum GameStatus {
     Cancelled,
 }
 
+/// длина секрета ссылки-приглашения
+const INVITE_TOKEN_LEN: usize = 24;
+
+/// новый секрет для ссылки-приглашения в приватную игру
+pub fn new_invite_token() -> String {
+    ::rand::thread_rng()
+        .sample_iter(&::rand::distributions::Alphanumeric)
+        .take(INVITE_TOKEN_LEN)
+        .map(char::from)
+        .collect()
+}
+
+/// с каким наименьшим числом игроков можно начать игру
+pub const MIN_PLAYERS: usize = 2;
+
+/// Pid зрителей не пересекаются с номерами игроков (0..5).
+///
+/// Pid действует только вместе с `gmid` своей игры и служит паролем зрителя,
+/// поэтому его не должно быть легко угадать. Берутся только положительные
+/// значения `i32`, чтобы не зависеть от того, знаковым ли числом pid хранит клиент.
+const SPECTATOR_PIDS: std::ops::RangeInclusive<u32> = 1000..=i32::MAX as u32;
+
 #[derive(Debug)]
-pub struct GameManager<'a> {
-    db: &'a DbConn,
+pub struct GameManager<'a, S = DbConn> {
+    storage: &'a S,
     pub game: entity::game::Model,
     pub turns: Vec<(entity::turn::Model, entity::user::Model)>,
     active_pid: Option<u32>,
 }
 
-impl<'db> GameManager<'db> {
-    pub async fn load_game(db: &'db DbConn, gmid: u32) -> Result<Self, GameManagerError> {
-        let game = entity::game::Entity::find_by_id(gmid)
-            .one(db)
+impl<'s, S: GameStorage> GameManager<'s, S> {
+    pub async fn load_game(storage: &'s S, gmid: u32) -> Result<Self, GameManagerError> {
+        let game = storage
+            .find_game(gmid)
             .await?
             .ok_or(GameManagerError::GameNotFound(gmid))?;
 
-        let turns = game
-            .find_related(entity::turn::Entity)
-            .find_also_related(entity::user::Entity)
-            .all(db)
-            .await?
-            .into_iter()
-            .map(|(turn, user)| (turn, user.unwrap()))
-            .collect::<Vec<_>>();
+        let turns = storage.find_turns(gmid).await?;
 
         Ok(Self {
-            db,
+            storage,
             game,
             turns,
             active_pid: None,
         })
     }
+
+    /// менеджер поверх уже загруженного (например, из кэша) состояния игры
+    pub fn from_parts(
+        storage: &'s S,
+        game: entity::game::Model,
+        turns: Vec<(entity::turn::Model, entity::user::Model)>,
+    ) -> Self {
+        Self {
+            storage,
+            game,
+            turns,
+            active_pid: None,
+        }
+    }
+
+    /// создаёт игру и сразу добавляет в неё владельца первым игроком
+    pub async fn create(
+        storage: &'s S,
+        game: entity::game::Model,
+        owner: &entity::user::Model,
+        loadout: Loadout,
+    ) -> Result<Self, GameManagerError> {
+        validate_loadout(loadout)?;
+
+        let game = storage.insert_game(game).await?;
+        let turn = entity::turn::Model::new(game.id, owner.id, 0, loadout);
+        let turn = storage.insert_turn(turn).await?;
+
+        Ok(Self {
+            storage,
+            game,
+            turns: vec![(turn, owner.clone())],
+            active_pid: None,
+        })
+    }
+
+    /// Захватывает игру на запись и загружает её.
+    ///
+    /// Вызывается первым запросом транзакции: увеличение `version` блокирует
+    /// строку игры, поэтому параллельные изменения той же игры выполняются
+    /// строго друг за другом и видят уже записанные ходы.
+    pub async fn lock_game(storage: &'s S, gmid: u32) -> Result<Self, GameManagerError> {
+        if !storage.lock_game(gmid).await? {
+            Err(GameManagerError::GameNotFound(gmid))?
+        }
+
+        Self::load_game(storage, gmid).await
+    }
 }
 
-impl GameManager<'_> {
+impl<S: GameStorage> GameManager<'_, S> {
     /// число сделанных полных ходов
     pub fn move_cnt(&self) -> u32 {
         if self.status() == GameStatus::Open {
@@ -107,23 +232,511 @@ impl GameManager<'_> {
     }
 
     pub fn status(&self) -> GameStatus {
-        if { self.game.players_cnt as usize } <= self.turns.len() {
+        if self.game.cancelled_at.is_some() {
+            GameStatus::Cancelled
+        } else if self.game.finished_at.is_some() {
+            GameStatus::Finished
+        } else if self.game.starts_at > Some(::chrono::Utc::now().naive_utc()) {
+            // до назначенного времени набор открыт, даже если все места заняты
+            GameStatus::Open
+        } else if { self.game.players_cnt as usize } <= self.turns.len() {
             GameStatus::Started
         } else {
             GameStatus::Open
         }
     }
 
-    pub async fn apply_results(&mut self, packet: &mut Packet) -> Result<(), GameManagerError> {
-        use ActiveValue::*;
+    /// добавляет пользователя в набирающую игроков игру
+    pub async fn join(&mut self, user: &entity::user::Model) -> Result<(), GameManagerError> {
+        self.join_with(user, user.loadout()).await
+    }
+
+    /// добавляет пользователя в набирающую игроков игру с заданным мехосом
+    pub async fn join_with(
+        &mut self,
+        user: &entity::user::Model,
+        loadout: Loadout,
+    ) -> Result<(), GameManagerError> {
+        validate_loadout(loadout)?;
+
+        if self.status() != GameStatus::Open {
+            Err(GameManagerError::GameNotOpen(self.game.id))?
+        }
+
+        if self.turns.iter().any(|(_, u)| u.id == user.id) {
+            Err(GameManagerError::AlreadyJoined(user.id))?
+        }
+
+        // игра по расписанию открыта и с занятыми местами
+        if self.turns.len() >= self.game.players_cnt as usize {
+            Err(GameManagerError::GameNotOpen(self.game.id))?
+        }
+
+        // до начала игры есть только ходы первого шага, а номера игроков
+        // всегда идут подряд, поэтому следующий свободный номер равен их числу
+        let turn =
+            entity::turn::Model::new(self.game.id, user.id, self.turns.len() as u32, loadout);
+        let turn = self.storage.insert_turn(turn).await?;
+
+        self.turns.push((turn, user.clone()));
+
+        if self.status() == GameStatus::Started {
+            self.open_step().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Убирает игрока из набирающей игроков игры (сам вышел или был исключён владельцем).
+    ///
+    /// Номера игроков переписываются несколькими запросами, поэтому менеджер
+    /// должен работать поверх транзакции.
+    pub async fn leave(&mut self, user_id: u32) -> Result<(), GameManagerError> {
+        if self.status() != GameStatus::Open {
+            Err(GameManagerError::GameNotOpen(self.game.id))?
+        }
+
+        if user_id == self.game.owner_id {
+            Err(GameManagerError::OwnerCannotLeave)?
+        }
+
+        let removed = self
+            .turns
+            .iter()
+            .map(|(t, _)| t)
+            .find(|t| t.user_id == user_id)
+            .cloned()
+            .ok_or(GameManagerError::NotJoined(user_id))?;
+
+        // состояние менеджера меняется, только когда все запросы прошли
+        let mut turns = self
+            .turns
+            .iter()
+            .filter(|(t, _)| t.id != removed.id)
+            .cloned()
+            .collect::<Vec<_>>();
+
+        // номера оставшихся игроков сдвигаются, чтобы не было пропусков;
+        // сдвиг идёт по возрастанию, так что номера не пересекаются
+        turns.sort_by_key(|(t, _)| t.player_number);
+
+        self.storage.delete_turn(removed.id).await?;
+
+        for (turn, _) in turns
+            .iter_mut()
+            .filter(|(t, _)| t.player_number > removed.player_number)
+        {
+            let mut changed = turn.clone();
+            changed.player_number -= 1;
+            *turn = self.storage.update_turn(changed).await?;
+        }
+
+        self.turns = turns;
+
+        Ok(())
+    }
+
+    /// Открывает игру для зрителей или закрывает её.
+    ///
+    /// При открытии выдаётся новый pid, поэтому ранее выданный зрителям pid
+    /// после закрытия и повторного открытия больше не действует.
+    pub async fn set_spectators(&mut self, enabled: bool) -> Result<(), GameManagerError> {
+        if self.status() == GameStatus::Cancelled {
+            Err(GameManagerError::GameNotActive(self.game.id))?
+        }
+        if self.game.imported_from.is_some() {
+            Err(GameManagerError::ImportedReadOnly(self.game.id))?
+        }
+
+        if enabled == self.game.spectator_pid.is_some() {
+            return Ok(());
+        }
+
+        let mut game = self.game.clone();
+        game.spectator_pid = enabled.then(|| ::rand::thread_rng().gen_range(SPECTATOR_PIDS));
+        self.game = self.storage.update_game(game).await?;
+
+        Ok(())
+    }
+
+    /// Параметры реванша: та же трасса и те же правила, но новое случайное число
+    /// и столько же мест, сколько было игроков-людей. Места роботов в реванш не переходят.
+    pub fn rematch(&self, owner_id: u32) -> Result<entity::game::Model, GameManagerError> {
+        if self.status() != GameStatus::Finished {
+            Err(GameManagerError::GameNotFinished(self.game.id))?
+        }
+        // все места загруженной игры принадлежат загрузившему её
+        if self.game.imported_from.is_some() {
+            Err(GameManagerError::ImportedReadOnly(self.game.id))?
+        }
+
+        let Some(world) = self.game.world() else {
+            Err(DbErr::Custom(format!(
+                "game {} has unknown world",
+                self.game.id
+            )))?
+        };
+
+        let mut game = entity::game::Model::new(
+            owner_id,
+            world,
+            self.game.game_type,
+            self.game.laps,
+            self.game.seeds,
+            self.game.duration,
+            self.last_loadouts().len().max(MIN_PLAYERS) as u32,
+        );
+        // реванш приватной игры тоже приватный, но со своей ссылкой
+        game.is_private = self.game.is_private;
+        game.invite_token = game.is_private.then(new_invite_token);
+
+        Ok(game)
+    }
+
+    /// Участники-люди игры и мехосы их последних ходов, по номерам игроков.
+    ///
+    /// Роботы пропускаются, пользователь, занимавший несколько мест, попадает один раз.
+    pub fn last_loadouts(&self) -> Vec<(&entity::user::Model, Loadout)> {
+        let mut loadouts: Vec<(&entity::user::Model, Loadout)> = vec![];
+        for (turn, user) in self.last_turns() {
+            if !turn.is_robot && loadouts.iter().all(|(u, _)| u.id != user.id) {
+                loadouts.push((user, turn.loadout()));
+            }
+        }
+
+        loadouts
+    }
+
+    /// Проверяет, что в игру можно войти по ссылке с секретом `token`.
+    ///
+    /// В публичные игры ссылка не нужна.
+    pub fn check_invite(&self, token: Option<&str>) -> Result<(), GameManagerError> {
+        if !self.game.is_private {
+            return Ok(());
+        }
+
+        match token {
+            None => Err(GameManagerError::InviteRequired(self.game.id)),
+            Some(token) if self.game.invite_token.as_deref() == Some(token) => Ok(()),
+            Some(_) => Err(GameManagerError::InvalidInviteToken(self.game.id)),
+        }
+    }
+
+    /// Выдаёт новую ссылку-приглашение в приватную игру или отзывает текущую.
+    ///
+    /// Старая ссылка в обоих случаях перестаёт действовать.
+    pub async fn set_invite_token(&mut self, enabled: bool) -> Result<(), GameManagerError> {
+        if self.status() != GameStatus::Open || !self.game.is_private {
+            Err(GameManagerError::GameNotOpen(self.game.id))?
+        }
+
+        let mut game = self.game.clone();
+        game.invite_token = enabled.then(new_invite_token);
+        self.game = self.storage.update_game(game).await?;
+
+        Ok(())
+    }
+
+    /// Закрывает набор и начинает игру с уже вошедшими игроками:
+    /// число мест уменьшается до числа игроков.
+    pub async fn start(&mut self) -> Result<(), GameManagerError> {
+        if self.status() != GameStatus::Open {
+            Err(GameManagerError::GameNotOpen(self.game.id))?
+        }
+
+        if self.turns.len() < MIN_PLAYERS {
+            Err(GameManagerError::NotEnoughPlayers(self.game.id))?
+        }
+
+        let now = ::chrono::Utc::now().naive_utc();
+        let mut game = self.game.clone();
+        game.players_cnt = self.turns.len() as u32;
+        game.starts_at = game.starts_at.map(|t| t.min(now));
+        game.step_started_at = Some(now);
+        self.game = self.storage.update_game(game).await?;
+
+        Ok(())
+    }
+
+    /// Открывает первый шаг игры по расписанию, все места в которой заняли до срока:
+    /// к назначенному времени она начинается сама, но время на ход ещё не отсчитывается.
+    ///
+    /// Возвращает `false`, если открывать нечего.
+    pub async fn open_scheduled(&mut self) -> Result<bool, GameManagerError> {
+        if self.status() != GameStatus::Started || self.game.step_started_at.is_some() {
+            return Ok(false);
+        }
+
+        self.open_step().await?;
+
+        Ok(true)
+    }
+
+    /// Занимает свободные места роботами и тем самым начинает игру.
+    ///
+    /// Роботы играют от имени пользователя `robot` и пропускают каждый ход.
+    pub async fn add_robots(
+        &mut self,
+        robot: &entity::user::Model,
+    ) -> Result<(), GameManagerError> {
+        if self.status() != GameStatus::Open {
+            Err(GameManagerError::GameNotOpen(self.game.id))?
+        }
+
+        for player_number in self.turns.len() as u32..self.game.players_cnt {
+            let mut turn =
+                entity::turn::Model::new(self.game.id, robot.id, player_number, robot.loadout());
+            turn.is_robot = true;
+            turn.seeds = Some(String::new());
+
+            let turn = self.storage.insert_turn(turn).await?;
+            self.turns.push((turn, robot.clone()));
+        }
+
+        let now = ::chrono::Utc::now().naive_utc();
+        let mut game = self.game.clone();
+        game.starts_at = game.starts_at.map(|t| t.min(now));
+        game.step_started_at = Some(now);
+        self.game = self.storage.update_game(game).await?;
+
+        Ok(())
+    }
+
+    /// Отмечает начало нового шага: от него отсчитывается время на ход.
+    ///
+    /// Шаг, открытый на паузе, начинается с её начала, и снятие паузы
+    /// сдвигает его начало на момент продолжения игры.
+    async fn open_step(&mut self) -> Result<(), GameManagerError> {
+        let mut game = self.game.clone();
+        game.step_started_at = Some(
+            game.paused_at
+                .unwrap_or_else(|| ::chrono::Utc::now().naive_utc()),
+        );
+        self.game = self.storage.update_game(game).await?;
+
+        Ok(())
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.game.paused_at.is_some()
+    }
+
+    /// Ставит идущую игру на паузу или снимает её.
+    ///
+    /// Время паузы не считается временем на ход: при снятии паузы
+    /// начало шага сдвигается на её длительность.
+    pub async fn set_paused(&mut self, paused: bool) -> Result<(), GameManagerError> {
+        if self.status() != GameStatus::Started {
+            Err(GameManagerError::GameNotActive(self.game.id))?
+        }
+
+        let now = ::chrono::Utc::now().naive_utc();
+        let mut game = self.game.clone();
+        match (paused, game.paused_at) {
+            (true, None) => game.paused_at = Some(now),
+            (false, Some(paused_at)) => {
+                game.paused_at = None;
+                game.step_started_at = game.step_started_at.map(|t| t + (now - paused_at));
+            }
+            _ => return Ok(()),
+        }
+        self.game = self.storage.update_game(game).await?;
+
+        Ok(())
+    }
+
+    /// пользователи, которые ещё не сделали ход в открытом шаге
+    pub fn waiting_players(&self) -> Vec<u32> {
+        if self.status() != GameStatus::Started {
+            return vec![];
+        }
+
+        let current_step = self.move_cnt() + 1;
+
+        self.last_turns()
+            .into_iter()
+            .filter(|(t, _)| !t.is_robot)
+            .filter(|(t, _)| t.step_number < current_step || t.seeds.is_none())
+            .map(|(_, u)| u.id)
+            .collect()
+    }
 
+    /// время на ход в игре по переписке, в ней `duration` задаётся в часах
+    pub fn turn_duration(&self) -> Option<::chrono::Duration> {
+        (!self.game.is_express).then(|| ::chrono::Duration::hours(self.game.duration.into()))
+    }
+
+    /// Сколько осталось до конца открытого шага в игре по переписке.
+    ///
+    /// Пока игра стоит на паузе, время не идёт.
+    pub fn time_left(&self, now: ::chrono::NaiveDateTime) -> Option<::chrono::Duration> {
+        if self.status() != GameStatus::Started {
+            return None;
+        }
+
+        let duration = self.turn_duration()?;
+        let step_started_at = self
+            .game
+            .step_started_at
+            .or(self.game.starts_at)
+            .unwrap_or(self.game.created_at);
+        let now = self.game.paused_at.unwrap_or(now);
+
+        Some((step_started_at + duration - now).max(::chrono::Duration::zero()))
+    }
+
+    /// пользователи, которые ещё ходят сами: не роботы и не сдавшиеся
+    pub fn active_players(&self) -> Vec<u32> {
+        self.last_turns()
+            .into_iter()
+            .filter(|(t, _)| !t.is_robot && !t.is_resigned)
+            .map(|(_, u)| u.id)
+            .collect()
+    }
+
+    /// Записывает пустые ходы роботов и сдавшихся игроков в открытый шаг,
+    /// чтобы остальные их не ждали.
+    pub async fn auto_moves(&mut self) -> Result<(), GameManagerError> {
+        if self.status() != GameStatus::Started {
+            return Ok(());
+        }
+
+        let current_step = self.move_cnt() + 1;
+
+        let absent = self
+            .last_turns()
+            .into_iter()
+            .filter(|(t, _)| (t.is_robot || t.is_resigned) && t.step_number < current_step)
+            .map(|(t, u)| (t.clone(), u.clone()))
+            .collect::<Vec<_>>();
+
+        for (mut turn, user) in absent {
+            turn.step_number = current_step;
+            turn.seeds = Some(String::new());
+            turn.user_seeds_cnt = 0;
+            turn.is_received = false;
+
+            let turn = self.storage.insert_turn(turn).await?;
+            self.turns.push((turn, user));
+        }
+
+        Ok(())
+    }
+
+    /// Игрок сдаётся в идущей игре.
+    ///
+    /// Уже сделанный ход остаётся в силе, несделанный записывается пустым,
+    /// а дальше за игрока ходит сервер. Если живых игроков не осталось, игра завершается.
+    pub async fn resign(&mut self, user_id: u32) -> Result<(), GameManagerError> {
+        if self.status() != GameStatus::Started {
+            Err(GameManagerError::GameNotActive(self.game.id))?
+        }
+
+        let current_step = self.move_cnt() + 1;
+
+        let (last_turn, _) = self
+            .last_turns()
+            .into_iter()
+            .find(|(t, u)| u.id == user_id && !t.is_robot)
+            .ok_or(GameManagerError::NotJoined(user_id))?;
+        if last_turn.is_resigned {
+            Err(GameManagerError::PlayerResigned(last_turn.player_number))?
+        }
+
+        let mut turn = last_turn.clone();
+        turn.is_resigned = true;
+
+        if turn.step_number == current_step {
+            turn.seeds.get_or_insert_with(String::new);
+            let turn = self.storage.update_turn(turn).await?;
+            let stored = self
+                .turns
+                .iter_mut()
+                .find(|(t, _)| t.id == turn.id)
+                .unwrap();
+            stored.0 = turn;
+        } else {
+            turn.step_number = current_step;
+            turn.seeds = Some(String::new());
+            turn.user_seeds_cnt = 0;
+            turn.is_received = false;
+
+            let user = self
+                .turns
+                .iter()
+                .find(|(t, _)| t.id == turn.id)
+                .unwrap()
+                .1
+                .clone();
+            let turn = self.storage.insert_turn(turn).await?;
+            self.turns.push((turn, user));
+        }
+
+        let is_abandoned = self
+            .last_turns()
+            .iter()
+            .all(|(t, _)| t.is_robot || t.is_resigned);
+        if is_abandoned {
+            let mut game = self.game.clone();
+            game.finished_at = Some(::chrono::Utc::now().naive_utc());
+            self.game = self.storage.update_game(game).await?;
+            return Ok(());
+        }
+
+        // ход сдавшегося мог закрыть шаг
+        if self.move_cnt() >= current_step {
+            self.open_step().await?;
+        }
+
+        self.auto_moves().await
+    }
+
+    /// последние ходы каждого игрока, по номерам игроков
+    fn last_turns(&self) -> Vec<(&entity::turn::Model, &entity::user::Model)> {
+        let mut last = std::collections::BTreeMap::new();
+        for (turn, user) in &self.turns {
+            let entry = last.entry(turn.player_number).or_insert((turn, user));
+            if entry.0.step_number < turn.step_number {
+                *entry = (turn, user);
+            }
+        }
+
+        last.into_values().collect()
+    }
+
+    /// отменяет набирающую игроков игру
+    pub async fn cancel(&mut self) -> Result<(), GameManagerError> {
+        if self.status() != GameStatus::Open {
+            Err(GameManagerError::GameNotOpen(self.game.id))?
+        }
+
+        let mut game = self.game.clone();
+        game.cancelled_at = Some(::chrono::Utc::now().naive_utc());
+        self.game = self.storage.update_game(game).await?;
+
+        Ok(())
+    }
+
+    pub async fn apply_results(&mut self, packet: &mut Packet) -> Result<(), GameManagerError> {
         assert!(self.active_pid.is_some());
         assert!(packet.t_type == PacketType::OG_CONTROL_PACKET);
 
-        if self.status() != GameStatus::Started {
+        if self.is_spectator() {
+            Err(GameManagerError::SpectatorReadOnly(
+                self.active_pid.unwrap(),
+            ))?
+        }
+
+        if !matches!(self.status(), GameStatus::Started | GameStatus::Finished) {
+            // результаты финального хода приходят от каждого игрока, в том числе
+            // после того, как игра уже отмечена завершённой
             Err(GameManagerError::GameNotActive(self.game.id))? // попытка сделать ход в не начатой игре
         }
 
+        for player in &packet.players {
+            warn_unknown_components(player.loadout());
+        }
+
         if packet.move_cnt == 0 && packet.steps.is_empty() {
             return Ok(());
         }
@@ -164,101 +777,187 @@ impl GameManager<'_> {
 
             // TODO: check is_finished, add ratings
 
-            let mut turn = turn.clone().into_active_model();
-            turn.is_finished = Set(income_t.is_finished);
-            turn.rank = Set(income_t.rank);
-            turn.move_time = Set(income_t.move_time);
-            turn.move_steps = Set(income_t.move_steps);
-            turn.bottles_cnt = Set(income_t.bottles_cnt);
-            turn.total_seeds_cnt = Set(income_t.total_seeds_cnt);
-            turn.arcanes_cnt = Set(income_t.arcanes_cnt);
-            turn.destroys_cnt = Set(income_t.destroys_cnt);
-            turn.prop_pers = Set(income_p.pers_car_comp_id);
-            turn.prop_car = Set(income_p.front_car_comp_id);
-            turn.prop_fwheel = Set(income_p.fwheel_car_comp_id);
-            turn.prop_bwheel = Set(income_p.bwheel_car_comp_id);
-            turn.update(self.db)
-                .await
-                .map_err(GameManagerError::DbErr)?;
+            let mut turn = turn.clone();
+            turn.is_finished = income_t.is_finished;
+            turn.rank = income_t.rank;
+            turn.move_time = income_t.move_time;
+            turn.move_steps = income_t.move_steps;
+            turn.bottles_cnt = income_t.bottles_cnt;
+            turn.total_seeds_cnt = income_t.total_seeds_cnt;
+            turn.arcanes_cnt = income_t.arcanes_cnt;
+            turn.destroys_cnt = income_t.destroys_cnt;
+            turn.prop_pers = income_p.pers_car_comp_id;
+            turn.prop_car = income_p.front_car_comp_id;
+            turn.prop_fwheel = income_p.fwheel_car_comp_id;
+            turn.prop_bwheel = income_p.bwheel_car_comp_id;
+            self.storage.update_turn(turn).await?;
+        }
+
+        if self.game.finished_at.is_none() && self.is_final_step(&income_turns) {
+            let mut game = self.game.clone();
+            game.finished_at = Some(::chrono::Utc::now().naive_utc());
+            self.game = self.storage.update_game(game).await?;
         }
 
         Ok(())
     }
 
-    pub async fn apply_step(&mut self, packet: &mut Packet) -> Result<(), GameManagerError> {
-        use ActiveValue::*;
+    /// является ли ход с такими результатами обсчёта последним в игре
+    fn is_final_step(&self, results: &[&PlayerTurnInfo]) -> bool {
+        // роботы и сдавшиеся стоят на месте, их финиша не ждут
+        let absent = self
+            .turns
+            .iter()
+            .filter(|(t, _)| t.is_robot || t.is_resigned)
+            .map(|(t, _)| t.player_number)
+            .collect::<Vec<_>>();
+
+        let mut results = results.iter().filter(|t| !absent.contains(&t.player_id));
 
+        match self.game.game_type {
+            GameType::Winner => results.any(|t| t.is_finished),
+            GameType::All => results.all(|t| t.is_finished),
+        }
+    }
+
+    pub async fn apply_step(
+        &mut self,
+        packet: &mut Packet,
+    ) -> Result<StepOutcome, GameManagerError> {
         assert!(self.active_pid.is_some());
         assert!(packet.t_type == PacketType::OG_SEEDS_PACKET);
 
-        if self.status() != GameStatus::Started {
+        if self.is_spectator() {
+            Err(GameManagerError::SpectatorReadOnly(
+                self.active_pid.unwrap(),
+            ))?
+        }
+
+        if !matches!(self.status(), GameStatus::Started | GameStatus::Finished) {
             Err(GameManagerError::GameNotActive(self.game.id))? // попытка сделать ход в не начатой игре
         }
 
+        let is_resigned = self
+            .turns
+            .iter()
+            .any(|(t, _)| t.player_number == self.active_pid.unwrap() && t.is_resigned);
+        if is_resigned {
+            Err(GameManagerError::PlayerResigned(self.active_pid.unwrap()))?
+        }
+
         let current_step = { self.move_cnt() + 1 };
 
         let income_step = packet
             .steps
             .iter()
-            .find(|step| {
-                step.player_id == self.active_pid.unwrap() && step.step_number == current_step
-            })
+            .filter(|step| step.player_id == self.active_pid.unwrap())
+            .max_by_key(|step| step.step_number)
             .ok_or(GameManagerError::IncorrectStepNumber)?;
+        let income_seeds = match income_step.seeds.as_str() {
+            "" => None,
+            s => Some(s),
+        };
+
+        // клиент не дождался ответа и отправил тот же ход повторно
+        let is_duplicate = self
+            .turns
+            .iter()
+            .map(|(t, _)| t)
+            .find(|t| {
+                t.player_number == self.active_pid.unwrap()
+                    && t.step_number == income_step.step_number
+            })
+            .is_some_and(|t| {
+                t.seeds.is_some()
+                    && t.seeds.as_deref() == income_seeds
+                    && t.user_seeds_cnt == income_step.user_seeds_cnt
+            });
+        if is_duplicate {
+            return Ok(StepOutcome::Duplicate);
+        }
+
+        if income_step.step_number < current_step || self.status() == GameStatus::Finished {
+            // все игроки уже сделали этот ход, менять его нельзя
+            Err(GameManagerError::StepClosed(income_step.step_number))?
+        }
+        if income_step.step_number != current_step {
+            Err(GameManagerError::IncorrectStepNumber)?
+        }
+
         let income_player = packet
             .players
             .iter()
             .find(|p| p.uid == self.active_pid.unwrap());
+        if let Some(income_player) = income_player {
+            warn_unknown_components(income_player.loadout());
+        }
 
         let (last_turn, _) = self
             .turns
-            .iter_mut()
+            .iter()
             .filter(|(t, _)| t.player_number == self.active_pid.unwrap())
             .max_by_key(|(t, _)| t.step_number)
             .unwrap();
 
-        let mut turn = last_turn.clone().into_active_model();
-        if last_turn.step_number != current_step {
+        let is_new_step = last_turn.step_number != current_step;
+        let last_turn_id = last_turn.id;
+
+        let mut turn = last_turn.clone();
+        if is_new_step {
             // we need to insert new row in DB
-            turn.id = NotSet;
-            turn.is_received = NotSet;
-            turn.step_number = Set(current_step);
-            turn.user_seeds_cnt = Set(0);
-            turn.seeds = Set(None);
-        }
-
-        turn.is_finished = Set(income_step.is_finished);
-        turn.rank = Set(income_step.rank);
-        turn.move_time = Set(income_step.move_time);
-        turn.move_steps = Set(income_step.move_steps);
-        turn.bottles_cnt = Set(income_step.bottles_cnt);
-        turn.total_seeds_cnt = Set(income_step.total_seeds_cnt);
-        turn.arcanes_cnt = Set(income_step.arcanes_cnt);
-        turn.destroys_cnt = Set(income_step.destroys_cnt);
-        turn.user_seeds_cnt = Set(income_step.user_seeds_cnt);
-        turn.seeds = Set(match income_step.seeds.as_str() {
-            "" => None,
-            s => Some(s.to_owned()),
-        });
+            turn.is_received = false;
+            turn.step_number = current_step;
+        }
+
+        turn.is_finished = income_step.is_finished;
+        turn.rank = income_step.rank;
+        turn.move_time = income_step.move_time;
+        turn.move_steps = income_step.move_steps;
+        turn.bottles_cnt = income_step.bottles_cnt;
+        turn.total_seeds_cnt = income_step.total_seeds_cnt;
+        turn.arcanes_cnt = income_step.arcanes_cnt;
+        turn.destroys_cnt = income_step.destroys_cnt;
+        turn.user_seeds_cnt = income_step.user_seeds_cnt;
+        turn.seeds = income_seeds.map(str::to_owned);
 
         if let Some(income_player) = income_player {
-            turn.prop_pers = Set(income_player.pers_car_comp_id);
-            turn.prop_car = Set(income_player.front_car_comp_id);
-            turn.prop_fwheel = Set(income_player.fwheel_car_comp_id);
-            turn.prop_bwheel = Set(income_player.bwheel_car_comp_id);
+            turn.prop_pers = income_player.pers_car_comp_id;
+            turn.prop_car = income_player.front_car_comp_id;
+            turn.prop_fwheel = income_player.fwheel_car_comp_id;
+            turn.prop_bwheel = income_player.bwheel_car_comp_id;
         }
 
-        if turn.id.is_not_set() {
-            turn.insert(self.db).await?;
+        let outcome = if is_new_step {
+            let turn = self.storage.insert_turn(turn).await?;
+            let user = self
+                .turns
+                .iter()
+                .find(|(t, _)| t.id == last_turn_id)
+                .unwrap()
+                .1
+                .clone();
+            self.turns.push((turn, user));
+            StepOutcome::Inserted
         } else {
-            turn.update(self.db).await?;
+            let turn = self.storage.update_turn(turn).await?;
+            let stored = self
+                .turns
+                .iter_mut()
+                .find(|(t, _)| t.id == turn.id)
+                .unwrap();
+            stored.0 = turn;
+            StepOutcome::Updated
+        };
+
+        // ход был последним в шаге
+        if self.move_cnt() >= current_step {
+            self.open_step().await?;
         }
 
-        Ok(())
+        Ok(outcome)
     }
 
     pub async fn get_refresh_packet(&self, packet: &Packet) -> Result<Packet, GameManagerError> {
-        use ActiveValue::*;
-
         assert!(self.active_pid.is_some());
         assert!(packet.t_type == PacketType::OG_REFRESH_PACKET);
 
@@ -295,9 +994,14 @@ impl GameManager<'_> {
             })
             .collect();
 
-        if current_turns.len() < self.game.players_cnt as usize {
+        // на паузе результаты шага не выдаются: клиент ждёт, как будто сходили не все
+        if current_turns.len() < self.game.players_cnt as usize || self.is_paused() {
             // не все сделали свои ходы (turns) в этот ход (step)
             p.t_type = PacketType::OG_REFRESH_ANSWER_PACKET;
+            if self.is_spectator() {
+                // зрителю видны только завершённые ходы
+                p.steps.clear();
+            }
         } else {
             p.t_type = PacketType::OG_GAME_PACKET;
             p.move_cnt = self.move_cnt();
@@ -306,27 +1010,26 @@ impl GameManager<'_> {
                 .iter()
                 .map(|(t, u)| crate::data::Player {
                     uid: t.player_number,
-                    nickname: u.login().to_string(),
+                    nickname: t.nickname(u).to_string(),
                     pers_car_comp_id: t.prop_pers,
                     front_car_comp_id: t.prop_car,
                     fwheel_car_comp_id: t.prop_fwheel,
                     bwheel_car_comp_id: t.prop_bwheel,
-                    is_robot: false,
+                    is_robot: t.is_robot,
                     password: Some(String::from("")),
                 })
                 .collect();
 
-            let mut my_turn = current_turns
+            let my_turn = current_turns
                 .iter()
-                .find(|(t, _)| t.player_number == p.packet_owner_pid)
-                .unwrap()
-                .0
-                .clone()
-                .into_active_model();
-
-            if !matches!(my_turn.is_received.take(), Some(true)) {
-                my_turn.is_received = Set(true);
-                my_turn.update(self.db).await?;
+                .map(|(t, _)| t)
+                .find(|t| t.player_number == p.packet_owner_pid);
+
+            // зритель не отмечает доставку ходов, у него нет своих ходов
+            if let Some(my_turn) = my_turn.filter(|t| !t.is_received) {
+                let mut my_turn = my_turn.clone();
+                my_turn.is_received = true;
+                self.storage.update_turn(my_turn).await?;
             }
         }
 
@@ -343,12 +1046,12 @@ impl GameManager<'_> {
         {
             players.push(Player {
                 uid: turn.player_number,
-                nickname: user.login().to_string(),
-                pers_car_comp_id: turn.prop_car,
+                nickname: turn.nickname(user).to_string(),
+                pers_car_comp_id: turn.prop_pers,
                 front_car_comp_id: turn.prop_car,
                 fwheel_car_comp_id: turn.prop_fwheel,
                 bwheel_car_comp_id: turn.prop_bwheel,
-                is_robot: false,
+                is_robot: turn.is_robot,
                 password: None,
             });
         }
@@ -396,7 +1099,7 @@ impl GameManager<'_> {
             seeds: self.game.seeds,
             duration: self.game.duration,
             move_cnt: self.move_cnt(),
-            is_express: true,
+            is_express: self.game.is_express,
             url: crate::data::UrlProperty {
                 post: "".into(),
                 post_port: 0,
@@ -409,15 +1112,25 @@ impl GameManager<'_> {
     }
 }
 
-impl GameManager<'_> {
+impl<S: GameStorage> GameManager<'_, S> {
+    /// является ли `player_id` игроком или действующим pid зрителя
+    fn is_known_pid(&self, player_id: u32) -> bool {
+        self.game.spectator_pid == Some(player_id)
+            || self
+                .turns
+                .iter()
+                .find(|(t, _u)| t.player_number == player_id)
+                .is_some()
+    }
+
+    /// игра рассматривается от лица зрителя: доступно только чтение
+    pub fn is_spectator(&self) -> bool {
+        self.active_pid.is_some() && self.active_pid == self.game.spectator_pid
+    }
+
     /// Установить pid игрока, от лица которого рассматривать эту игру
     pub fn set_pid(&mut self, player_id: u32) -> Result<(), GameManagerError> {
-        if self
-            .turns
-            .iter()
-            .find(|(t, _u)| t.player_number == player_id)
-            .is_some()
-        {
+        if self.is_known_pid(player_id) {
             self.active_pid = Some(player_id);
             Ok(())
         } else {
@@ -427,33 +1140,103 @@ impl GameManager<'_> {
 
     /// Установить pid игрока, от лица которого рассматривать эту игру
     pub fn with_pid(mut self, player_id: u32) -> Result<Self, Self> {
-        if self
-            .turns
-            .iter()
-            .find(|(t, _u)| t.player_number == player_id)
-            .is_some()
-        {
+        if self.is_known_pid(player_id) {
             self.active_pid = Some(player_id);
             Ok(self)
         } else {
             Err(self)
         }
     }
-}
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::NaiveDateTime;
+
+    fn now() -> NaiveDateTime {
+        ::chrono::Utc::now().naive_utc()
+    }
+
+    #[test]
+    fn empty_open_game() {
+        let manager = GameManager {
+            storage: &MemoryStorage::default(),
+            game: entity::game::Model {
+                id: 1,
+                owner_id: 0,
+                world_id: 0,
+                track_id: 0,
+                rnd: 123,
+                game_type: entity::game::GameType::Winner,
+                laps: 1,
+                seeds: 10,
+                duration: 100,
+                is_express: true,
+                players_cnt: 3,
+                finished_at: None,
+                cancelled_at: None,
+                version: 0,
+                spectator_pid: None,
+                is_private: false,
+                invite_token: None,
+                starts_at: None,
+                fill_with_robots: false,
+                paused_at: None,
+                step_started_at: None,
+                imported_from: None,
+                created_at: now(),
+                updated_at: now(),
+            },
+            turns: vec![],
+            active_pid: None,
+        };
+
+        assert_eq!(manager.status(), GameStatus::Open);
+        assert_eq!(manager.move_cnt(), 0);
+    }
 
-#[cfg(test)]
-mod test {
-    use super::*;
-    use chrono::NaiveDateTime;
+    #[test]
+    fn finished_game() {
+        let manager = GameManager {
+            storage: &MemoryStorage::default(),
+            game: entity::game::Model {
+                id: 1,
+                owner_id: 0,
+                world_id: 0,
+                track_id: 0,
+                rnd: 123,
+                game_type: entity::game::GameType::Winner,
+                laps: 1,
+                seeds: 10,
+                duration: 100,
+                is_express: true,
+                players_cnt: 3,
+                finished_at: Some(now()),
+                cancelled_at: None,
+                version: 0,
+                spectator_pid: None,
+                is_private: false,
+                invite_token: None,
+                starts_at: None,
+                fill_with_robots: false,
+                paused_at: None,
+                step_started_at: None,
+                imported_from: None,
+                created_at: now(),
+                updated_at: now(),
+            },
+            turns: vec![],
+            active_pid: None,
+        };
 
-    fn now() -> NaiveDateTime {
-        ::chrono::Utc::now().naive_utc()
+        assert_eq!(manager.status(), GameStatus::Finished);
     }
 
     #[test]
-    fn empty_open_game() {
+    fn cancelled_game() {
         let manager = GameManager {
-            db: &DbConn::Disconnected,
+            storage: &MemoryStorage::default(),
             game: entity::game::Model {
                 id: 1,
                 owner_id: 0,
@@ -466,6 +1249,17 @@ mod test {
                 duration: 100,
                 is_express: true,
                 players_cnt: 3,
+                finished_at: None,
+                cancelled_at: Some(now()),
+                version: 0,
+                spectator_pid: None,
+                is_private: false,
+                invite_token: None,
+                starts_at: None,
+                fill_with_robots: false,
+                paused_at: None,
+                step_started_at: None,
+                imported_from: None,
                 created_at: now(),
                 updated_at: now(),
             },
@@ -473,14 +1267,14 @@ mod test {
             active_pid: None,
         };
 
-        assert_eq!(manager.status(), GameStatus::Open);
+        assert_eq!(manager.status(), GameStatus::Cancelled);
         assert_eq!(manager.move_cnt(), 0);
     }
 
     #[test]
     fn open_game_with_2_players() {
         let manager = GameManager {
-            db: &DbConn::Disconnected,
+            storage: &MemoryStorage::default(),
             game: entity::game::Model {
                 id: 1,
                 owner_id: 1,
@@ -493,6 +1287,17 @@ mod test {
                 duration: 100,
                 is_express: true,
                 players_cnt: 3,
+                finished_at: None,
+                cancelled_at: None,
+                version: 0,
+                spectator_pid: None,
+                is_private: false,
+                invite_token: None,
+                starts_at: None,
+                fill_with_robots: false,
+                paused_at: None,
+                step_started_at: None,
+                imported_from: None,
                 created_at: now(),
                 updated_at: now(),
             },
@@ -519,17 +1324,13 @@ mod test {
                         prop_fwheel: 1,
                         prop_bwheel: 1,
                         is_received: false,
+                        is_robot: false,
+                        is_resigned: false,
+                        nickname: None,
                         created_at: now(),
                         updated_at: now(),
                     },
-                    entity::user::Model {
-                        id: 1,
-                        steam_id: 111,
-                        login: Some(String::from("login111")),
-                        is_blocked: entity::user::UserBlocked::Nope,
-                        created_at: now(),
-                        updated_at: now(),
-                    },
+                    memory_user(1),
                 ),
                 (
                     entity::turn::Model {
@@ -553,17 +1354,13 @@ mod test {
                         prop_fwheel: 1,
                         prop_bwheel: 1,
                         is_received: false,
+                        is_robot: false,
+                        is_resigned: false,
+                        nickname: None,
                         created_at: now(),
                         updated_at: now(),
                     },
-                    entity::user::Model {
-                        id: 2,
-                        steam_id: 222,
-                        login: Some(String::from("login222")),
-                        is_blocked: entity::user::UserBlocked::Nope,
-                        created_at: now(),
-                        updated_at: now(),
-                    },
+                    memory_user(2),
                 ),
             ],
             active_pid: None,
@@ -576,7 +1373,7 @@ mod test {
     #[test]
     fn started_game_with_no_steps() {
         let manager = GameManager {
-            db: &DbConn::Disconnected,
+            storage: &MemoryStorage::default(),
             game: entity::game::Model {
                 id: 1,
                 owner_id: 1,
@@ -589,6 +1386,17 @@ mod test {
                 duration: 100,
                 is_express: true,
                 players_cnt: 3,
+                finished_at: None,
+                cancelled_at: None,
+                version: 0,
+                spectator_pid: None,
+                is_private: false,
+                invite_token: None,
+                starts_at: None,
+                fill_with_robots: false,
+                paused_at: None,
+                step_started_at: None,
+                imported_from: None,
                 created_at: now(),
                 updated_at: now(),
             },
@@ -615,17 +1423,13 @@ mod test {
                         prop_fwheel: 1,
                         prop_bwheel: 1,
                         is_received: false,
+                        is_robot: false,
+                        is_resigned: false,
+                        nickname: None,
                         created_at: now(),
                         updated_at: now(),
                     },
-                    entity::user::Model {
-                        id: 1,
-                        steam_id: 111,
-                        login: Some(String::from("login111")),
-                        is_blocked: entity::user::UserBlocked::Nope,
-                        created_at: now(),
-                        updated_at: now(),
-                    },
+                    memory_user(1),
                 ),
                 (
                     entity::turn::Model {
@@ -649,17 +1453,13 @@ mod test {
                         prop_fwheel: 1,
                         prop_bwheel: 1,
                         is_received: false,
+                        is_robot: false,
+                        is_resigned: false,
+                        nickname: None,
                         created_at: now(),
                         updated_at: now(),
                     },
-                    entity::user::Model {
-                        id: 2,
-                        steam_id: 222,
-                        login: Some(String::from("login222")),
-                        is_blocked: entity::user::UserBlocked::Nope,
-                        created_at: now(),
-                        updated_at: now(),
-                    },
+                    memory_user(2),
                 ),
                 (
                     entity::turn::Model {
@@ -679,6 +1479,9 @@ mod test {
                         user_seeds_cnt: 0,
                         seeds: None,
                         is_received: false,
+                        is_robot: false,
+                        is_resigned: false,
+                        nickname: None,
                         prop_pers: 1,
                         prop_car: 1,
                         prop_fwheel: 1,
@@ -686,14 +1489,7 @@ mod test {
                         created_at: now(),
                         updated_at: now(),
                     },
-                    entity::user::Model {
-                        id: 3,
-                        steam_id: 333,
-                        login: Some(String::from("login333")),
-                        is_blocked: entity::user::UserBlocked::Nope,
-                        created_at: now(),
-                        updated_at: now(),
-                    },
+                    memory_user(3),
                 ),
             ],
             active_pid: None,
@@ -706,7 +1502,7 @@ mod test {
     #[test]
     fn started_game_with_1_step_by_2nd_player() {
         let manager = GameManager {
-            db: &DbConn::Disconnected,
+            storage: &MemoryStorage::default(),
             game: entity::game::Model {
                 id: 1,
                 owner_id: 1,
@@ -719,6 +1515,17 @@ mod test {
                 duration: 100,
                 is_express: true,
                 players_cnt: 3,
+                finished_at: None,
+                cancelled_at: None,
+                version: 0,
+                spectator_pid: None,
+                is_private: false,
+                invite_token: None,
+                starts_at: None,
+                fill_with_robots: false,
+                paused_at: None,
+                step_started_at: None,
+                imported_from: None,
                 created_at: now(),
                 updated_at: now(),
             },
@@ -745,17 +1552,13 @@ mod test {
                         prop_fwheel: 1,
                         prop_bwheel: 1,
                         is_received: false,
+                        is_robot: false,
+                        is_resigned: false,
+                        nickname: None,
                         created_at: now(),
                         updated_at: now(),
                     },
-                    entity::user::Model {
-                        id: 1,
-                        steam_id: 111,
-                        login: Some(String::from("login111")),
-                        is_blocked: entity::user::UserBlocked::Nope,
-                        created_at: now(),
-                        updated_at: now(),
-                    },
+                    memory_user(1),
                 ),
                 (
                     entity::turn::Model {
@@ -779,17 +1582,13 @@ mod test {
                         prop_fwheel: 1,
                         prop_bwheel: 1,
                         is_received: false,
+                        is_robot: false,
+                        is_resigned: false,
+                        nickname: None,
                         created_at: now(),
                         updated_at: now(),
                     },
-                    entity::user::Model {
-                        id: 2,
-                        steam_id: 222,
-                        login: Some(String::from("login222")),
-                        is_blocked: entity::user::UserBlocked::Nope,
-                        created_at: now(),
-                        updated_at: now(),
-                    },
+                    memory_user(2),
                 ),
                 (
                     entity::turn::Model {
@@ -813,17 +1612,13 @@ mod test {
                         prop_fwheel: 1,
                         prop_bwheel: 1,
                         is_received: false,
+                        is_robot: false,
+                        is_resigned: false,
+                        nickname: None,
                         created_at: now(),
                         updated_at: now(),
                     },
-                    entity::user::Model {
-                        id: 3,
-                        steam_id: 333,
-                        login: Some(String::from("login333")),
-                        is_blocked: entity::user::UserBlocked::Nope,
-                        created_at: now(),
-                        updated_at: now(),
-                    },
+                    memory_user(3),
                 ),
             ],
             active_pid: None,
@@ -836,7 +1631,7 @@ mod test {
     #[test]
     fn started_game_with_1_step_by_all_players_without_received() {
         let manager = GameManager {
-            db: &DbConn::Disconnected,
+            storage: &MemoryStorage::default(),
             game: entity::game::Model {
                 id: 1,
                 owner_id: 1,
@@ -849,6 +1644,17 @@ mod test {
                 duration: 100,
                 is_express: true,
                 players_cnt: 3,
+                finished_at: None,
+                cancelled_at: None,
+                version: 0,
+                spectator_pid: None,
+                is_private: false,
+                invite_token: None,
+                starts_at: None,
+                fill_with_robots: false,
+                paused_at: None,
+                step_started_at: None,
+                imported_from: None,
                 created_at: now(),
                 updated_at: now(),
             },
@@ -875,17 +1681,13 @@ mod test {
                         prop_fwheel: 1,
                         prop_bwheel: 1,
                         is_received: false,
+                        is_robot: false,
+                        is_resigned: false,
+                        nickname: None,
                         created_at: now(),
                         updated_at: now(),
                     },
-                    entity::user::Model {
-                        id: 1,
-                        steam_id: 111,
-                        login: Some(String::from("login111")),
-                        is_blocked: entity::user::UserBlocked::Nope,
-                        created_at: now(),
-                        updated_at: now(),
-                    },
+                    memory_user(1),
                 ),
                 (
                     entity::turn::Model {
@@ -909,17 +1711,13 @@ mod test {
                         prop_fwheel: 1,
                         prop_bwheel: 1,
                         is_received: false,
+                        is_robot: false,
+                        is_resigned: false,
+                        nickname: None,
                         created_at: now(),
                         updated_at: now(),
                     },
-                    entity::user::Model {
-                        id: 2,
-                        steam_id: 222,
-                        login: Some(String::from("login222")),
-                        is_blocked: entity::user::UserBlocked::Nope,
-                        created_at: now(),
-                        updated_at: now(),
-                    },
+                    memory_user(2),
                 ),
                 (
                     entity::turn::Model {
@@ -943,17 +1741,13 @@ mod test {
                         prop_fwheel: 1,
                         prop_bwheel: 1,
                         is_received: false,
+                        is_robot: false,
+                        is_resigned: false,
+                        nickname: None,
                         created_at: now(),
                         updated_at: now(),
                     },
-                    entity::user::Model {
-                        id: 3,
-                        steam_id: 333,
-                        login: Some(String::from("login333")),
-                        is_blocked: entity::user::UserBlocked::Nope,
-                        created_at: now(),
-                        updated_at: now(),
-                    },
+                    memory_user(3),
                 ),
             ],
             active_pid: None,
@@ -966,7 +1760,7 @@ mod test {
     #[test]
     fn started_game_with_1_step_by_all_players_with_received_2nd_player() {
         let manager = GameManager {
-            db: &DbConn::Disconnected,
+            storage: &MemoryStorage::default(),
             game: entity::game::Model {
                 id: 1,
                 owner_id: 1,
@@ -979,6 +1773,17 @@ mod test {
                 duration: 100,
                 is_express: true,
                 players_cnt: 3,
+                finished_at: None,
+                cancelled_at: None,
+                version: 0,
+                spectator_pid: None,
+                is_private: false,
+                invite_token: None,
+                starts_at: None,
+                fill_with_robots: false,
+                paused_at: None,
+                step_started_at: None,
+                imported_from: None,
                 created_at: now(),
                 updated_at: now(),
             },
@@ -1005,17 +1810,13 @@ mod test {
                         prop_fwheel: 1,
                         prop_bwheel: 1,
                         is_received: false,
+                        is_robot: false,
+                        is_resigned: false,
+                        nickname: None,
                         created_at: now(),
                         updated_at: now(),
                     },
-                    entity::user::Model {
-                        id: 1,
-                        steam_id: 111,
-                        login: Some(String::from("login111")),
-                        is_blocked: entity::user::UserBlocked::Nope,
-                        created_at: now(),
-                        updated_at: now(),
-                    },
+                    memory_user(1),
                 ),
                 (
                     entity::turn::Model {
@@ -1039,17 +1840,13 @@ mod test {
                         prop_fwheel: 1,
                         prop_bwheel: 1,
                         is_received: true,
+                        is_robot: false,
+                        is_resigned: false,
+                        nickname: None,
                         created_at: now(),
                         updated_at: now(),
                     },
-                    entity::user::Model {
-                        id: 2,
-                        steam_id: 222,
-                        login: Some(String::from("login222")),
-                        is_blocked: entity::user::UserBlocked::Nope,
-                        created_at: now(),
-                        updated_at: now(),
-                    },
+                    memory_user(2),
                 ),
                 (
                     entity::turn::Model {
@@ -1073,17 +1870,13 @@ mod test {
                         prop_fwheel: 1,
                         prop_bwheel: 1,
                         is_received: false,
+                        is_robot: false,
+                        is_resigned: false,
+                        nickname: None,
                         created_at: now(),
                         updated_at: now(),
                     },
-                    entity::user::Model {
-                        id: 3,
-                        steam_id: 333,
-                        login: Some(String::from("login333")),
-                        is_blocked: entity::user::UserBlocked::Nope,
-                        created_at: now(),
-                        updated_at: now(),
-                    },
+                    memory_user(3),
                 ),
             ],
             active_pid: None,
@@ -1096,7 +1889,7 @@ mod test {
     #[test]
     fn started_game_with_1_step_by_all_players_with_step2_by_2nd_player() {
         let manager = GameManager {
-            db: &DbConn::Disconnected,
+            storage: &MemoryStorage::default(),
             game: entity::game::Model {
                 id: 1,
                 owner_id: 1,
@@ -1109,6 +1902,17 @@ mod test {
                 duration: 100,
                 is_express: true,
                 players_cnt: 3,
+                finished_at: None,
+                cancelled_at: None,
+                version: 0,
+                spectator_pid: None,
+                is_private: false,
+                invite_token: None,
+                starts_at: None,
+                fill_with_robots: false,
+                paused_at: None,
+                step_started_at: None,
+                imported_from: None,
                 created_at: now(),
                 updated_at: now(),
             },
@@ -1135,17 +1939,13 @@ mod test {
                         prop_fwheel: 1,
                         prop_bwheel: 1,
                         is_received: false,
+                        is_robot: false,
+                        is_resigned: false,
+                        nickname: None,
                         created_at: now(),
                         updated_at: now(),
                     },
-                    entity::user::Model {
-                        id: 1,
-                        steam_id: 111,
-                        login: Some(String::from("login111")),
-                        is_blocked: entity::user::UserBlocked::Nope,
-                        created_at: now(),
-                        updated_at: now(),
-                    },
+                    memory_user(1),
                 ),
                 (
                     entity::turn::Model {
@@ -1169,17 +1969,13 @@ mod test {
                         prop_fwheel: 1,
                         prop_bwheel: 1,
                         is_received: true,
+                        is_robot: false,
+                        is_resigned: false,
+                        nickname: None,
                         created_at: now(),
                         updated_at: now(),
                     },
-                    entity::user::Model {
-                        id: 2,
-                        steam_id: 222,
-                        login: Some(String::from("login222")),
-                        is_blocked: entity::user::UserBlocked::Nope,
-                        created_at: now(),
-                        updated_at: now(),
-                    },
+                    memory_user(2),
                 ),
                 (
                     entity::turn::Model {
@@ -1203,17 +1999,13 @@ mod test {
                         prop_fwheel: 1,
                         prop_bwheel: 1,
                         is_received: false,
+                        is_robot: false,
+                        is_resigned: false,
+                        nickname: None,
                         created_at: now(),
                         updated_at: now(),
                     },
-                    entity::user::Model {
-                        id: 3,
-                        steam_id: 333,
-                        login: Some(String::from("login333")),
-                        is_blocked: entity::user::UserBlocked::Nope,
-                        created_at: now(),
-                        updated_at: now(),
-                    },
+                    memory_user(3),
                 ),
                 (
                     entity::turn::Model {
@@ -1237,17 +2029,13 @@ mod test {
                         prop_fwheel: 1,
                         prop_bwheel: 1,
                         is_received: false,
+                        is_robot: false,
+                        is_resigned: false,
+                        nickname: None,
                         created_at: now(),
                         updated_at: now(),
                     },
-                    entity::user::Model {
-                        id: 2,
-                        steam_id: 222,
-                        login: Some(String::from("login222")),
-                        is_blocked: entity::user::UserBlocked::Nope,
-                        created_at: now(),
-                        updated_at: now(),
-                    },
+                    memory_user(2),
                 ),
             ],
             active_pid: None,
@@ -1260,7 +2048,7 @@ mod test {
     #[test]
     fn started_game_with_received_1_step_all_players() {
         let manager = GameManager {
-            db: &DbConn::Disconnected,
+            storage: &MemoryStorage::default(),
             game: entity::game::Model {
                 id: 1,
                 owner_id: 1,
@@ -1273,6 +2061,17 @@ mod test {
                 duration: 100,
                 is_express: true,
                 players_cnt: 3,
+                finished_at: None,
+                cancelled_at: None,
+                version: 0,
+                spectator_pid: None,
+                is_private: false,
+                invite_token: None,
+                starts_at: None,
+                fill_with_robots: false,
+                paused_at: None,
+                step_started_at: None,
+                imported_from: None,
                 created_at: now(),
                 updated_at: now(),
             },
@@ -1299,17 +2098,13 @@ mod test {
                         prop_fwheel: 1,
                         prop_bwheel: 1,
                         is_received: true,
+                        is_robot: false,
+                        is_resigned: false,
+                        nickname: None,
                         created_at: now(),
                         updated_at: now(),
                     },
-                    entity::user::Model {
-                        id: 1,
-                        steam_id: 111,
-                        login: Some(String::from("login111")),
-                        is_blocked: entity::user::UserBlocked::Nope,
-                        created_at: now(),
-                        updated_at: now(),
-                    },
+                    memory_user(1),
                 ),
                 (
                     entity::turn::Model {
@@ -1333,17 +2128,13 @@ mod test {
                         prop_fwheel: 1,
                         prop_bwheel: 1,
                         is_received: true,
+                        is_robot: false,
+                        is_resigned: false,
+                        nickname: None,
                         created_at: now(),
                         updated_at: now(),
                     },
-                    entity::user::Model {
-                        id: 2,
-                        steam_id: 222,
-                        login: Some(String::from("login222")),
-                        is_blocked: entity::user::UserBlocked::Nope,
-                        created_at: now(),
-                        updated_at: now(),
-                    },
+                    memory_user(2),
                 ),
                 (
                     entity::turn::Model {
@@ -1367,17 +2158,13 @@ mod test {
                         prop_fwheel: 1,
                         prop_bwheel: 1,
                         is_received: true,
+                        is_robot: false,
+                        is_resigned: false,
+                        nickname: None,
                         created_at: now(),
                         updated_at: now(),
                     },
-                    entity::user::Model {
-                        id: 3,
-                        steam_id: 333,
-                        login: Some(String::from("login333")),
-                        is_blocked: entity::user::UserBlocked::Nope,
-                        created_at: now(),
-                        updated_at: now(),
-                    },
+                    memory_user(3),
                 ),
             ],
             active_pid: None,
@@ -1390,7 +2177,7 @@ mod test {
     #[test]
     fn started_game_with_received_1_step_by_all_step_2_by_player_2() {
         let manager = GameManager {
-            db: &DbConn::Disconnected,
+            storage: &MemoryStorage::default(),
             game: entity::game::Model {
                 id: 1,
                 owner_id: 1,
@@ -1403,6 +2190,17 @@ mod test {
                 duration: 100,
                 is_express: true,
                 players_cnt: 3,
+                finished_at: None,
+                cancelled_at: None,
+                version: 0,
+                spectator_pid: None,
+                is_private: false,
+                invite_token: None,
+                starts_at: None,
+                fill_with_robots: false,
+                paused_at: None,
+                step_started_at: None,
+                imported_from: None,
                 created_at: now(),
                 updated_at: now(),
             },
@@ -1429,17 +2227,13 @@ mod test {
                         prop_fwheel: 1,
                         prop_bwheel: 1,
                         is_received: true,
+                        is_robot: false,
+                        is_resigned: false,
+                        nickname: None,
                         created_at: now(),
                         updated_at: now(),
                     },
-                    entity::user::Model {
-                        id: 1,
-                        steam_id: 111,
-                        login: Some(String::from("login111")),
-                        is_blocked: entity::user::UserBlocked::Nope,
-                        created_at: now(),
-                        updated_at: now(),
-                    },
+                    memory_user(1),
                 ),
                 (
                     entity::turn::Model {
@@ -1463,17 +2257,13 @@ mod test {
                         prop_fwheel: 1,
                         prop_bwheel: 1,
                         is_received: true,
+                        is_robot: false,
+                        is_resigned: false,
+                        nickname: None,
                         created_at: now(),
                         updated_at: now(),
                     },
-                    entity::user::Model {
-                        id: 2,
-                        steam_id: 222,
-                        login: Some(String::from("login222")),
-                        is_blocked: entity::user::UserBlocked::Nope,
-                        created_at: now(),
-                        updated_at: now(),
-                    },
+                    memory_user(2),
                 ),
                 (
                     entity::turn::Model {
@@ -1497,17 +2287,13 @@ mod test {
                         prop_fwheel: 1,
                         prop_bwheel: 1,
                         is_received: true,
+                        is_robot: false,
+                        is_resigned: false,
+                        nickname: None,
                         created_at: now(),
                         updated_at: now(),
                     },
-                    entity::user::Model {
-                        id: 3,
-                        steam_id: 333,
-                        login: Some(String::from("login333")),
-                        is_blocked: entity::user::UserBlocked::Nope,
-                        created_at: now(),
-                        updated_at: now(),
-                    },
+                    memory_user(3),
                 ),
                 (
                     entity::turn::Model {
@@ -1531,17 +2317,13 @@ mod test {
                         prop_fwheel: 1,
                         prop_bwheel: 1,
                         is_received: false,
+                        is_robot: false,
+                        is_resigned: false,
+                        nickname: None,
                         created_at: now(),
                         updated_at: now(),
                     },
-                    entity::user::Model {
-                        id: 2,
-                        steam_id: 222,
-                        login: Some(String::from("login222")),
-                        is_blocked: entity::user::UserBlocked::Nope,
-                        created_at: now(),
-                        updated_at: now(),
-                    },
+                    memory_user(2),
                 ),
             ],
             active_pid: None,
@@ -1550,4 +2332,584 @@ mod test {
         assert_eq!(manager.status(), GameStatus::Started);
         assert_eq!(manager.move_cnt(), 1);
     }
+
+    fn memory_user(id: u32) -> entity::user::Model {
+        entity::user::Model {
+            id,
+            steam_id: id as i64 * 111,
+            login: Some(format!("login{}", id * 111)),
+            is_blocked: entity::user::UserBlocked::Nope,
+            prop_pers: 1,
+            prop_car: 1,
+            prop_fwheel: 1,
+            prop_bwheel: 1,
+            created_at: now(),
+            updated_at: now(),
+        }
+    }
+
+    /// игра на `players_cnt` игроков, в которую вошли пользователи с id от 1 до `joined`
+    fn memory_storage(players_cnt: u32, joined: u32) -> MemoryStorage {
+        let storage = MemoryStorage::default();
+
+        storage.add_game(entity::game::Model {
+            id: 1,
+            owner_id: 1,
+            world_id: 0,
+            track_id: 0,
+            rnd: 123,
+            game_type: entity::game::GameType::Winner,
+            laps: 1,
+            seeds: 10,
+            duration: 100,
+            is_express: true,
+            players_cnt,
+            finished_at: None,
+            cancelled_at: None,
+            version: 0,
+            spectator_pid: None,
+            is_private: false,
+            invite_token: None,
+            starts_at: None,
+            fill_with_robots: false,
+            paused_at: None,
+            step_started_at: None,
+            imported_from: None,
+            created_at: now(),
+            updated_at: now(),
+        });
+
+        for id in 1..=5 {
+            storage.add_user(memory_user(id));
+        }
+
+        for id in 1..=joined {
+            storage.add_turn(entity::turn::Model {
+                id,
+                ..entity::turn::Model::new(1, id, id - 1, Loadout::default())
+            });
+        }
+
+        storage
+    }
+
+    #[tokio::test]
+    async fn memory_storage_join_and_leave() {
+        let storage = memory_storage(4, 1);
+
+        for id in 2..=4 {
+            let mut manager = GameManager::lock_game(&storage, 1).await.unwrap();
+            manager.join(&memory_user(id)).await.unwrap();
+        }
+
+        let mut manager = GameManager::lock_game(&storage, 1).await.unwrap();
+        assert_eq!(manager.status(), GameStatus::Started);
+        assert_eq!(manager.game.version, 4);
+        assert!(matches!(
+            manager.leave(2).await,
+            Err(GameManagerError::GameNotOpen(1))
+        ));
+
+        let storage = memory_storage(4, 3);
+
+        let mut manager = GameManager::lock_game(&storage, 1).await.unwrap();
+        manager.leave(2).await.unwrap();
+
+        let manager = GameManager::load_game(&storage, 1).await.unwrap();
+        let mut players = manager
+            .turns
+            .iter()
+            .map(|(t, u)| (t.player_number, u.id))
+            .collect::<Vec<_>>();
+        players.sort();
+        assert_eq!(players, vec![(0, 1), (1, 3)]);
+    }
+
+    #[tokio::test]
+    async fn leave_keeps_state_on_storage_error() {
+        let storage = memory_storage(4, 3);
+
+        let mut manager = GameManager::lock_game(&storage, 1).await.unwrap();
+        // ход третьего игрока пропал из хранилища: сдвиг его номера не удастся
+        storage.delete_turn(3).await.unwrap();
+        assert!(manager.leave(2).await.is_err());
+
+        let players = manager
+            .turns
+            .iter()
+            .map(|(t, u)| (t.player_number, u.id))
+            .collect::<Vec<_>>();
+        assert_eq!(players, vec![(0, 1), (1, 2), (2, 3)]);
+    }
+
+    #[tokio::test]
+    async fn memory_storage_apply_step() {
+        use crate::data::KdlabCodec;
+
+        let storage = memory_storage(2, 2);
+        let mut packet = Packet::decode(STEP_PACKET).unwrap();
+
+        let mut manager = GameManager::lock_game(&storage, 1).await.unwrap();
+        manager.set_pid(0).unwrap();
+        assert_eq!(manager.move_cnt(), 0);
+        assert_eq!(
+            manager.apply_step(&mut packet).await.unwrap(),
+            StepOutcome::Updated
+        );
+
+        let mut manager = GameManager::lock_game(&storage, 1).await.unwrap();
+        manager.set_pid(0).unwrap();
+        assert_eq!(
+            manager.apply_step(&mut packet).await.unwrap(),
+            StepOutcome::Duplicate
+        );
+
+        let (turn, _) = manager
+            .turns
+            .iter()
+            .find(|(t, _)| t.player_number == 0)
+            .unwrap();
+        assert_eq!(turn.user_seeds_cnt, 2);
+        assert_eq!(turn.seeds.as_deref(), Some("165#741#51#-1#465#427#51#-1"));
+    }
+
+    /// ход первого игрока: два семени
+    const STEP_PACKET: &str = "KDLAB;104;3;1;0;0;0;password;0;0;12711;A;1;100;10;0;2;1;Y;;0;;;1;1;0;N;0;0;0;0;0;0;0;2;165#741#51#-1#465#427#51#-1;BITRIX";
+
+    /// меняет игру в хранилище в обход менеджера
+    async fn edit_game(storage: &MemoryStorage, edit: impl FnOnce(&mut entity::game::Model)) {
+        let mut game = GameManager::load_game(storage, 1).await.unwrap().game;
+        edit(&mut game);
+        storage.update_game(game).await.unwrap();
+    }
+
+    /// все, кто ещё не сходил в открытом шаге, делают пустой ход
+    async fn skip_step(storage: &MemoryStorage) {
+        let manager = GameManager::load_game(storage, 1).await.unwrap();
+        let current_step = manager.move_cnt() + 1;
+        for (mut turn, _) in manager.turns {
+            if turn.step_number == current_step && turn.seeds.is_none() {
+                turn.seeds = Some(String::new());
+                storage.update_turn(turn).await.unwrap();
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn spectator_pid_is_given_while_spectators_are_allowed() {
+        let storage = memory_storage(2, 2);
+
+        let mut manager = GameManager::lock_game(&storage, 1).await.unwrap();
+        assert!(manager.set_pid(*SPECTATOR_PIDS.start()).is_err());
+
+        manager.set_spectators(true).await.unwrap();
+        let spectator_pid = manager.game.spectator_pid.unwrap();
+        assert!(SPECTATOR_PIDS.contains(&spectator_pid));
+        manager.set_pid(spectator_pid).unwrap();
+        assert!(manager.is_spectator());
+        assert_eq!(
+            manager
+                .get_info(PacketType::OG_CONTROL_PACKET)
+                .packet_owner_pid,
+            spectator_pid
+        );
+
+        manager.set_spectators(false).await.unwrap();
+        assert!(manager.set_pid(spectator_pid).is_err());
+    }
+
+    #[tokio::test]
+    async fn spectator_cannot_move() {
+        use crate::data::KdlabCodec;
+
+        let storage = memory_storage(2, 2);
+        let mut manager = GameManager::lock_game(&storage, 1).await.unwrap();
+        manager.set_spectators(true).await.unwrap();
+        let spectator_pid = manager.game.spectator_pid.unwrap();
+        manager.set_pid(spectator_pid).unwrap();
+
+        let mut packet = Packet::decode(STEP_PACKET).unwrap();
+        packet.packet_owner_pid = spectator_pid;
+        assert!(matches!(
+            manager.apply_step(&mut packet).await,
+            Err(GameManagerError::SpectatorReadOnly(pid)) if pid == spectator_pid
+        ));
+    }
+
+    #[tokio::test]
+    async fn rematch_needs_finished_game() {
+        let storage = memory_storage(2, 2);
+        let manager = GameManager::load_game(&storage, 1).await.unwrap();
+
+        assert!(matches!(
+            manager.rematch(1),
+            Err(GameManagerError::GameNotFinished(1))
+        ));
+    }
+
+    #[tokio::test]
+    async fn rematch_repeats_track_for_new_owner() {
+        let storage = memory_storage(3, 2);
+        edit_game(&storage, |game| game.finished_at = Some(now())).await;
+        let finished = GameManager::load_game(&storage, 1).await.unwrap();
+
+        let game = finished.rematch(2).unwrap();
+        assert_eq!(game.owner_id, 2);
+        assert_eq!(game.players_cnt, 2);
+        assert_eq!(
+            (game.world_id, game.track_id),
+            (finished.game.world_id, finished.game.track_id)
+        );
+    }
+
+    #[tokio::test]
+    async fn last_loadouts_are_taken_from_last_turns() {
+        let storage = memory_storage(2, 2);
+        let loadout = Loadout {
+            pers: 2,
+            car: 3,
+            fwheel: 4,
+            bwheel: 5,
+        };
+        storage.add_turn(entity::turn::Model {
+            id: 3,
+            step_number: 2,
+            ..entity::turn::Model::new(1, 2, 1, loadout)
+        });
+
+        let manager = GameManager::load_game(&storage, 1).await.unwrap();
+        let participants = manager
+            .last_loadouts()
+            .into_iter()
+            .map(|(u, l)| (u.id, l))
+            .collect::<Vec<_>>();
+        assert_eq!(participants, [(1, Loadout::default()), (2, loadout)]);
+    }
+
+    #[tokio::test]
+    async fn rematch_drops_robot_seats() {
+        let storage = memory_storage(4, 2);
+        for (id, player_number) in [(3, 2), (4, 3)] {
+            storage.add_turn(entity::turn::Model {
+                id,
+                is_robot: true,
+                ..entity::turn::Model::new(1, 5, player_number, Loadout::default())
+            });
+        }
+        edit_game(&storage, |game| game.finished_at = Some(now())).await;
+
+        let manager = GameManager::load_game(&storage, 1).await.unwrap();
+        let participants = manager
+            .last_loadouts()
+            .iter()
+            .map(|(u, _)| u.id)
+            .collect::<Vec<_>>();
+        assert_eq!(participants, [1, 2]);
+        assert_eq!(manager.rematch(1).unwrap().players_cnt, 2);
+    }
+
+    #[tokio::test]
+    async fn public_game_needs_no_invite() {
+        let storage = memory_storage(3, 1);
+
+        let mut manager = GameManager::lock_game(&storage, 1).await.unwrap();
+        assert!(manager.check_invite(None).is_ok());
+        assert!(matches!(
+            manager.set_invite_token(true).await,
+            Err(GameManagerError::GameNotOpen(1))
+        ));
+    }
+
+    #[tokio::test]
+    async fn private_game_checks_invite_token() {
+        let storage = memory_storage(3, 1);
+        edit_game(&storage, |game| game.is_private = true).await;
+
+        let mut manager = GameManager::lock_game(&storage, 1).await.unwrap();
+        manager.set_invite_token(true).await.unwrap();
+        let token = manager.game.invite_token.clone().unwrap();
+        assert_eq!(token.len(), INVITE_TOKEN_LEN);
+
+        assert!(matches!(
+            manager.check_invite(None),
+            Err(GameManagerError::InviteRequired(1))
+        ));
+        assert!(matches!(
+            manager.check_invite(Some("wrong")),
+            Err(GameManagerError::InvalidInviteToken(1))
+        ));
+        assert!(manager.check_invite(Some(&token)).is_ok());
+    }
+
+    #[tokio::test]
+    async fn invite_token_is_revoked() {
+        let storage = memory_storage(3, 1);
+        edit_game(&storage, |game| game.is_private = true).await;
+
+        let mut manager = GameManager::lock_game(&storage, 1).await.unwrap();
+        manager.set_invite_token(true).await.unwrap();
+        let token = manager.game.invite_token.clone().unwrap();
+
+        // новая ссылка заменяет старую, отозванная не действует вовсе
+        manager.set_invite_token(true).await.unwrap();
+        assert!(manager.check_invite(Some(&token)).is_err());
+        manager.set_invite_token(false).await.unwrap();
+        assert!(manager.game.invite_token.is_none());
+        assert!(matches!(
+            manager.check_invite(Some(&token)),
+            Err(GameManagerError::InvalidInviteToken(1))
+        ));
+    }
+
+    #[tokio::test]
+    async fn scheduled_game_stays_open_until_start_time() {
+        let storage = memory_storage(3, 1);
+        edit_game(&storage, |game| {
+            game.starts_at = Some(now() + ::chrono::Duration::hours(1))
+        })
+        .await;
+
+        let mut manager = GameManager::lock_game(&storage, 1).await.unwrap();
+        for id in 2..=3 {
+            manager.join(&memory_user(id)).await.unwrap();
+        }
+        assert_eq!(manager.status(), GameStatus::Open);
+        assert!(manager.game.step_started_at.is_none());
+        assert!(matches!(
+            manager.join(&memory_user(4)).await,
+            Err(GameManagerError::GameNotOpen(1))
+        ));
+    }
+
+    #[tokio::test]
+    async fn full_scheduled_game_opens_first_step_when_due() {
+        let storage = memory_storage(2, 2);
+        edit_game(&storage, |game| {
+            game.starts_at = Some(now() - ::chrono::Duration::minutes(1))
+        })
+        .await;
+
+        let mut manager = GameManager::lock_game(&storage, 1).await.unwrap();
+        assert_eq!(manager.status(), GameStatus::Started);
+        assert!(manager.open_scheduled().await.unwrap());
+        assert!(manager.game.step_started_at.is_some());
+        assert!(!manager.open_scheduled().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn start_needs_two_players() {
+        let storage = memory_storage(3, 1);
+
+        let mut manager = GameManager::lock_game(&storage, 1).await.unwrap();
+        assert!(matches!(
+            manager.start().await,
+            Err(GameManagerError::NotEnoughPlayers(1))
+        ));
+    }
+
+    #[tokio::test]
+    async fn start_shrinks_seats_to_joined_players() {
+        let storage = memory_storage(4, 3);
+        edit_game(&storage, |game| {
+            game.starts_at = Some(now() + ::chrono::Duration::hours(1))
+        })
+        .await;
+
+        let mut manager = GameManager::lock_game(&storage, 1).await.unwrap();
+        manager.start().await.unwrap();
+        assert_eq!(manager.status(), GameStatus::Started);
+        assert_eq!(manager.game.players_cnt, 3);
+        assert!(manager.game.starts_at <= Some(now()));
+        assert!(manager.game.step_started_at.is_some());
+        assert!(matches!(
+            manager.start().await,
+            Err(GameManagerError::GameNotOpen(1))
+        ));
+        assert!(matches!(
+            manager.join(&memory_user(4)).await,
+            Err(GameManagerError::GameNotOpen(1))
+        ));
+    }
+
+    #[tokio::test]
+    async fn early_started_step_waits_only_for_joined_players() {
+        let storage = memory_storage(4, 3);
+        let mut manager = GameManager::lock_game(&storage, 1).await.unwrap();
+        manager.start().await.unwrap();
+
+        manager.set_pid(0).unwrap();
+        let info = manager.get_info(PacketType::OG_CONTROL_PACKET);
+        assert_eq!(
+            info.players
+                .iter()
+                .map(|p| (p.uid, p.nickname.as_str()))
+                .collect::<Vec<_>>(),
+            vec![(0, "login111"), (1, "login222"), (2, "login333")]
+        );
+
+        skip_step(&storage).await;
+        let manager = GameManager::load_game(&storage, 1).await.unwrap();
+        assert_eq!(manager.move_cnt(), 1);
+    }
+
+    #[tokio::test]
+    async fn robots_take_free_seats() {
+        let storage = memory_storage(3, 1);
+
+        let mut manager = GameManager::lock_game(&storage, 1).await.unwrap();
+        manager.add_robots(&memory_user(5)).await.unwrap();
+        assert_eq!(manager.status(), GameStatus::Started);
+        assert_eq!(manager.turns.len(), 3);
+        assert!(manager.turns[1..].iter().all(|(t, _)| t.is_robot));
+        assert!(manager.get_info(PacketType::OG_CONTROL_PACKET).players[1].is_robot);
+        assert_eq!(manager.waiting_players(), vec![1]);
+    }
+
+    #[tokio::test]
+    async fn robots_move_after_step_closes() {
+        let storage = memory_storage(3, 1);
+        let mut manager = GameManager::lock_game(&storage, 1).await.unwrap();
+        manager.add_robots(&memory_user(5)).await.unwrap();
+
+        // в первом шаге роботы уже сходили
+        manager.auto_moves().await.unwrap();
+        assert_eq!(manager.turns.len(), 3);
+
+        skip_step(&storage).await;
+        let mut manager = GameManager::lock_game(&storage, 1).await.unwrap();
+        assert_eq!(manager.move_cnt(), 1);
+        manager.auto_moves().await.unwrap();
+        let robot_steps = manager
+            .turns
+            .iter()
+            .filter(|(t, _)| t.is_robot && t.step_number == 2)
+            .count();
+        assert_eq!(robot_steps, 2);
+    }
+
+    #[tokio::test]
+    async fn resign_only_once_and_only_joined() {
+        let storage = memory_storage(3, 3);
+
+        let mut manager = GameManager::lock_game(&storage, 1).await.unwrap();
+        manager.resign(2).await.unwrap();
+        assert!(matches!(
+            manager.resign(2).await,
+            Err(GameManagerError::PlayerResigned(1))
+        ));
+        assert!(matches!(
+            manager.resign(5).await,
+            Err(GameManagerError::NotJoined(5))
+        ));
+    }
+
+    #[tokio::test]
+    async fn resigned_player_cannot_move() {
+        use crate::data::KdlabCodec;
+
+        let storage = memory_storage(3, 3);
+        let mut manager = GameManager::lock_game(&storage, 1).await.unwrap();
+        manager.resign(2).await.unwrap();
+
+        let mut packet = Packet::decode(STEP_PACKET).unwrap();
+        packet.packet_owner_pid = 1;
+        manager.set_pid(1).unwrap();
+        assert!(matches!(
+            manager.apply_step(&mut packet).await,
+            Err(GameManagerError::PlayerResigned(1))
+        ));
+    }
+
+    #[tokio::test]
+    async fn resigned_player_skips_next_steps() {
+        let storage = memory_storage(3, 3);
+        let mut manager = GameManager::lock_game(&storage, 1).await.unwrap();
+        manager.resign(2).await.unwrap();
+
+        skip_step(&storage).await;
+        let mut manager = GameManager::lock_game(&storage, 1).await.unwrap();
+        assert_eq!(manager.move_cnt(), 1);
+        manager.auto_moves().await.unwrap();
+        let filler = manager
+            .turns
+            .iter()
+            .find(|(t, _)| t.step_number == 2)
+            .map(|(t, _)| (t.player_number, t.is_resigned, t.seeds.clone()));
+        assert_eq!(filler, Some((1, true, Some(String::new()))));
+        assert_eq!(manager.waiting_players(), vec![1, 3]);
+    }
+
+    #[tokio::test]
+    async fn game_finishes_when_everyone_resigned() {
+        let storage = memory_storage(3, 3);
+        let mut manager = GameManager::lock_game(&storage, 1).await.unwrap();
+
+        manager.resign(1).await.unwrap();
+        manager.resign(2).await.unwrap();
+        assert_eq!(manager.status(), GameStatus::Started);
+        manager.resign(3).await.unwrap();
+        assert_eq!(manager.status(), GameStatus::Finished);
+    }
+
+    #[tokio::test]
+    async fn express_game_has_no_deadline() {
+        let storage = memory_storage(2, 2);
+
+        let mut manager = GameManager::lock_game(&storage, 1).await.unwrap();
+        manager.set_pid(0).unwrap();
+        assert!(manager.get_info(PacketType::OG_CONTROL_PACKET).is_express);
+        assert_eq!(manager.turn_duration(), None);
+        assert_eq!(manager.time_left(now()), None);
+    }
+
+    #[tokio::test]
+    async fn correspondence_turn_time_runs_from_step_start() {
+        let storage = memory_storage(2, 2);
+        let step_started_at = now() - ::chrono::Duration::hours(10);
+        edit_game(&storage, |game| {
+            game.is_express = false;
+            game.duration = 24;
+            game.step_started_at = Some(step_started_at);
+        })
+        .await;
+
+        let mut manager = GameManager::lock_game(&storage, 1).await.unwrap();
+        manager.set_pid(0).unwrap();
+        assert!(!manager.get_info(PacketType::OG_CONTROL_PACKET).is_express);
+
+        let at = step_started_at + ::chrono::Duration::hours(20);
+        assert_eq!(manager.time_left(at), Some(::chrono::Duration::hours(4)));
+        let at = step_started_at + ::chrono::Duration::hours(30);
+        assert_eq!(manager.time_left(at), Some(::chrono::Duration::zero()));
+    }
+
+    #[tokio::test]
+    async fn correspondence_turn_time_stops_on_pause() {
+        let storage = memory_storage(2, 2);
+        let step_started_at = now() - ::chrono::Duration::hours(10);
+        edit_game(&storage, |game| {
+            game.is_express = false;
+            game.duration = 24;
+            game.step_started_at = Some(step_started_at);
+            game.paused_at = Some(step_started_at + ::chrono::Duration::hours(12));
+        })
+        .await;
+
+        let manager = GameManager::load_game(&storage, 1).await.unwrap();
+        let at = step_started_at + ::chrono::Duration::hours(30);
+        assert_eq!(manager.time_left(at), Some(::chrono::Duration::hours(12)));
+    }
+
+    #[tokio::test]
+    async fn waiting_players_are_those_without_a_move() {
+        let storage = memory_storage(2, 2);
+        let manager = GameManager::load_game(&storage, 1).await.unwrap();
+        assert_eq!(manager.waiting_players(), vec![1, 2]);
+
+        let mut turn = manager.turns[1].0.clone();
+        turn.seeds = Some(String::new());
+        storage.update_turn(turn).await.unwrap();
+        let manager = GameManager::load_game(&storage, 1).await.unwrap();
+        assert_eq!(manager.waiting_players(), vec![1]);
+    }
 }
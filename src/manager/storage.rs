@@ -0,0 +1,220 @@
+use std::{collections::BTreeMap, sync::Mutex};
+
+use ::async_trait::async_trait;
+use ::sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ActiveValue, ColumnTrait, ConnectionTrait, DbErr,
+    EntityTrait, IntoActiveModel, QueryFilter,
+};
+
+use entity::{game, turn, user};
+
+/// Хранилище игр и ходов, с которым работает [`GameManager`](super::GameManager).
+///
+/// Менеджер не знает, где лежат данные: в базе через sea-orm или в памяти.
+#[async_trait]
+pub trait GameStorage: Send + Sync {
+    async fn find_game(&self, gmid: u32) -> Result<Option<game::Model>, DbErr>;
+
+    /// все ходы игры вместе с игроками, которые их сделали
+    async fn find_turns(&self, gmid: u32) -> Result<Vec<(turn::Model, user::Model)>, DbErr>;
+
+    /// Захватывает игру на запись, увеличивая её `version`.
+    /// Возвращает `false`, если игры нет.
+    async fn lock_game(&self, gmid: u32) -> Result<bool, DbErr>;
+
+    /// `id` и даты создания новой игры назначает хранилище
+    async fn insert_game(&self, game: game::Model) -> Result<game::Model, DbErr>;
+
+    async fn update_game(&self, game: game::Model) -> Result<game::Model, DbErr>;
+
+    /// `id` и даты создания нового хода назначает хранилище
+    async fn insert_turn(&self, turn: turn::Model) -> Result<turn::Model, DbErr>;
+
+    async fn update_turn(&self, turn: turn::Model) -> Result<turn::Model, DbErr>;
+
+    async fn delete_turn(&self, turn_id: u32) -> Result<(), DbErr>;
+}
+
+/// Хранилище в базе данных: подходит как соединение, так и транзакция.
+#[async_trait]
+impl<C> GameStorage for C
+where
+    C: ConnectionTrait + Send,
+{
+    async fn find_game(&self, gmid: u32) -> Result<Option<game::Model>, DbErr> {
+        game::Entity::find_by_id(gmid).one(self).await
+    }
+
+    async fn find_turns(&self, gmid: u32) -> Result<Vec<(turn::Model, user::Model)>, DbErr> {
+        let turns = turn::Entity::find()
+            .filter(turn::Column::GameId.eq(gmid))
+            .find_also_related(user::Entity)
+            .all(self)
+            .await?
+            .into_iter()
+            .map(|(turn, user)| (turn, user.unwrap()))
+            .collect();
+
+        Ok(turns)
+    }
+
+    async fn lock_game(&self, gmid: u32) -> Result<bool, DbErr> {
+        let res = game::Entity::update_many()
+            .col_expr(
+                game::Column::Version,
+                Expr::col(game::Column::Version).add(1),
+            )
+            .filter(game::Column::Id.eq(gmid))
+            .exec(self)
+            .await?;
+
+        Ok(res.rows_affected > 0)
+    }
+
+    async fn insert_game(&self, game: game::Model) -> Result<game::Model, DbErr> {
+        // все поля помечаются изменёнными, чтобы их проверил `before_save`
+        let mut game = game.into_active_model().reset_all();
+        game.id = ActiveValue::NotSet;
+        game.created_at = ActiveValue::NotSet;
+        game.updated_at = ActiveValue::NotSet;
+
+        game.insert(self).await
+    }
+
+    async fn update_game(&self, game: game::Model) -> Result<game::Model, DbErr> {
+        game.into_active_model().reset_all().update(self).await
+    }
+
+    async fn insert_turn(&self, turn: turn::Model) -> Result<turn::Model, DbErr> {
+        let mut turn = turn.into_active_model();
+        turn.id = ActiveValue::NotSet;
+        turn.created_at = ActiveValue::NotSet;
+        turn.updated_at = ActiveValue::NotSet;
+
+        turn.insert(self).await
+    }
+
+    async fn update_turn(&self, turn: turn::Model) -> Result<turn::Model, DbErr> {
+        turn.into_active_model().reset_all().update(self).await
+    }
+
+    async fn delete_turn(&self, turn_id: u32) -> Result<(), DbErr> {
+        turn::Entity::delete_by_id(turn_id).exec(self).await?;
+
+        Ok(())
+    }
+}
+
+/// Хранилище в памяти: для тестов и замеров игровой логики без базы данных.
+///
+/// Транзакций нет, каждая операция атомарна сама по себе.
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    state: Mutex<MemoryState>,
+}
+
+#[derive(Debug, Default)]
+struct MemoryState {
+    games: BTreeMap<u32, game::Model>,
+    turns: BTreeMap<u32, turn::Model>,
+    users: BTreeMap<u32, user::Model>,
+}
+
+impl MemoryStorage {
+    pub fn add_user(&self, user: user::Model) {
+        self.state.lock().unwrap().users.insert(user.id, user);
+    }
+
+    pub fn add_game(&self, game: game::Model) {
+        self.state.lock().unwrap().games.insert(game.id, game);
+    }
+
+    /// добавляет ход как есть, вместе с его `id`
+    pub fn add_turn(&self, turn: turn::Model) {
+        self.state.lock().unwrap().turns.insert(turn.id, turn);
+    }
+}
+
+#[async_trait]
+impl GameStorage for MemoryStorage {
+    async fn find_game(&self, gmid: u32) -> Result<Option<game::Model>, DbErr> {
+        Ok(self.state.lock().unwrap().games.get(&gmid).cloned())
+    }
+
+    async fn find_turns(&self, gmid: u32) -> Result<Vec<(turn::Model, user::Model)>, DbErr> {
+        let state = self.state.lock().unwrap();
+
+        let turns = state
+            .turns
+            .values()
+            .filter(|t| t.game_id == gmid)
+            .map(|t| (t.clone(), state.users[&t.user_id].clone()))
+            .collect();
+
+        Ok(turns)
+    }
+
+    async fn lock_game(&self, gmid: u32) -> Result<bool, DbErr> {
+        let mut state = self.state.lock().unwrap();
+
+        Ok(match state.games.get_mut(&gmid) {
+            Some(game) => {
+                game.version += 1;
+                true
+            }
+            None => false,
+        })
+    }
+
+    async fn insert_game(&self, mut game: game::Model) -> Result<game::Model, DbErr> {
+        let mut state = self.state.lock().unwrap();
+
+        let now = ::chrono::Utc::now().naive_utc();
+        game.id = state.games.keys().max().map_or(1, |id| id + 1);
+        game.created_at = now;
+        game.updated_at = now;
+        state.games.insert(game.id, game.clone());
+
+        Ok(game)
+    }
+
+    async fn update_game(&self, game: game::Model) -> Result<game::Model, DbErr> {
+        let mut state = self.state.lock().unwrap();
+
+        match state.games.get_mut(&game.id) {
+            Some(stored) => *stored = game.clone(),
+            None => Err(DbErr::RecordNotUpdated)?,
+        }
+
+        Ok(game)
+    }
+
+    async fn insert_turn(&self, mut turn: turn::Model) -> Result<turn::Model, DbErr> {
+        let mut state = self.state.lock().unwrap();
+
+        let now = ::chrono::Utc::now().naive_utc();
+        turn.id = state.turns.keys().max().map_or(1, |id| id + 1);
+        turn.created_at = now;
+        turn.updated_at = now;
+        state.turns.insert(turn.id, turn.clone());
+
+        Ok(turn)
+    }
+
+    async fn update_turn(&self, turn: turn::Model) -> Result<turn::Model, DbErr> {
+        let mut state = self.state.lock().unwrap();
+
+        match state.turns.get_mut(&turn.id) {
+            Some(stored) => *stored = turn.clone(),
+            None => Err(DbErr::RecordNotUpdated)?,
+        }
+
+        Ok(turn)
+    }
+
+    async fn delete_turn(&self, turn_id: u32) -> Result<(), DbErr> {
+        self.state.lock().unwrap().turns.remove(&turn_id);
+
+        Ok(())
+    }
+}
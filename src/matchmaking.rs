@@ -0,0 +1,383 @@
+//! Очередь подбора соперников: игра создаётся, как только набирается
+//! нужное число игроков с одинаковыми настройками.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use ::chrono::NaiveDateTime;
+use ::sea_orm::{ColumnTrait, ConnectionTrait, DbErr, EntityTrait, QueryFilter, TransactionTrait};
+use ::serde::{Deserialize, Serialize};
+
+use crate::{
+    events::GameEvent,
+    manager::{GameManager, GameManagerError, GameStatus},
+    state::Registry,
+    tournament::race_points,
+};
+use entity::{
+    game::{self, GameType, World},
+    turn, user,
+};
+
+/// рейтинг игрока без законченных игр
+pub const DEFAULT_RATING: u32 = 50;
+
+/// Рейтинг игрока: доля набранных очков от возможных в законченных играх, в процентах.
+///
+/// Очки за место считаются так же, как в турнирах.
+pub async fn rating<C: ConnectionTrait>(db: &C, user_id: u32) -> Result<u32, DbErr> {
+    let turns = turn::Entity::find()
+        .filter(turn::Column::UserId.eq(user_id))
+        .find_also_related(game::Entity)
+        .filter(game::Column::FinishedAt.is_not_null())
+        // в загруженных из записей играх все места занимает загрузивший
+        .filter(game::Column::ImportedFrom.is_null())
+        .all(db)
+        .await?;
+
+    // итог игры — в последнем ходе игрока
+    let mut last = HashMap::<u32, (turn::Model, game::Model)>::new();
+    for (turn, game) in turns {
+        let Some(game) = game else {
+            continue;
+        };
+        match last.get(&turn.game_id) {
+            Some((t, _)) if t.step_number >= turn.step_number => {}
+            _ => {
+                last.insert(turn.game_id, (turn, game));
+            }
+        }
+    }
+
+    let (points, max) = last.values().fold((0, 0), |(points, max), (turn, game)| {
+        (
+            points + race_points(game.players_cnt, turn.rank),
+            max + game.players_cnt.saturating_sub(1),
+        )
+    });
+
+    Ok(match max {
+        0 => DEFAULT_RATING,
+        max => points * 100 / max,
+    })
+}
+
+/// настройки игры, которую ищет игрок
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Preferences {
+    pub players_cnt: u32,
+    pub game_type: GameType,
+    pub laps: u32,
+    pub seeds: u32,
+    pub duration: u32,
+    /// допустимая разница рейтингов соперников, `None` — любая
+    pub rating_range: Option<u32>,
+}
+
+impl Preferences {
+    fn same_game(&self, other: &Self) -> bool {
+        (
+            self.players_cnt,
+            self.game_type,
+            self.laps,
+            self.seeds,
+            self.duration,
+        ) == (
+            other.players_cnt,
+            other.game_type,
+            other.laps,
+            other.seeds,
+            other.duration,
+        )
+    }
+}
+
+/// заявка игрока в очереди
+#[derive(Debug, Clone)]
+pub struct Ticket {
+    pub user: user::Model,
+    pub prefs: Preferences,
+    pub rating: u32,
+    pub queued_at: NaiveDateTime,
+}
+
+impl Ticket {
+    pub fn new(user: user::Model, prefs: Preferences, rating: u32) -> Self {
+        Self {
+            user,
+            prefs,
+            rating,
+            queued_at: ::chrono::Utc::now().naive_utc(),
+        }
+    }
+
+    fn accepts(&self, other: &Self) -> bool {
+        self.prefs
+            .rating_range
+            .is_none_or(|range| self.rating.abs_diff(other.rating) <= range)
+    }
+
+    /// могут ли игроки оказаться в одной игре: каждый должен устраивать другого
+    pub fn compatible(&self, other: &Self) -> bool {
+        self.user.id != other.user.id
+            && self.prefs.same_game(&other.prefs)
+            && self.accepts(other)
+            && other.accepts(self)
+    }
+}
+
+/// Ищет соперников для новой заявки среди ожидающих, дольше ждущие первыми.
+///
+/// Возвращает индексы заявок в `waiting`, если набралась вся игра.
+pub fn find_group(waiting: &[Ticket], ticket: &Ticket) -> Option<Vec<usize>> {
+    let need = (ticket.prefs.players_cnt as usize).saturating_sub(1);
+    let mut group = Vec::with_capacity(need);
+
+    for (idx, candidate) in waiting.iter().enumerate() {
+        if group.len() == need {
+            break;
+        }
+
+        if ticket.compatible(candidate) && group.iter().all(|&g| waiting[g].compatible(candidate)) {
+            group.push(idx);
+        }
+    }
+
+    (need > 0 && group.len() == need).then_some(group)
+}
+
+/// что происходит с игроком в очереди
+#[derive(Debug, Clone)]
+pub enum QueueStatus {
+    Idle,
+    /// заявка ждёт, вместе с числом подходящих ей ожидающих соперников
+    Waiting(Ticket, usize),
+    /// для игрока создана игра
+    Matched(u32),
+}
+
+/// Очередь подбора соперников, общая для всех воркеров.
+#[derive(Debug, Default, Clone)]
+pub struct Matchmaker {
+    inner: Arc<Mutex<Queue>>,
+}
+
+#[derive(Debug, Default)]
+struct Queue {
+    waiting: Vec<Ticket>,
+    /// игры, созданные для игроков, до их следующей заявки или выхода из очереди
+    matched: HashMap<u32, u32>,
+}
+
+impl Matchmaker {
+    /// Ставит заявку в очередь, заменяя прежнюю заявку игрока.
+    ///
+    /// Если соперники нашлись, их заявки забираются из очереди и возвращаются
+    /// вместе с новой, владелец будущей игры (дольше всех ждущий) первым.
+    pub fn enqueue(&self, ticket: Ticket) -> Option<Vec<Ticket>> {
+        let mut queue = self.inner.lock().unwrap();
+        queue.waiting.retain(|t| t.user.id != ticket.user.id);
+        queue.matched.remove(&ticket.user.id);
+
+        match find_group(&queue.waiting, &ticket) {
+            Some(idx) => {
+                let mut group = idx
+                    .into_iter()
+                    .rev()
+                    .map(|i| queue.waiting.remove(i))
+                    .collect::<Vec<_>>();
+                group.reverse();
+                group.push(ticket);
+                Some(group)
+            }
+            None => {
+                queue.waiting.push(ticket);
+                None
+            }
+        }
+    }
+
+    /// возвращает заявки в очередь на прежние места, если игру создать не удалось
+    pub fn requeue(&self, tickets: Vec<Ticket>) {
+        let mut queue = self.inner.lock().unwrap();
+        queue.waiting.extend(tickets);
+        queue.waiting.sort_by_key(|t| t.queued_at);
+    }
+
+    pub fn leave(&self, user_id: u32) {
+        let mut queue = self.inner.lock().unwrap();
+        queue.waiting.retain(|t| t.user.id != user_id);
+        queue.matched.remove(&user_id);
+    }
+
+    fn matched(&self, game_id: u32, tickets: &[Ticket]) {
+        let mut queue = self.inner.lock().unwrap();
+        for ticket in tickets {
+            queue.matched.insert(ticket.user.id, game_id);
+        }
+    }
+
+    pub fn status(&self, user_id: u32) -> QueueStatus {
+        let queue = self.inner.lock().unwrap();
+
+        if let Some(&game_id) = queue.matched.get(&user_id) {
+            return QueueStatus::Matched(game_id);
+        }
+
+        match queue.waiting.iter().find(|t| t.user.id == user_id) {
+            Some(ticket) => {
+                let rivals = queue
+                    .waiting
+                    .iter()
+                    .filter(|t| ticket.compatible(t))
+                    .count();
+                QueueStatus::Waiting(ticket.clone(), rivals)
+            }
+            None => QueueStatus::Idle,
+        }
+    }
+}
+
+/// Ставит заявку в очередь и, если соперники нашлись, создаёт для них игру.
+///
+/// Возвращает id созданной игры.
+pub async fn enqueue(reg: &Registry, ticket: Ticket) -> Result<Option<u32>, GameManagerError> {
+    let Some(group) = reg.matchmaking.enqueue(ticket) else {
+        return Ok(None);
+    };
+
+    match create_game(reg, &group).await {
+        Ok(game_id) => {
+            reg.matchmaking.matched(game_id, &group);
+            Ok(Some(game_id))
+        }
+        Err(e) => {
+            reg.matchmaking.requeue(group);
+            Err(e)
+        }
+    }
+}
+
+/// создаёт игру так же, как её создал бы первый игрок, а остальные в неё вошли
+async fn create_game(reg: &Registry, group: &[Ticket]) -> Result<u32, GameManagerError> {
+    let owner = &group[0];
+    let prefs = owner.prefs;
+
+    let game = game::Model::new(
+        owner.user.id,
+        World::random(),
+        prefs.game_type,
+        prefs.laps,
+        prefs.seeds,
+        prefs.duration,
+        prefs.players_cnt,
+    );
+
+    let txn = reg.db.begin().await?;
+    let mut manager = GameManager::create(&txn, game, &owner.user, owner.user.loadout()).await?;
+    for ticket in &group[1..] {
+        manager.join(&ticket.user).await?;
+    }
+    let (game_id, status) = (manager.game.id, manager.status());
+    txn.commit().await?;
+
+    reg.notify(GameEvent::GameCreated { game_id });
+    for ticket in &group[1..] {
+        reg.notify(GameEvent::PlayerJoined {
+            game_id,
+            user_id: ticket.user.id,
+        });
+    }
+    if status == GameStatus::Started {
+        reg.notify(GameEvent::StatusChanged { game_id, status });
+    }
+
+    Ok(game_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ticket(user_id: u32, players_cnt: u32, rating: u32, rating_range: Option<u32>) -> Ticket {
+        let user = user::Model {
+            id: user_id,
+            steam_id: user_id as i64,
+            login: None,
+            is_blocked: user::UserBlocked::Nope,
+            prop_pers: 1,
+            prop_car: 1,
+            prop_fwheel: 1,
+            prop_bwheel: 1,
+            created_at: ::chrono::Utc::now().naive_utc(),
+            updated_at: ::chrono::Utc::now().naive_utc(),
+        };
+        let prefs = Preferences {
+            players_cnt,
+            game_type: GameType::Winner,
+            laps: 1,
+            seeds: 10,
+            duration: 100,
+            rating_range,
+        };
+
+        Ticket::new(user, prefs, rating)
+    }
+
+    #[test]
+    fn group_needs_same_settings() {
+        let waiting = vec![
+            ticket(1, 3, 50, None),
+            ticket(2, 2, 50, None),
+            ticket(3, 3, 50, None),
+        ];
+
+        assert_eq!(
+            find_group(&waiting, &ticket(4, 3, 50, None)),
+            Some(vec![0, 2])
+        );
+        assert_eq!(find_group(&waiting, &ticket(4, 2, 50, None)), Some(vec![1]));
+        assert_eq!(find_group(&waiting, &ticket(4, 4, 50, None)), None);
+        // сам с собой игрок не играет
+        assert_eq!(find_group(&waiting, &ticket(2, 2, 50, None)), None);
+    }
+
+    #[test]
+    fn group_respects_rating_range() {
+        let waiting = vec![ticket(1, 2, 90, None), ticket(2, 2, 40, Some(5))];
+
+        // новичок ищет близкий рейтинг: первый слишком силён
+        assert_eq!(find_group(&waiting, &ticket(3, 2, 50, Some(10))), None);
+        // ограничение ожидающего тоже учитывается
+        assert_eq!(find_group(&waiting, &ticket(3, 2, 50, None)), Some(vec![0]));
+        assert_eq!(
+            find_group(&waiting[1..], &ticket(3, 2, 44, None)),
+            Some(vec![0])
+        );
+    }
+
+    #[test]
+    fn queue_takes_matched_tickets() {
+        let mm = Matchmaker::default();
+
+        assert!(mm.enqueue(ticket(1, 2, 50, None)).is_none());
+        assert!(mm.enqueue(ticket(2, 3, 50, None)).is_none());
+        assert!(matches!(mm.status(1), QueueStatus::Waiting(_, 0)));
+
+        let group = mm.enqueue(ticket(3, 2, 50, None)).unwrap();
+        assert_eq!(
+            group.iter().map(|t| t.user.id).collect::<Vec<_>>(),
+            vec![1, 3]
+        );
+        assert!(matches!(mm.status(1), QueueStatus::Idle));
+        assert!(matches!(mm.status(2), QueueStatus::Waiting(_, 0)));
+
+        mm.matched(7, &group);
+        assert!(matches!(mm.status(3), QueueStatus::Matched(7)));
+
+        mm.leave(2);
+        assert!(matches!(mm.status(2), QueueStatus::Idle));
+    }
+}
@@ -0,0 +1,232 @@
+//! Уведомления игроков о событиях в их играх.
+//!
+//! Уведомления создаются по событиям игр, а о скором конце хода в играх
+//! по переписке — по таймеру. Одно и то же уведомление дважды не создаётся.
+
+use std::time::Duration;
+
+use ::log::{error, warn};
+use ::sea_orm::{
+    sea_query::Expr, ActiveValue, ColumnTrait, ConnectionTrait, DbErr, EntityTrait, PaginatorTrait,
+    QueryFilter, QueryOrder, QuerySelect,
+};
+use ::tokio::sync::broadcast::error::RecvError;
+
+use crate::{
+    events::GameEvent,
+    manager::{GameManagerError, GameStatus},
+    state::Registry,
+};
+use entity::{
+    game,
+    notification::{self, NotificationKind},
+};
+
+/// как часто проверять сроки ходов в играх по переписке
+const DEADLINE_TICK: Duration = Duration::from_secs(60);
+
+/// сколько последних уведомлений показывать пользователю
+pub const LIST_LIMIT: u64 = 100;
+
+/// Создаёт уведомления тем из `user_ids`, у кого такого же ещё нет.
+pub async fn push<C: ConnectionTrait>(
+    db: &C,
+    user_ids: &[u32],
+    game_id: u32,
+    kind: NotificationKind,
+    step_number: u32,
+) -> Result<(), DbErr> {
+    use ActiveValue::*;
+
+    let sent = notification::Entity::find()
+        .filter(notification::Column::GameId.eq(game_id))
+        .filter(notification::Column::Kind.eq(kind))
+        .filter(notification::Column::StepNumber.eq(step_number))
+        .filter(notification::Column::UserId.is_in(user_ids.iter().copied()))
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|n| n.user_id)
+        .collect::<Vec<_>>();
+
+    let mut user_ids = user_ids
+        .iter()
+        .copied()
+        .filter(|id| !sent.contains(id))
+        .collect::<Vec<_>>();
+    user_ids.sort();
+    user_ids.dedup();
+
+    if user_ids.is_empty() {
+        return Ok(());
+    }
+
+    notification::Entity::insert_many(user_ids.into_iter().map(|user_id| {
+        notification::ActiveModel {
+            user_id: Set(user_id),
+            game_id: Set(game_id),
+            kind: Set(kind),
+            step_number: Set(step_number),
+            ..Default::default()
+        }
+    }))
+    .exec(db)
+    .await?;
+
+    Ok(())
+}
+
+/// число непрочитанных уведомлений пользователя
+pub async fn unread<C: ConnectionTrait>(db: &C, user_id: u32) -> Result<u64, DbErr> {
+    notification::Entity::find()
+        .filter(notification::Column::UserId.eq(user_id))
+        .filter(notification::Column::ReadAt.is_null())
+        .count(db)
+        .await
+}
+
+/// последние уведомления пользователя, новые первыми
+pub async fn list<C: ConnectionTrait>(
+    db: &C,
+    user_id: u32,
+) -> Result<Vec<notification::Model>, DbErr> {
+    notification::Entity::find()
+        .filter(notification::Column::UserId.eq(user_id))
+        .order_by_desc(notification::Column::Id)
+        .limit(LIST_LIMIT)
+        .all(db)
+        .await
+}
+
+/// Отмечает прочитанными все уведомления пользователя или только уведомление `id`.
+pub async fn mark_read<C: ConnectionTrait>(
+    db: &C,
+    user_id: u32,
+    id: Option<u32>,
+) -> Result<(), DbErr> {
+    let mut update = notification::Entity::update_many()
+        .col_expr(
+            notification::Column::ReadAt,
+            Expr::value(::chrono::Utc::now().naive_utc()),
+        )
+        .filter(notification::Column::UserId.eq(user_id))
+        .filter(notification::Column::ReadAt.is_null());
+    if let Some(id) = id {
+        update = update.filter(notification::Column::Id.eq(id));
+    }
+    update.exec(db).await?;
+
+    Ok(())
+}
+
+/// уведомления об изменениях в игре
+pub async fn on_event(reg: &Registry, event: &GameEvent) -> Result<(), GameManagerError> {
+    match *event {
+        // игра, созданная сразу полной, начинается без отдельного события
+        GameEvent::GameCreated { game_id }
+        | GameEvent::StatusChanged {
+            game_id,
+            status: GameStatus::Started,
+        } => {
+            let manager = reg.games.load_game(&reg.db, game_id).await?;
+            if manager.status() == GameStatus::Started {
+                let players = manager.active_players();
+                push(&reg.db, &players, game_id, NotificationKind::GameStarted, 0).await?;
+            }
+        }
+        GameEvent::StatusChanged {
+            game_id,
+            status: GameStatus::Finished,
+        } => {
+            let manager = reg.games.load_game(&reg.db, game_id).await?;
+            let players = manager
+                .turns
+                .iter()
+                .filter(|(t, _)| !t.is_robot)
+                .map(|(_, u)| u.id)
+                .collect::<Vec<_>>();
+            push(
+                &reg.db,
+                &players,
+                game_id,
+                NotificationKind::GameFinished,
+                0,
+            )
+            .await?;
+        }
+        // если ход закрыл шаг, все, кто ещё ходит, снова ждут хода
+        GameEvent::TurnSaved { game_id, .. } => {
+            let manager = reg.games.load_game(&reg.db, game_id).await?;
+            let step_number = manager.move_cnt() + 1;
+            let players = manager.waiting_players();
+            push(
+                &reg.db,
+                &players,
+                game_id,
+                NotificationKind::TurnStarted,
+                step_number,
+            )
+            .await?;
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+/// предупреждает игроков по переписке, у которых осталась четверть времени на ход
+pub async fn check_deadlines(reg: &Registry) -> Result<(), GameManagerError> {
+    let now = ::chrono::Utc::now().naive_utc();
+    let running = game::Entity::find()
+        .filter(game::Column::IsExpress.eq(false))
+        .filter(game::Column::FinishedAt.is_null())
+        .filter(game::Column::CancelledAt.is_null())
+        .filter(game::Column::PausedAt.is_null())
+        .all(&reg.db)
+        .await?;
+
+    for game in running {
+        let manager = reg.games.load_game(&reg.db, game.id).await?;
+        let (Some(left), Some(duration)) = (manager.time_left(now), manager.turn_duration()) else {
+            continue;
+        };
+
+        if left * 4 <= duration {
+            push(
+                &reg.db,
+                &manager.waiting_players(),
+                game.id,
+                NotificationKind::DeadlineNear,
+                manager.move_cnt() + 1,
+            )
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Фоновая задача: создаёт уведомления по событиям игр и раз в [`DEADLINE_TICK`] проверяет сроки ходов.
+pub async fn run(reg: Registry) {
+    let mut events = reg.events.subscribe();
+    let mut interval = ::tokio::time::interval(DEADLINE_TICK);
+
+    loop {
+        ::tokio::select! {
+            event = events.recv() => match event {
+                Ok(event) => {
+                    if let Err(e) = on_event(&reg, &event).await {
+                        error!("notifications: game {}: {e}", event.game_id());
+                    }
+                }
+                Err(RecvError::Lagged(n)) => warn!("notifications: {n} events skipped"),
+                Err(RecvError::Closed) => break,
+            },
+            _ = interval.tick() => {
+                if let Err(e) = check_deadlines(&reg).await {
+                    error!("notifications: {e}");
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,118 @@
+//! Пауза в идущей игре по решению большинства игроков.
+//!
+//! Любой игрок может предложить паузу или её снятие, решение принимается,
+//! когда за него проголосовало больше половины игроков, которые ещё ходят сами.
+
+use ::sea_orm::{
+    ActiveModelTrait, ActiveValue, ColumnTrait, ConnectionTrait, DbErr, EntityTrait, QueryFilter,
+};
+
+use crate::manager::{GameManager, GameManagerError, GameStatus};
+use entity::vote;
+
+/// сколько голосов нужно, чтобы решение приняли `players` игроков
+pub fn majority(players: usize) -> usize {
+    players / 2 + 1
+}
+
+/// голоса за смену состояния паузы в игре
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tally {
+    /// за паузу, если игра идёт, или за продолжение, если стоит
+    pub pause: bool,
+    /// id проголосовавших игроков
+    pub voters: Vec<u32>,
+    pub needed: usize,
+}
+
+/// Голоса за смену состояния паузы от игроков, которые ещё ходят сами.
+pub async fn tally<C: ConnectionTrait + Send>(
+    db: &C,
+    manager: &GameManager<'_, C>,
+) -> Result<Tally, DbErr> {
+    let pause = !manager.is_paused();
+    let active = manager.active_players();
+
+    let voters = vote::Entity::find()
+        .filter(vote::Column::GameId.eq(manager.game.id))
+        .filter(vote::Column::Pause.eq(pause))
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|v| v.user_id)
+        .filter(|user_id| active.contains(user_id))
+        .collect();
+
+    Ok(Tally {
+        pause,
+        voters,
+        needed: majority(active.len()),
+    })
+}
+
+/// Записывает голос игрока и, если набралось большинство, ставит игру на паузу или снимает её.
+///
+/// Голос за уже действующее состояние ничего не меняет.
+/// Возвращает `true`, если состояние паузы изменилось.
+pub async fn vote<C: ConnectionTrait + Send>(
+    db: &C,
+    manager: &mut GameManager<'_, C>,
+    user_id: u32,
+    pause: bool,
+) -> Result<bool, GameManagerError> {
+    use ActiveValue::*;
+
+    if manager.status() != GameStatus::Started {
+        Err(GameManagerError::GameNotActive(manager.game.id))?
+    }
+
+    if !manager.active_players().contains(&user_id) {
+        Err(GameManagerError::NotJoined(user_id))?
+    }
+
+    if manager.is_paused() == pause {
+        return Ok(false);
+    }
+
+    // прежний голос игрока заменяется новым
+    vote::Entity::delete_many()
+        .filter(vote::Column::GameId.eq(manager.game.id))
+        .filter(vote::Column::UserId.eq(user_id))
+        .exec(db)
+        .await?;
+    vote::ActiveModel {
+        game_id: Set(manager.game.id),
+        user_id: Set(user_id),
+        pause: Set(pause),
+        ..Default::default()
+    }
+    .insert(db)
+    .await?;
+
+    let tally = tally(db, manager).await?;
+    if tally.voters.len() < tally.needed {
+        return Ok(false);
+    }
+
+    manager.set_paused(pause).await?;
+    vote::Entity::delete_many()
+        .filter(vote::Column::GameId.eq(manager.game.id))
+        .exec(db)
+        .await?;
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn majority_is_more_than_half() {
+        assert_eq!(majority(1), 1);
+        assert_eq!(majority(2), 2);
+        assert_eq!(majority(3), 2);
+        assert_eq!(majority(4), 3);
+        assert_eq!(majority(5), 3);
+    }
+}
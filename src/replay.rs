@@ -0,0 +1,424 @@
+use ::serde::{Deserialize, Serialize};
+
+use crate::{
+    data::{KdlabCodec, Language, Packet, PacketType, Player, PlayerTurnInfo, UrlProperty},
+    manager::{warn_unknown_components, GameManager, GameManagerError, GameStatus, GameStorage},
+};
+use entity::game::{GameType, World};
+
+#[derive(Debug, ::thiserror::Error)]
+pub enum ReplayError {
+    #[error("Game `{0}` is not finished yet")]
+    GameNotFinished(u32),
+    #[error("Replay is empty")]
+    Empty,
+    #[error("Incorrect packet at line {0}")]
+    IncorrectPacket(usize),
+    #[error("Incorrect step `{0}`")]
+    IncorrectStep(u32),
+    #[error("Incorrect players")]
+    IncorrectPlayers,
+    #[error("Unknown track {0}/{1}")]
+    IncorrectTrack(u8, u8),
+    #[error("{0}")]
+    Manager(#[from] GameManagerError),
+    #[error("Incorrect JSON: {0}")]
+    Json(#[from] ::serde_json::Error),
+}
+
+/// Самодостаточная запись завершённой игры.
+///
+/// В текстовом виде это последовательность `OG_GAME_PACKET`, по одному на ход,
+/// каждый на отдельной строке; в JSON-виде — сама эта структура.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Replay {
+    pub game_id: u32,
+    pub owner_pid: u32,
+    pub world_id: u8,
+    pub track_id: u8,
+    pub rnd: u16,
+    pub game_type: GameType,
+    pub laps: u32,
+    pub seeds: u32,
+    pub duration: u32,
+    pub is_express: bool,
+    /// игроки и их мехосы
+    pub players: Vec<Player>,
+    /// завершённые ходы по порядку, в каждом — ходы всех игроков
+    pub steps: Vec<Vec<PlayerTurnInfo>>,
+}
+
+impl Replay {
+    /// собирает запись завершённой игры
+    pub fn from_game<S: GameStorage>(gm: &GameManager<'_, S>) -> Result<Self, ReplayError> {
+        if gm.status() != GameStatus::Finished {
+            Err(ReplayError::GameNotFinished(gm.game.id))?
+        }
+
+        // для каждого игрока берётся мехос его последнего хода, как и в `GameManager::get_info`
+        let mut players = vec![];
+        while let Some((turn, user)) = gm
+            .turns
+            .iter()
+            .filter(|(t, _)| t.player_number as usize == players.len())
+            .max_by_key(|(t, _)| t.step_number)
+        {
+            players.push(Player {
+                uid: turn.player_number,
+                nickname: turn.nickname(user).to_string(),
+                pers_car_comp_id: turn.prop_pers,
+                front_car_comp_id: turn.prop_car,
+                fwheel_car_comp_id: turn.prop_fwheel,
+                bwheel_car_comp_id: turn.prop_bwheel,
+                is_robot: turn.is_robot,
+                password: None,
+            });
+        }
+
+        let mut steps = vec![];
+        loop {
+            let step_number = steps.len() as u32 + 1;
+            let mut step = gm
+                .turns
+                .iter()
+                .map(|(t, _)| t)
+                .filter(|t| t.step_number == step_number && t.seeds.is_some())
+                .map(PlayerTurnInfo::from)
+                .collect::<Vec<_>>();
+            if step.len() < players.len() {
+                break;
+            }
+
+            step.sort_by_key(|t| t.player_id);
+            steps.push(step);
+        }
+
+        let owner_pid = gm
+            .turns
+            .iter()
+            .find(|(_, u)| u.id == gm.game.owner_id)
+            .map(|(t, _)| t.player_number)
+            .unwrap_or_default();
+
+        Ok(Self {
+            // запись загруженной игры выгружается под номером исходной
+            game_id: gm.game.imported_from.unwrap_or(gm.game.id),
+            owner_pid,
+            world_id: gm.game.world_id as u8,
+            track_id: gm.game.track_id as u8,
+            rnd: gm.game.rnd as u16,
+            game_type: gm.game.game_type,
+            laps: gm.game.laps,
+            seeds: gm.game.seeds,
+            duration: gm.game.duration,
+            is_express: gm.game.is_express,
+            players,
+            steps,
+        })
+    }
+
+    /// Сохраняет запись завершённой игрой пользователя `owner_id`, доступной только для просмотра.
+    ///
+    /// Все места в такой игре принадлежат загрузившему, а имена игроков берутся из записи.
+    pub async fn import<S: GameStorage>(
+        &self,
+        storage: &S,
+        owner_id: u32,
+    ) -> Result<entity::game::Model, ReplayError> {
+        let world = World::try_from((self.world_id.into(), self.track_id.into()))
+            .map_err(|_| ReplayError::IncorrectTrack(self.world_id, self.track_id))?;
+
+        let mut game = entity::game::Model::new(
+            owner_id,
+            world,
+            self.game_type,
+            self.laps,
+            self.seeds,
+            self.duration,
+            self.players.len() as u32,
+        );
+        game.rnd = self.rnd.into();
+        game.is_express = self.is_express;
+        game.finished_at = Some(::chrono::Utc::now().naive_utc());
+        game.imported_from = Some(self.game_id);
+        let game = storage
+            .insert_game(game)
+            .await
+            .map_err(GameManagerError::DbErr)?;
+
+        for step in &self.steps {
+            for info in step {
+                let player = &self.players[info.player_id as usize];
+                let turn = entity::turn::Model {
+                    step_number: info.step_number,
+                    is_finished: info.is_finished,
+                    rank: info.rank,
+                    move_time: info.move_time,
+                    move_steps: info.move_steps,
+                    bottles_cnt: info.bottles_cnt,
+                    total_seeds_cnt: info.total_seeds_cnt,
+                    arcanes_cnt: info.arcanes_cnt,
+                    destroys_cnt: info.destroys_cnt,
+                    user_seeds_cnt: info.user_seeds_cnt,
+                    seeds: Some(info.seeds.clone()),
+                    is_received: true,
+                    is_robot: player.is_robot,
+                    nickname: Some(player.nickname.clone()),
+                    ..entity::turn::Model::new(game.id, owner_id, info.player_id, player.loadout())
+                };
+                storage
+                    .insert_turn(turn)
+                    .await
+                    .map_err(GameManagerError::DbErr)?;
+            }
+        }
+
+        Ok(game)
+    }
+
+    /// `OG_GAME_PACKET` каждого хода, как их получали игроки
+    pub fn packets(&self) -> Vec<Packet> {
+        self.steps
+            .iter()
+            .enumerate()
+            .map(|(idx, step)| Packet {
+                version: 104,
+                t_type: PacketType::OG_GAME_PACKET,
+                gmid: self.game_id,
+                language: Language::Ru,
+                game_owner_pid: self.owner_pid,
+                packet_owner_pid: self.owner_pid,
+                password: "password".into(),
+                kd_world_id: self.world_id,
+                kd_route_id: self.track_id,
+                game_rnd: self.rnd,
+                game_type: self.game_type,
+                laps: self.laps,
+                seeds: self.seeds,
+                duration: self.duration,
+                move_cnt: idx as u32 + 1,
+                is_express: self.is_express,
+                url: UrlProperty::default(),
+                players: self.players.clone(),
+                steps: step.clone(),
+            })
+            .collect()
+    }
+
+    pub fn to_text(&self) -> String {
+        self.packets()
+            .iter()
+            .map(|p| p.encode() + "\n")
+            .collect()
+    }
+
+    pub fn from_text(input: &str) -> Result<Self, ReplayError> {
+        let mut packets = input
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(idx, line)| {
+                Packet::decode(line.trim())
+                    .filter(|p| p.t_type == PacketType::OG_GAME_PACKET)
+                    .ok_or(ReplayError::IncorrectPacket(idx + 1))
+            })
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter();
+
+        let first = packets.next().ok_or(ReplayError::Empty)?;
+        let mut replay = Self {
+            game_id: first.gmid,
+            owner_pid: first.game_owner_pid,
+            world_id: first.kd_world_id,
+            track_id: first.kd_route_id,
+            rnd: first.game_rnd,
+            game_type: first.game_type,
+            laps: first.laps,
+            seeds: first.seeds,
+            duration: first.duration,
+            is_express: first.is_express,
+            players: first.players,
+            steps: vec![first.steps],
+        };
+        replay.steps.extend(packets.map(|p| p.steps));
+
+        replay.validate()?;
+        Ok(replay)
+    }
+
+    pub fn from_json(input: &str) -> Result<Self, ReplayError> {
+        let replay: Self = ::serde_json::from_str(input)?;
+
+        replay.validate()?;
+        Ok(replay)
+    }
+
+    /// разбирает запись в любом из двух видов
+    pub fn parse(input: &str) -> Result<Self, ReplayError> {
+        match input.trim_start().starts_with('{') {
+            true => Self::from_json(input),
+            false => Self::from_text(input),
+        }
+    }
+
+    /// проверяет, что запись можно показать: игроки пронумерованы подряд,
+    /// а в каждом ходе есть ходы всех игроков
+    fn validate(&self) -> Result<(), ReplayError> {
+        if !(2..=5).contains(&self.players.len())
+            || self
+                .players
+                .iter()
+                .enumerate()
+                .any(|(idx, p)| p.uid != idx as u32)
+        {
+            Err(ReplayError::IncorrectPlayers)?
+        }
+
+        for player in &self.players {
+            warn_unknown_components(player.loadout());
+        }
+
+        let is_available = World::try_from((self.world_id.into(), self.track_id.into()))
+            .is_ok_and(World::is_available);
+        if !is_available {
+            Err(ReplayError::IncorrectTrack(self.world_id, self.track_id))?
+        }
+
+        if self.steps.is_empty() {
+            Err(ReplayError::Empty)?
+        }
+
+        for (idx, step) in self.steps.iter().enumerate() {
+            let step_number = idx as u32 + 1;
+            let is_complete = step.len() == self.players.len()
+                && step.iter().all(|t| t.step_number == step_number)
+                && (0..self.players.len() as u32)
+                    .all(|pid| step.iter().any(|t| t.player_id == pid));
+            if !is_complete {
+                Err(ReplayError::IncorrectStep(step_number))?
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn replay() -> Replay {
+        let turn = |step_number, player_id, seeds: &str| PlayerTurnInfo {
+            step_number,
+            player_id,
+            is_finished: false,
+            rank: 0,
+            move_time: 0,
+            move_steps: 0,
+            bottles_cnt: 0,
+            total_seeds_cnt: 0,
+            arcanes_cnt: 0,
+            destroys_cnt: 0,
+            user_seeds_cnt: 1,
+            seeds: seeds.to_owned(),
+        };
+
+        Replay {
+            game_id: 7,
+            owner_pid: 0,
+            world_id: 0,
+            track_id: 0,
+            rnd: 12711,
+            game_type: GameType::All,
+            laps: 1,
+            seeds: 10,
+            duration: 100,
+            is_express: true,
+            players: vec![Player::new(0, "player"), Player::new(1, "player2")],
+            steps: vec![
+                vec![turn(1, 0, "620#402#51#-1"), turn(1, 1, "592#382#51#-1")],
+                vec![turn(2, 0, "913#303#51#-1"), turn(2, 1, "892#329#61#-1")],
+            ],
+        }
+    }
+
+    #[test]
+    fn text_roundtrip() {
+        let replay = replay();
+        let text = replay.to_text();
+
+        assert_eq!(text.lines().count(), 2);
+        assert!(text.lines().all(|l| l.starts_with("KDLAB;104;1;7;")));
+        assert_eq!(Replay::parse(&text).unwrap(), replay);
+    }
+
+    #[test]
+    fn json_roundtrip() {
+        let replay = replay();
+        let json = ::serde_json::to_string(&replay).unwrap();
+
+        assert_eq!(Replay::parse(&json).unwrap(), replay);
+    }
+
+    #[tokio::test]
+    async fn import_roundtrip() {
+        use crate::manager::MemoryStorage;
+
+        let now = ::chrono::Utc::now().naive_utc();
+        let storage = MemoryStorage::default();
+        storage.add_user(entity::user::Model {
+            id: 3,
+            steam_id: 333,
+            login: Some("importer".into()),
+            is_blocked: entity::user::UserBlocked::Nope,
+            prop_pers: 1,
+            prop_car: 1,
+            prop_fwheel: 1,
+            prop_bwheel: 1,
+            created_at: now,
+            updated_at: now,
+        });
+
+        let replay = replay();
+        let game = replay.import(&storage, 3).await.unwrap();
+        assert_eq!(game.imported_from, Some(7));
+
+        let gm = GameManager::load_game(&storage, game.id).await.unwrap();
+        assert_eq!(gm.status(), GameStatus::Finished);
+        assert!(gm.turns.iter().all(|(_, u)| u.id == 3));
+        assert!(matches!(
+            gm.rematch(3),
+            Err(GameManagerError::ImportedReadOnly(id)) if id == game.id
+        ));
+
+        // имена игроков и номер исходной игры сохраняются
+        assert_eq!(Replay::from_game(&gm).unwrap(), replay);
+    }
+
+    #[test]
+    fn unknown_track() {
+        let mut replay = replay();
+        replay.track_id = 9;
+
+        assert!(matches!(
+            Replay::parse(&replay.to_text()),
+            Err(ReplayError::IncorrectTrack(0, 9))
+        ));
+    }
+
+    #[test]
+    fn incomplete_step() {
+        let mut replay = replay();
+        replay.steps[1].pop();
+
+        assert!(matches!(
+            Replay::parse(&replay.to_text()),
+            Err(ReplayError::IncorrectStep(2))
+        ));
+        assert!(matches!(
+            Replay::parse("KDLAB;104;3;1;BITRIX"),
+            Err(ReplayError::IncorrectPacket(1))
+        ));
+        assert!(matches!(Replay::parse(""), Err(ReplayError::Empty)));
+    }
+}
@@ -0,0 +1,108 @@
+//! Запуск игр по расписанию.
+//!
+//! К назначенному времени игра начинается с вошедшими игроками, свободные места
+//! занимают роботы, если владелец это разрешил, а без них слишком малая игра отменяется.
+
+use std::time::Duration;
+
+use ::log::error;
+use ::sea_orm::{
+    ActiveModelTrait, ActiveValue, ColumnTrait, ConnectionTrait, DbErr, EntityTrait, QueryFilter,
+    QueryOrder, TransactionTrait,
+};
+
+use crate::{
+    events::GameEvent,
+    manager::{GameManager, GameManagerError, GameStatus, MIN_PLAYERS},
+    state::Registry,
+};
+use entity::{game, user};
+
+/// как часто проверять, не пора ли начать игры
+const TICK: Duration = Duration::from_secs(10);
+
+/// `steam_id` пользователя, от имени которого играют роботы; настоящих таких нет
+pub const ROBOT_STEAM_ID: i64 = 0;
+
+/// пользователь `robot`, создаётся при первом обращении
+pub async fn robot_user<C: ConnectionTrait>(db: &C) -> Result<user::Model, DbErr> {
+    use ActiveValue::*;
+
+    let robot = user::Entity::find()
+        .filter(user::Column::SteamId.eq(ROBOT_STEAM_ID))
+        .one(db)
+        .await?;
+
+    match robot {
+        Some(robot) => Ok(robot),
+        None => {
+            user::ActiveModel {
+                steam_id: Set(ROBOT_STEAM_ID),
+                login: Set(Some(String::from("robot"))),
+                ..Default::default()
+            }
+            .insert(db)
+            .await
+        }
+    }
+}
+
+/// начинает или отменяет игры, время которых уже наступило
+pub async fn start_due_games(reg: &Registry) -> Result<(), GameManagerError> {
+    let due = game::Entity::find()
+        .filter(game::Column::StartsAt.lte(::chrono::Utc::now().naive_utc()))
+        .filter(game::Column::FinishedAt.is_null())
+        .filter(game::Column::CancelledAt.is_null())
+        .filter(game::Column::StepStartedAt.is_null())
+        .order_by_asc(game::Column::Id)
+        .all(&reg.db)
+        .await?;
+
+    for game in due {
+        if let Err(e) = start_game(reg, game.id).await {
+            error!("scheduler: game {}: {e}", game.id);
+        }
+    }
+
+    Ok(())
+}
+
+async fn start_game(reg: &Registry, game_id: u32) -> Result<(), GameManagerError> {
+    let txn = reg.db.begin().await?;
+    let mut manager = GameManager::lock_game(&txn, game_id).await?;
+
+    if manager.status() != GameStatus::Open {
+        // игры, в которых к сроку заняты все места, уже идут сами,
+        // остаётся только открыть им первый шаг
+        if !manager.open_scheduled().await? {
+            return Ok(());
+        }
+    } else if manager.game.fill_with_robots {
+        let robot = robot_user(&txn).await?;
+        manager.add_robots(&robot).await?;
+    } else if manager.turns.len() >= MIN_PLAYERS {
+        manager.start().await?;
+    } else {
+        manager.cancel().await?;
+    }
+
+    let status = manager.status();
+    txn.commit().await?;
+
+    reg.notify(GameEvent::StatusChanged { game_id, status });
+
+    Ok(())
+}
+
+/// Фоновая задача: раз в [`TICK`] проверяет игры по расписанию.
+pub async fn run(reg: Registry) {
+    let mut interval = ::tokio::time::interval(TICK);
+
+    loop {
+        interval.tick().await;
+
+        if let Err(e) = start_due_games(&reg).await {
+            error!("scheduler: {e}");
+        }
+    }
+}
@@ -1,7 +1,34 @@
+use std::time::Duration;
+
 use ::sea_orm::DbConn;
 
+use crate::{
+    cache::GameCache,
+    events::{Events, GameEvent},
+    matchmaking::Matchmaker,
+    webhooks::Targets,
+};
+
 #[derive(Debug, Default, Clone)]
 pub struct Registry {
     pub db: DbConn,
     pub steam_key: Option<&'static str>,
+    /// состояние активных игр, общее для всех воркеров
+    pub games: GameCache,
+    pub events: Events,
+    /// очередь подбора соперников
+    pub matchmaking: Matchmaker,
+    /// Сколько держать `OG_REFRESH_PACKET` в ожидании хода остальных игроков.
+    /// `None` — отвечать сразу.
+    pub refresh_long_poll: Option<Duration>,
+    /// куда можно отправлять вебхуки
+    pub webhook_targets: Targets,
+}
+
+impl Registry {
+    /// сбрасывает кэш изменившейся игры и рассылает событие об изменении
+    pub fn notify(&self, event: GameEvent) {
+        self.games.invalidate(event.game_id());
+        self.events.publish(event);
+    }
 }
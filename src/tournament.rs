@@ -0,0 +1,739 @@
+//! Турниры: составление туров, подсчёт очков и создание заездов.
+//!
+//! Заезды туров — обычные игры. Когда все игры тура закончены,
+//! [`advance`] подводит итоги и создаёт игры следующего тура или завершает турнир.
+
+use std::collections::HashMap;
+
+use ::log::{error, warn};
+use ::sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ActiveValue, ColumnTrait, ConnectionTrait, DbErr,
+    EntityTrait, QueryFilter, QueryOrder, TransactionTrait,
+};
+use ::serde::Serialize;
+use ::tokio::sync::broadcast::error::RecvError;
+
+use crate::{
+    events::GameEvent,
+    manager::{GameManager, GameManagerError, GameStatus},
+    state::Registry,
+};
+use entity::{
+    tournament::{self, TournamentFormat},
+    tournament_game, tournament_participant, turn, user,
+};
+
+#[derive(Debug, ::thiserror::Error)]
+pub enum TournamentError {
+    #[error("Tournament `{0}` not found")]
+    NotFound(u32),
+    #[error("Tournament `{0}` already started")]
+    AlreadyStarted(u32),
+    #[error("Tournament `{0}` needs at least two participants")]
+    NotEnoughParticipants(u32),
+    #[error("User `{0}` already joined this tournament")]
+    AlreadyJoined(u32),
+    #[error(transparent)]
+    Manager(#[from] GameManagerError),
+    #[error("DbErr: `{0}`")]
+    DbErr(#[from] DbErr),
+}
+
+/// итог одного участника в заезде
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RaceResult {
+    pub user_id: u32,
+    pub rank: u32,
+    pub points: u32,
+}
+
+/// заезд тура вместе с итогами, лучший участник первым
+#[derive(Debug, Clone, Serialize)]
+pub struct Race {
+    pub round: u32,
+    pub game_id: u32,
+    pub status: GameStatus,
+    pub results: Vec<RaceResult>,
+}
+
+impl Race {
+    /// Участник, проходящий дальше в турнире на выбывание.
+    ///
+    /// В отменённой игре очков нет ни у кого, и проходит участник с лучшим посевом.
+    pub fn winner(&self) -> Option<u32> {
+        self.results.first().map(|r| r.user_id)
+    }
+
+    pub fn is_complete(&self) -> bool {
+        matches!(self.status, GameStatus::Finished | GameStatus::Cancelled)
+    }
+}
+
+/// строка турнирной таблицы
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Standing {
+    pub user_id: u32,
+    pub points: u32,
+    pub wins: u32,
+    pub races: u32,
+}
+
+/// очки за место: последний не получает ничего, не доехавший тоже
+pub fn race_points(players_cnt: u32, rank: u32) -> u32 {
+    match rank {
+        0 => 0,
+        rank => players_cnt.saturating_sub(rank),
+    }
+}
+
+/// итоги заезда по последним ходам игроков; игроки идут в порядке номеров при равенстве очков
+pub fn race_results(turns: &[(turn::Model, user::Model)], players_cnt: u32) -> Vec<RaceResult> {
+    let mut last = HashMap::<u32, &turn::Model>::new();
+    for (turn, _) in turns {
+        let entry = last.entry(turn.player_number).or_insert(turn);
+        if entry.step_number < turn.step_number {
+            *entry = turn;
+        }
+    }
+
+    let mut last = last.into_values().collect::<Vec<_>>();
+    last.sort_by_key(|t| t.player_number);
+
+    let mut results = last
+        .into_iter()
+        .map(|t| RaceResult {
+            user_id: t.user_id,
+            rank: t.rank,
+            points: race_points(players_cnt, t.rank),
+        })
+        .collect::<Vec<_>>();
+    // сортировка устойчивая, порядок номеров игроков сохраняется
+    results.sort_by(|a, b| b.points.cmp(&a.points));
+
+    results
+}
+
+/// Турнирная таблица по законченным заездам.
+///
+/// `seeds` — участники в порядке регистрации: он разрешает равенство очков и побед.
+pub fn standings(seeds: &[u32], races: &[Race]) -> Vec<Standing> {
+    let mut table = seeds
+        .iter()
+        .map(|&user_id| Standing {
+            user_id,
+            points: 0,
+            wins: 0,
+            races: 0,
+        })
+        .collect::<Vec<_>>();
+
+    for race in races.iter().filter(|r| r.status == GameStatus::Finished) {
+        for (idx, result) in race.results.iter().enumerate() {
+            let Some(row) = table.iter_mut().find(|s| s.user_id == result.user_id) else {
+                continue;
+            };
+            row.points += result.points;
+            row.races += 1;
+            if idx == 0 && result.points > 0 {
+                row.wins += 1;
+            }
+        }
+    }
+
+    table.sort_by(|a, b| b.points.cmp(&a.points).then(b.wins.cmp(&a.wins)));
+
+    table
+}
+
+/// число туров круговой системы
+pub fn round_robin_rounds(participants: usize) -> u32 {
+    match participants {
+        0 | 1 => 0,
+        n => (n + n % 2 - 1) as u32,
+    }
+}
+
+/// Пары тура `round` (с единицы) круговой системы по методу кругов.
+///
+/// При нечётном числе участников один из них в каждом туре отдыхает.
+pub fn round_robin_pairs(players: &[u32], round: u32) -> Vec<Vec<u32>> {
+    let mut slots = players.iter().copied().map(Some).collect::<Vec<_>>();
+    if slots.len() % 2 == 1 {
+        slots.push(None);
+    }
+
+    let n = slots.len();
+    if n < 2 {
+        return vec![];
+    }
+
+    slots[1..].rotate_right((round.max(1) as usize - 1) % (n - 1));
+
+    (0..n / 2)
+        .filter_map(|i| match (slots[i], slots[n - 1 - i]) {
+            (Some(a), Some(b)) => Some(vec![a, b]),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Разбивает участников по заездам в заданном порядке.
+///
+/// Оставшийся в одиночестве участник пропускает тур.
+pub fn groups(players: &[u32], players_per_race: u32) -> Vec<Vec<u32>> {
+    players
+        .chunks(players_per_race.max(2) as usize)
+        .map(<[u32]>::to_vec)
+        .collect()
+}
+
+/// сколько шагов перебора тратится на поиск тура без повторных встреч
+const SWISS_SEARCH_STEPS: u32 = 10_000;
+
+/// Разбивает участников швейцарской системы по заездам.
+///
+/// Участники идут в порядке `order`, как в [`groups`], но те, кто уже
+/// встречался в законченных заездах `races`, по возможности попадают в разные
+/// заезды. Если так разбить нельзя, участники разбиваются подряд.
+pub fn swiss_groups(order: &[u32], players_per_race: u32, races: &[Race]) -> Vec<Vec<u32>> {
+    let fallback = groups(order, players_per_race);
+
+    let mut met = vec![];
+    for race in races.iter().filter(|r| r.status == GameStatus::Finished) {
+        for a in &race.results {
+            for b in &race.results {
+                met.push((a.user_id, b.user_id));
+            }
+        }
+    }
+
+    let mut search = SwissSearch {
+        order,
+        met,
+        used: vec![false; order.len()],
+        steps: SWISS_SEARCH_STEPS,
+        found: vec![],
+    };
+    let sizes = fallback.iter().map(Vec::len).collect::<Vec<_>>();
+    match search.place(&mut vec![], 0, &sizes) {
+        true => search.found,
+        false => fallback,
+    }
+}
+
+/// перебор с возвратом для [`swiss_groups`]
+struct SwissSearch<'a> {
+    order: &'a [u32],
+    /// пары участников, уже встречавшихся в одном заезде
+    met: Vec<(u32, u32)>,
+    /// участник из `order` уже попал в заезд
+    used: Vec<bool>,
+    steps: u32,
+    found: Vec<Vec<u32>>,
+}
+
+impl SwissSearch<'_> {
+    /// добирает в `group` участников начиная с `from`, а затем остальные заезды размеров `sizes`
+    fn place(&mut self, group: &mut Vec<usize>, from: usize, sizes: &[usize]) -> bool {
+        if self.steps == 0 {
+            return false;
+        }
+        self.steps -= 1;
+
+        let Some(&size) = sizes.first() else {
+            return true;
+        };
+
+        if group.len() == size {
+            let taken = std::mem::take(group);
+            self.found
+                .push(taken.iter().map(|&i| self.order[i]).collect());
+            if self.place(group, 0, &sizes[1..]) {
+                return true;
+            }
+            self.found.pop();
+            *group = taken;
+            return false;
+        }
+
+        // заезд открывает лучший из ещё не распределённых участников
+        let candidates: Vec<usize> = match group.is_empty() {
+            true => self.used.iter().position(|u| !u).into_iter().collect(),
+            false => (from..self.order.len()).collect(),
+        };
+        for i in candidates {
+            let player = self.order[i];
+            if self.used[i]
+                || group
+                    .iter()
+                    .any(|&j| self.met.contains(&(player, self.order[j])))
+            {
+                continue;
+            }
+
+            self.used[i] = true;
+            group.push(i);
+            if self.place(group, i + 1, sizes) {
+                return true;
+            }
+            group.pop();
+            self.used[i] = false;
+        }
+
+        false
+    }
+}
+
+/// число туров турнира на выбывание, пока не останется один участник
+pub fn elimination_rounds(participants: usize, players_per_race: u32) -> u32 {
+    let size = players_per_race.max(2) as usize;
+    let (mut left, mut rounds) = (participants, 0);
+    while left > 1 {
+        left = left.div_ceil(size);
+        rounds += 1;
+    }
+    rounds
+}
+
+/// участники турнира в порядке регистрации
+pub async fn participants<C: ConnectionTrait>(
+    db: &C,
+    tournament_id: u32,
+) -> Result<Vec<tournament_participant::Model>, DbErr> {
+    tournament_participant::Entity::find()
+        .filter(tournament_participant::Column::TournamentId.eq(tournament_id))
+        .order_by_asc(tournament_participant::Column::Id)
+        .all(db)
+        .await
+}
+
+/// все заезды турнира с итогами по порядку туров
+pub async fn races<C: ConnectionTrait + Send>(
+    db: &C,
+    tournament_id: u32,
+) -> Result<Vec<Race>, TournamentError> {
+    let games = tournament_game::Entity::find()
+        .filter(tournament_game::Column::TournamentId.eq(tournament_id))
+        .order_by_asc(tournament_game::Column::Round)
+        .order_by_asc(tournament_game::Column::Id)
+        .all(db)
+        .await?;
+
+    let mut races = Vec::with_capacity(games.len());
+    for game in games {
+        let manager = GameManager::load_game(db, game.game_id).await?;
+        races.push(Race {
+            round: game.round,
+            game_id: game.game_id,
+            status: manager.status(),
+            results: race_results(&manager.turns, manager.game.players_cnt),
+        });
+    }
+
+    Ok(races)
+}
+
+/// регистрирует пользователя в ещё не начатом турнире
+pub async fn join<C: ConnectionTrait>(
+    db: &C,
+    tournament: &tournament::Model,
+    user_id: u32,
+) -> Result<(), TournamentError> {
+    use ActiveValue::*;
+
+    if tournament.started_at.is_some() {
+        Err(TournamentError::AlreadyStarted(tournament.id))?
+    }
+
+    if participants(db, tournament.id)
+        .await?
+        .iter()
+        .any(|p| p.user_id == user_id)
+    {
+        Err(TournamentError::AlreadyJoined(user_id))?
+    }
+
+    tournament_participant::ActiveModel {
+        tournament_id: Set(tournament.id),
+        user_id: Set(user_id),
+        ..Default::default()
+    }
+    .insert(db)
+    .await?;
+
+    Ok(())
+}
+
+/// запускает турнир и создаёт игры первого тура
+pub async fn start(reg: &Registry, tournament_id: u32) -> Result<(), TournamentError> {
+    let txn = reg.db.begin().await?;
+
+    // условное обновление не даст запустить турнир дважды
+    let res = tournament::Entity::update_many()
+        .col_expr(
+            tournament::Column::StartedAt,
+            Expr::value(::chrono::Utc::now().naive_utc()),
+        )
+        .col_expr(tournament::Column::CurrentRound, Expr::value(1))
+        .filter(tournament::Column::Id.eq(tournament_id))
+        .filter(tournament::Column::StartedAt.is_null())
+        .exec(&txn)
+        .await?;
+
+    let mut tournament = tournament::Entity::find_by_id(tournament_id)
+        .one(&txn)
+        .await?
+        .ok_or(TournamentError::NotFound(tournament_id))?;
+
+    if res.rows_affected == 0 {
+        Err(TournamentError::AlreadyStarted(tournament_id))?
+    }
+
+    let seeds = participants(&txn, tournament_id)
+        .await?
+        .into_iter()
+        .map(|p| p.user_id)
+        .collect::<Vec<_>>();
+    if seeds.len() < 2 {
+        Err(TournamentError::NotEnoughParticipants(tournament_id))?
+    }
+
+    tournament.rounds = match tournament.format {
+        TournamentFormat::RoundRobin => round_robin_rounds(seeds.len()),
+        TournamentFormat::Swiss => tournament.rounds.max(1),
+        TournamentFormat::Elimination => {
+            elimination_rounds(seeds.len(), tournament.players_per_race)
+        }
+    };
+    let tournament = tournament::ActiveModel {
+        id: ActiveValue::Unchanged(tournament.id),
+        rounds: ActiveValue::Set(tournament.rounds),
+        ..Default::default()
+    }
+    .update(&txn)
+    .await?;
+
+    let pairing = match tournament.format {
+        TournamentFormat::RoundRobin => round_robin_pairs(&seeds, 1),
+        _ => groups(&seeds, tournament.players_per_race),
+    };
+    let created = create_round(&txn, &tournament, 1, &pairing).await?;
+    txn.commit().await?;
+
+    for game_id in created {
+        reg.notify(GameEvent::GameCreated { game_id });
+    }
+
+    Ok(())
+}
+
+/// создаёт игры тура, первый участник группы становится владельцем игры
+async fn create_round<C: ConnectionTrait + Send>(
+    txn: &C,
+    tournament: &tournament::Model,
+    round: u32,
+    pairing: &[Vec<u32>],
+) -> Result<Vec<u32>, TournamentError> {
+    use ActiveValue::*;
+
+    let mut created = vec![];
+
+    for group in pairing.iter().filter(|g| g.len() > 1) {
+        let users = user::Entity::find()
+            .filter(user::Column::Id.is_in(group.clone()))
+            .all(txn)
+            .await?;
+        let mut users = group
+            .iter()
+            .filter_map(|id| users.iter().find(|u| u.id == *id));
+        let Some(owner) = users.next() else {
+            continue;
+        };
+
+        let game = entity::game::Model::new(
+            owner.id,
+            tournament.tracks.world(round),
+            tournament.game_type,
+            tournament.laps,
+            tournament.seeds,
+            tournament.duration,
+            group.len() as u32,
+        );
+        // игра сразу заполняется участниками группы, посторонним в неё не войти
+        let mut manager = GameManager::create(txn, game, owner, owner.loadout()).await?;
+        for user in users {
+            manager.join(user).await?;
+        }
+
+        tournament_game::ActiveModel {
+            tournament_id: Set(tournament.id),
+            round: Set(round),
+            game_id: Set(manager.game.id),
+            ..Default::default()
+        }
+        .insert(txn)
+        .await?;
+
+        created.push(manager.game.id);
+    }
+
+    Ok(created)
+}
+
+/// Подводит итоги тура, если все его игры закончены, и переходит к следующему.
+///
+/// Возвращает `true`, если тур был закрыт.
+pub async fn advance(reg: &Registry, tournament_id: u32) -> Result<bool, TournamentError> {
+    let txn = reg.db.begin().await?;
+
+    let Some(tournament) = tournament::Entity::find_by_id(tournament_id)
+        .one(&txn)
+        .await?
+    else {
+        Err(TournamentError::NotFound(tournament_id))?
+    };
+    if tournament.started_at.is_none() || tournament.finished_at.is_some() {
+        return Ok(false);
+    }
+
+    let races = races(&txn, tournament_id).await?;
+    let round = tournament.current_round;
+    let current = races
+        .iter()
+        .filter(|r| r.round == round)
+        .collect::<Vec<_>>();
+    if !current.iter().all(|r| r.is_complete()) {
+        return Ok(false);
+    }
+
+    let participants = participants(&txn, tournament_id).await?;
+    let seeds = participants.iter().map(|p| p.user_id).collect::<Vec<_>>();
+
+    let mut alive = participants
+        .iter()
+        .filter(|p| p.eliminated_in.is_none())
+        .map(|p| p.user_id)
+        .collect::<Vec<_>>();
+    if tournament.format == TournamentFormat::Elimination {
+        for race in &current {
+            let losers = race
+                .results
+                .iter()
+                .map(|r| r.user_id)
+                .filter(|&id| Some(id) != race.winner())
+                .collect::<Vec<_>>();
+
+            tournament_participant::Entity::update_many()
+                .col_expr(
+                    tournament_participant::Column::EliminatedIn,
+                    Expr::value(round),
+                )
+                .filter(tournament_participant::Column::TournamentId.eq(tournament_id))
+                .filter(tournament_participant::Column::UserId.is_in(losers.clone()))
+                .exec(&txn)
+                .await?;
+
+            alive.retain(|id| !losers.contains(id));
+        }
+    }
+
+    let finished = match tournament.format {
+        TournamentFormat::Elimination => alive.len() < 2,
+        _ => round >= tournament.rounds,
+    };
+
+    let mut created = vec![];
+    let mut model = tournament::ActiveModel {
+        id: ActiveValue::Unchanged(tournament_id),
+        ..Default::default()
+    };
+    if finished {
+        model.finished_at = ActiveValue::Set(Some(::chrono::Utc::now().naive_utc()));
+    } else {
+        let next = round + 1;
+        let pairing = match tournament.format {
+            TournamentFormat::RoundRobin => round_robin_pairs(&seeds, next),
+            TournamentFormat::Swiss => {
+                let order = standings(&seeds, &races)
+                    .into_iter()
+                    .map(|s| s.user_id)
+                    .collect::<Vec<_>>();
+                swiss_groups(&order, tournament.players_per_race, &races)
+            }
+            TournamentFormat::Elimination => groups(&alive, tournament.players_per_race),
+        };
+
+        created = create_round(&txn, &tournament, next, &pairing).await?;
+        model.current_round = ActiveValue::Set(next);
+    }
+    model.update(&txn).await?;
+    txn.commit().await?;
+
+    for game_id in created {
+        reg.notify(GameEvent::GameCreated { game_id });
+    }
+
+    Ok(true)
+}
+
+/// проверяет все идущие турниры, например после запуска сервера
+pub async fn advance_all(reg: &Registry) -> Result<(), TournamentError> {
+    let running = tournament::Entity::find()
+        .filter(tournament::Column::StartedAt.is_not_null())
+        .filter(tournament::Column::FinishedAt.is_null())
+        .all(&reg.db)
+        .await?;
+
+    for tournament in running {
+        advance(reg, tournament.id).await?;
+    }
+
+    Ok(())
+}
+
+/// Фоновая задача: закрывает туры по мере завершения их игр.
+pub async fn run(reg: Registry) {
+    let mut rx = reg.events.subscribe();
+
+    if let Err(e) = advance_all(&reg).await {
+        error!("tournaments: {e}");
+    }
+
+    loop {
+        let game_id = match rx.recv().await {
+            Ok(GameEvent::StatusChanged {
+                game_id,
+                status: GameStatus::Finished | GameStatus::Cancelled,
+            }) => game_id,
+            Ok(_) => continue,
+            Err(RecvError::Lagged(n)) => {
+                warn!("tournaments: {n} events skipped, checking all tournaments");
+                if let Err(e) = advance_all(&reg).await {
+                    error!("tournaments: {e}");
+                }
+                continue;
+            }
+            Err(RecvError::Closed) => break,
+        };
+
+        let found = tournament_game::Entity::find()
+            .filter(tournament_game::Column::GameId.eq(game_id))
+            .one(&reg.db)
+            .await;
+
+        let res = match found {
+            Ok(Some(game)) => advance(&reg, game.tournament_id).await.map(|_| ()),
+            Ok(None) => Ok(()),
+            Err(e) => Err(e.into()),
+        };
+        if let Err(e) = res {
+            error!("tournaments: game {game_id}: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn race(status: GameStatus, points: &[(u32, u32)]) -> Race {
+        Race {
+            round: 1,
+            game_id: 1,
+            status,
+            results: points
+                .iter()
+                .map(|&(user_id, points)| RaceResult {
+                    user_id,
+                    rank: 0,
+                    points,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn points_by_rank() {
+        assert_eq!(race_points(4, 1), 3);
+        assert_eq!(race_points(4, 4), 0);
+        assert_eq!(race_points(4, 0), 0);
+        assert_eq!(race_points(2, 5), 0);
+    }
+
+    #[test]
+    fn round_robin_everyone_meets_once() {
+        for n in 2..=7u32 {
+            let players = (1..=n).collect::<Vec<_>>();
+            let mut met = vec![];
+            for round in 1..=round_robin_rounds(players.len()) {
+                let pairs = round_robin_pairs(&players, round);
+                let mut busy = pairs.concat();
+                busy.sort();
+                busy.dedup();
+                assert_eq!(busy.len(), pairs.len() * 2, "n={n} round={round}");
+
+                for pair in pairs {
+                    met.push((pair[0].min(pair[1]), pair[0].max(pair[1])));
+                }
+            }
+            met.sort();
+            met.dedup();
+            assert_eq!(met.len() as u32, n * (n - 1) / 2, "n={n}");
+        }
+    }
+
+    #[test]
+    fn groups_and_elimination_rounds() {
+        assert_eq!(
+            groups(&[1, 2, 3, 4, 5], 2),
+            vec![vec![1, 2], vec![3, 4], vec![5]]
+        );
+        assert_eq!(groups(&[1, 2, 3, 4, 5], 3), vec![vec![1, 2, 3], vec![4, 5]]);
+        assert_eq!(elimination_rounds(8, 2), 3);
+        assert_eq!(elimination_rounds(5, 2), 3);
+        assert_eq!(elimination_rounds(9, 3), 2);
+        assert_eq!(elimination_rounds(1, 2), 0);
+    }
+
+    #[test]
+    fn swiss_avoids_repeat_races() {
+        let races = vec![
+            race(GameStatus::Finished, &[(1, 1), (2, 0)]),
+            race(GameStatus::Finished, &[(3, 1), (4, 0)]),
+        ];
+
+        assert_eq!(
+            swiss_groups(&[1, 2, 3, 4], 2, &races),
+            vec![vec![1, 3], vec![2, 4]]
+        );
+        // отменённый заезд встречей не считается
+        let cancelled = vec![race(GameStatus::Cancelled, &[(1, 0), (2, 0)])];
+        assert_eq!(
+            swiss_groups(&[1, 2, 3, 4], 2, &cancelled),
+            vec![vec![1, 2], vec![3, 4]]
+        );
+        // без повторной встречи не обойтись
+        assert_eq!(swiss_groups(&[1, 2], 2, &races), vec![vec![1, 2]]);
+        assert_eq!(
+            swiss_groups(&[1, 2, 3, 4, 5], 3, &races),
+            vec![vec![1, 3, 5], vec![2, 4]]
+        );
+    }
+
+    #[test]
+    fn standings_order() {
+        let races = vec![
+            race(GameStatus::Finished, &[(3, 1), (1, 0)]),
+            race(GameStatus::Finished, &[(2, 1), (4, 0)]),
+            race(GameStatus::Finished, &[(2, 1), (3, 0)]),
+            // незаконченные заезды не учитываются
+            race(GameStatus::Started, &[(4, 0), (1, 0)]),
+        ];
+
+        let table = standings(&[1, 2, 3, 4], &races);
+        let order = table.iter().map(|s| s.user_id).collect::<Vec<_>>();
+        assert_eq!(order, vec![2, 3, 1, 4]);
+        assert_eq!(table[0].wins, 2);
+        assert_eq!(table[3].races, 1);
+    }
+}
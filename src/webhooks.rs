@@ -0,0 +1,521 @@
+//! Вебхуки: события игр отправляются POST-запросом на адреса, которые завели пользователи.
+//!
+//! Тело запроса — JSON с игрой и всеми её ходами, подписанный HMAC-SHA256 секретом
+//! вебхука. Каждая отправка попадает в журнал доставок, неудачные повторяются
+//! с растущей паузой, пока не кончатся попытки.
+//!
+//! Запросы уходят только на внешние адреса: сам сервер и его локальная сеть
+//! вебхукам недоступны ни при заведении, ни при отправке.
+
+use std::{net::IpAddr, sync::Arc, time::Duration};
+
+use ::futures::{stream, TryStreamExt};
+use ::hmac::{Hmac, Mac};
+use ::log::{error, warn};
+use ::reqwest::{
+    dns::{Addrs, Name, Resolve, Resolving},
+    header::CONTENT_TYPE,
+    redirect::Policy,
+    Client, Url,
+};
+use ::sea_orm::{
+    ActiveModelTrait, ActiveValue, ColumnTrait, ConnectionTrait, DbConn, DbErr, EntityTrait,
+    ModelTrait, PaginatorTrait, QueryFilter, QueryOrder, QuerySelect,
+};
+use ::serde::Serialize;
+use ::sha2::Sha256;
+use ::tokio::sync::broadcast::error::RecvError;
+
+use crate::{
+    events::GameEvent,
+    manager::{new_invite_token, GameManagerError, GameStatus},
+    state::Registry,
+};
+use entity::{game, turn, webhook, webhook_delivery};
+
+/// заголовок с подписью тела: `sha256=<hex>`
+pub const SIGNATURE_HEADER: &str = "X-Samogonki-Signature";
+/// заголовок с именем события
+pub const EVENT_HEADER: &str = "X-Samogonki-Event";
+
+/// после стольких неудачных попыток доставка бросается
+pub const MAX_ATTEMPTS: u32 = 6;
+/// пауза перед первым повтором, дальше она каждый раз удваивается
+const FIRST_RETRY: Duration = Duration::from_secs(30);
+/// сколько вебхуков может завести один пользователь
+pub const MAX_WEBHOOKS: u64 = 5;
+/// сколько последних доставок показывать в журнале
+pub const LOG_LIMIT: u64 = 50;
+
+/// как часто проверять доставки, которые пора отправить
+const TICK: Duration = Duration::from_secs(5);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// сколько доставок отправляется одновременно
+const PARALLEL_DELIVERIES: usize = 8;
+
+/// куда можно отправлять вебхуки
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Targets {
+    /// только на внешние адреса
+    #[default]
+    Public,
+    /// куда угодно, в том числе на локальные адреса: для тестов и разработки
+    Any,
+}
+
+#[derive(Debug, ::thiserror::Error)]
+pub enum WebhookError {
+    #[error("Webhook `{0}` not found")]
+    NotFound(u32),
+    #[error("`{0}` is not a valid http(s) url")]
+    InvalidUrl(String),
+    #[error("`{0}` points to a local or private address")]
+    ForbiddenTarget(String),
+    #[error("No more than {0} webhooks per user")]
+    TooMany(u64),
+    #[error("DbErr: `{0}`")]
+    DbErr(#[from] DbErr),
+}
+
+/// тело запроса к вебхуку
+#[derive(Debug, Serialize)]
+pub struct Payload<'a> {
+    pub event: &'static str,
+    pub game: &'a game::Model,
+    pub turns: Vec<&'a turn::Model>,
+}
+
+/// подпись тела запроса секретом вебхука
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(body);
+
+    format!("sha256={}", ::hex::encode(mac.finalize().into_bytes()))
+}
+
+/// Пауза перед следующей попыткой после `attempts` неудачных; `None`, если попытки кончились.
+pub fn retry_delay(attempts: u32) -> Option<::chrono::Duration> {
+    if attempts == 0 || attempts >= MAX_ATTEMPTS {
+        return None;
+    }
+
+    let delay = FIRST_RETRY * 2u32.pow(attempts - 1);
+    ::chrono::Duration::from_std(delay).ok()
+}
+
+/// Адрес снаружи: не сам сервер, не link-local и не из частных сетей.
+pub fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                || ip.is_documentation()
+                // общее адресное пространство провайдеров, 100.64.0.0/10
+                || (a == 100 && b & 0xc0 == 64))
+        }
+        IpAddr::V6(ip) => {
+            if let Some(ip) = ip.to_ipv4_mapped() {
+                return is_public(ip.into());
+            }
+            let first = ip.segments()[0];
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                // уникальные локальные fc00::/7 и link-local fe80::/10
+                || first & 0xfe00 == 0xfc00
+                || first & 0xffc0 == 0xfe80)
+        }
+    }
+}
+
+/// Проверяет, что все адреса хоста из `url` внешние.
+async fn check_target(url: &Url) -> Result<(), WebhookError> {
+    let forbidden = || WebhookError::ForbiddenTarget(url.to_string());
+
+    let host = url.host_str().ok_or_else(forbidden)?;
+    // IPv6 в адресе записывается в квадратных скобках
+    let bare = host.trim_matches(|c| c == '[' || c == ']');
+    let addrs = match bare.parse::<IpAddr>() {
+        Ok(ip) => vec![ip],
+        Err(_) => {
+            let port = url.port_or_known_default().unwrap_or(80);
+            ::tokio::net::lookup_host((host, port))
+                .await
+                .map_err(|_| WebhookError::InvalidUrl(url.to_string()))?
+                .map(|addr| addr.ip())
+                .collect()
+        }
+    };
+
+    if addrs.is_empty() || !addrs.into_iter().all(is_public) {
+        Err(forbidden())?
+    }
+
+    Ok(())
+}
+
+/// Заводит вебхук пользователю, секрет для подписи генерируется.
+pub async fn create<C: ConnectionTrait>(
+    db: &C,
+    owner_id: u32,
+    url: &str,
+    targets: Targets,
+) -> Result<webhook::Model, WebhookError> {
+    use ActiveValue::*;
+
+    let url = url.trim();
+    let parsed = match Url::parse(url) {
+        Ok(u) if matches!(u.scheme(), "http" | "https") => u,
+        _ => Err(WebhookError::InvalidUrl(url.to_owned()))?,
+    };
+    if targets == Targets::Public {
+        check_target(&parsed).await?;
+    }
+
+    let count = webhook::Entity::find()
+        .filter(webhook::Column::OwnerId.eq(owner_id))
+        .count(db)
+        .await?;
+    if count >= MAX_WEBHOOKS {
+        Err(WebhookError::TooMany(MAX_WEBHOOKS))?
+    }
+
+    let hook = webhook::ActiveModel {
+        owner_id: Set(owner_id),
+        url: Set(url.to_owned()),
+        secret: Set(new_invite_token()),
+        ..Default::default()
+    }
+    .insert(db)
+    .await?;
+
+    Ok(hook)
+}
+
+/// удаляет вебхук пользователя вместе с журналом его доставок
+pub async fn delete<C: ConnectionTrait>(
+    db: &C,
+    owner_id: u32,
+    id: u32,
+) -> Result<(), WebhookError> {
+    let hook = webhook::Entity::find_by_id(id)
+        .filter(webhook::Column::OwnerId.eq(owner_id))
+        .one(db)
+        .await?
+        .ok_or(WebhookError::NotFound(id))?;
+
+    webhook_delivery::Entity::delete_many()
+        .filter(webhook_delivery::Column::WebhookId.eq(hook.id))
+        .exec(db)
+        .await?;
+    hook.delete(db).await?;
+
+    Ok(())
+}
+
+/// вебхуки пользователя и последние доставки на них, новые первыми
+pub async fn list<C: ConnectionTrait>(
+    db: &C,
+    owner_id: u32,
+) -> Result<(Vec<webhook::Model>, Vec<webhook_delivery::Model>), DbErr> {
+    let hooks = webhook::Entity::find()
+        .filter(webhook::Column::OwnerId.eq(owner_id))
+        .order_by_asc(webhook::Column::Id)
+        .all(db)
+        .await?;
+
+    let deliveries = webhook_delivery::Entity::find()
+        .filter(webhook_delivery::Column::WebhookId.is_in(hooks.iter().map(|h| h.id)))
+        .order_by_desc(webhook_delivery::Column::Id)
+        .limit(LOG_LIMIT)
+        .all(db)
+        .await?;
+
+    Ok((hooks, deliveries))
+}
+
+/// Ставит событие игры в очередь доставки на вебхуки её владельца и игроков.
+///
+/// Приватные игры наружу не отправляются, о шаге сообщается один раз, когда сходили все.
+/// Ходы открытого шага в тело не попадают: по ним можно было бы подсмотреть чужой ход.
+pub async fn on_event(reg: &Registry, event: &GameEvent) -> Result<(), GameManagerError> {
+    use ActiveValue::*;
+
+    let name = match *event {
+        GameEvent::GameCreated { .. } => "game_created",
+        GameEvent::PlayerJoined { .. } => "player_joined",
+        GameEvent::TurnSaved { .. } => "step_completed",
+        GameEvent::StatusChanged {
+            status: GameStatus::Finished,
+            ..
+        } => "game_finished",
+        _ => return Ok(()),
+    };
+
+    let game_id = event.game_id();
+    let manager = reg.games.load_game(&reg.db, game_id).await?;
+    if manager.game.is_private {
+        return Ok(());
+    }
+
+    // ролей у пользователей нет, поэтому о чужих играх не узнаёт никто
+    let mut members = manager
+        .turns
+        .iter()
+        .filter(|(t, _)| !t.is_robot)
+        .map(|(_, u)| u.id)
+        .collect::<Vec<_>>();
+    members.push(manager.game.owner_id);
+
+    let hooks = webhook::Entity::find()
+        .filter(webhook::Column::OwnerId.is_in(members))
+        .all(&reg.db)
+        .await?;
+    if hooks.is_empty() {
+        return Ok(());
+    }
+
+    let step_number = match *event {
+        GameEvent::TurnSaved { step_number, .. } => {
+            // шаг ещё не закрыт или о нём уже сообщили по одному из прошлых ходов
+            let sent = webhook_delivery::Entity::find()
+                .filter(webhook_delivery::Column::GameId.eq(game_id))
+                .filter(webhook_delivery::Column::Event.eq(name))
+                .filter(webhook_delivery::Column::StepNumber.eq(step_number))
+                .count(&reg.db)
+                .await?;
+            if manager.move_cnt() < step_number || sent > 0 {
+                return Ok(());
+            }
+            step_number
+        }
+        _ => 0,
+    };
+
+    let payload = Payload {
+        event: name,
+        game: &manager.game,
+        turns: manager
+            .turns
+            .iter()
+            .map(|(t, _)| t)
+            .filter(|t| t.step_number <= manager.move_cnt())
+            .collect(),
+    };
+    let payload = ::serde_json::to_string(&payload).expect("payload is always serializable");
+    let now = ::chrono::Utc::now().naive_utc();
+
+    webhook_delivery::Entity::insert_many(hooks.into_iter().map(|hook| {
+        webhook_delivery::ActiveModel {
+            webhook_id: Set(hook.id),
+            game_id: Set(game_id),
+            step_number: Set(step_number),
+            event: Set(name.to_owned()),
+            payload: Set(payload.clone()),
+            next_attempt_at: Set(Some(now)),
+            ..Default::default()
+        }
+    }))
+    .exec(&reg.db)
+    .await?;
+
+    Ok(())
+}
+
+/// Резолвер, который не отдаёт локальные и частные адреса:
+/// имя вебхука могут перенаправить на них уже после его проверки.
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let addrs = ::tokio::net::lookup_host((name.as_str(), 0))
+                .await?
+                .filter(|addr| is_public(addr.ip()))
+                .collect::<Vec<_>>();
+            if addrs.is_empty() {
+                Err(format!("`{}` has no public addresses", name.as_str()))?
+            }
+
+            let addrs: Addrs = Box::new(addrs.into_iter());
+            Ok::<_, Box<dyn std::error::Error + Send + Sync>>(addrs)
+        })
+    }
+}
+
+/// HTTP-клиент для доставок
+pub fn client(targets: Targets) -> Client {
+    // перенаправление увело бы запрос мимо проверки адреса
+    let builder = Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .redirect(Policy::none());
+    let builder = match targets {
+        Targets::Public => builder.dns_resolver(Arc::new(PublicResolver)),
+        Targets::Any => builder,
+    };
+
+    builder
+        .build()
+        .expect("HTTP client with default TLS settings")
+}
+
+/// отправляет доставки, время которых подошло, по [`PARALLEL_DELIVERIES`] за раз
+pub async fn deliver_due(db: &DbConn, client: &Client) -> Result<(), DbErr> {
+    let due = webhook_delivery::Entity::find()
+        .find_also_related(webhook::Entity)
+        .filter(webhook_delivery::Column::NextAttemptAt.lte(::chrono::Utc::now().naive_utc()))
+        .order_by_asc(webhook_delivery::Column::Id)
+        .all(db)
+        .await?;
+
+    stream::iter(due.into_iter().map(Ok))
+        .try_for_each_concurrent(PARALLEL_DELIVERIES, |(delivery, hook)| async move {
+            match hook {
+                Some(hook) => deliver(db, client, &hook, delivery).await,
+                None => Ok(()),
+            }
+        })
+        .await
+}
+
+/// одна попытка доставки с записью её итога в журнал
+async fn deliver(
+    db: &DbConn,
+    client: &Client,
+    hook: &webhook::Model,
+    delivery: webhook_delivery::Model,
+) -> Result<(), DbErr> {
+    use ActiveValue::*;
+
+    let response = client
+        .post(&hook.url)
+        .header(CONTENT_TYPE, "application/json")
+        .header(EVENT_HEADER, &delivery.event)
+        .header(
+            SIGNATURE_HEADER,
+            sign(&hook.secret, delivery.payload.as_bytes()),
+        )
+        .body(delivery.payload.clone())
+        .send()
+        .await;
+
+    let (status_code, error) = match response {
+        Ok(r) if r.status().is_success() => (Some(u32::from(r.status().as_u16())), None),
+        Ok(r) => (
+            Some(u32::from(r.status().as_u16())),
+            Some(format!("unexpected status {}", r.status())),
+        ),
+        Err(e) => (None, Some(e.to_string())),
+    };
+
+    let attempts = delivery.attempts + 1;
+    let now = ::chrono::Utc::now().naive_utc();
+    let (delivered_at, next_attempt_at) = match error {
+        None => (Some(now), None),
+        Some(_) => (None, retry_delay(attempts).map(|delay| now + delay)),
+    };
+
+    webhook_delivery::ActiveModel {
+        id: Unchanged(delivery.id),
+        attempts: Set(attempts),
+        status_code: Set(status_code),
+        error: Set(error),
+        delivered_at: Set(delivered_at),
+        next_attempt_at: Set(next_attempt_at),
+        ..Default::default()
+    }
+    .update(db)
+    .await?;
+
+    Ok(())
+}
+
+/// Фоновая задача: ставит события игр в очередь доставки.
+///
+/// Сами доставки идут в отдельной задаче, чтобы медленные адресаты
+/// не задерживали разбор событий.
+pub async fn run(reg: Registry) {
+    ::actix_web::rt::spawn(deliver_loop(Registry::clone(&reg)));
+
+    let mut events = reg.events.subscribe();
+
+    loop {
+        match events.recv().await {
+            Ok(event) => {
+                if let Err(e) = on_event(&reg, &event).await {
+                    error!("webhooks: game {}: {e}", event.game_id());
+                }
+            }
+            Err(RecvError::Lagged(n)) => warn!("webhooks: {n} events skipped"),
+            Err(RecvError::Closed) => break,
+        }
+    }
+}
+
+/// раз в [`TICK`] отправляет доставки, время которых подошло
+async fn deliver_loop(reg: Registry) {
+    let client = client(reg.webhook_targets);
+    let mut interval = ::tokio::time::interval(TICK);
+
+    loop {
+        interval.tick().await;
+
+        if let Err(e) = deliver_due(&reg.db, &client).await {
+            error!("webhooks: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retry_delay_doubles() {
+        let secs = |attempts| retry_delay(attempts).map(|d| d.num_seconds());
+
+        assert_eq!(secs(1), Some(30));
+        assert_eq!(secs(2), Some(60));
+        assert_eq!(secs(5), Some(480));
+        assert_eq!(secs(MAX_ATTEMPTS), None);
+    }
+
+    #[test]
+    fn only_public_targets() {
+        let public = |ip: &str| is_public(ip.parse().unwrap());
+
+        assert!(public("93.184.216.34"));
+        assert!(public("2606:2800:220:1:248:1893:25c8:1946"));
+
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+        ] {
+            assert!(!public(ip), "{ip}");
+        }
+    }
+
+    #[test]
+    fn sign_is_hmac_sha256() {
+        // RFC 4231, test case 2
+        assert_eq!(
+            sign("Jefe", b"what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+}
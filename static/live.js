@@ -0,0 +1,45 @@
+// Перерисовывает блок `#live` страницы при каждом событии из SSE-потока `url`.
+function liveUpdate(url) {
+    const events = ["game_created", "turn_saved", "player_joined", "player_left", "status_changed", "pause_changed"];
+    const source = new EventSource(url);
+
+    // события, пришедшие во время загрузки, приводят к ещё одной загрузке после неё
+    let running = false;
+    let queued = false;
+
+    const reload = async () => {
+        const resp = await fetch(window.location.href, { headers: { "Accept": "text/html" } });
+        if (!resp.ok) {
+            return;
+        }
+
+        const doc = new DOMParser().parseFromString(await resp.text(), "text/html");
+        const fresh = doc.getElementById("live");
+        const current = document.getElementById("live");
+        if (fresh && current) {
+            current.replaceWith(fresh);
+        }
+    };
+
+    const refresh = async () => {
+        if (running) {
+            queued = true;
+            return;
+        }
+
+        running = true;
+        do {
+            queued = false;
+            try {
+                await reload();
+            } catch (e) {
+                console.warn("live update failed", e);
+            }
+        } while (queued);
+        running = false;
+    };
+
+    for (const name of events) {
+        source.addEventListener(name, refresh);
+    }
+}
@@ -9,7 +9,32 @@ pub use ::tokio::sync::Mutex;
 
 pub use crate::main::{data::Player, data::PlayerTurnInfo};
 
+#[allow(unused)]
 pub mod tapi;
 
 #[allow(unused)]
 pub const TEST_URL_GET_GAME: &str = "/test/get-game";
+
+/// пользователи с id от 1 до `count`
+#[allow(unused)]
+pub async fn seed_users(
+    db: &::sea_orm::DbConn,
+    count: u32,
+) -> Result<Vec<entity::user::Model>, ::sea_orm::DbErr> {
+    use ::sea_orm::{ActiveModelTrait, ActiveValue::*};
+
+    let mut users = vec![];
+    for id in 1..=count {
+        let user = entity::user::ActiveModel {
+            id: Set(id),
+            steam_id: Set(id as i64 * 111),
+            login: Set(Some(format!("player{id}"))),
+            ..Default::default()
+        }
+        .insert(db)
+        .await?;
+        users.push(user);
+    }
+
+    Ok(users)
+}
@@ -10,6 +10,14 @@ pub async fn setup_schema(db: &DbConn) -> Result<(), DbErr> {
         schema.create_table_from_entity(Game),
         // schema.create_table_from_entity(Player),
         schema.create_table_from_entity(Turn),
+        schema.create_table_from_entity(Invite),
+        schema.create_table_from_entity(Tournament),
+        schema.create_table_from_entity(TournamentParticipant),
+        schema.create_table_from_entity(TournamentGame),
+        schema.create_table_from_entity(Vote),
+        schema.create_table_from_entity(Notification),
+        schema.create_table_from_entity(Webhook),
+        schema.create_table_from_entity(WebhookDelivery),
     ];
 
     for stmt in stmts {
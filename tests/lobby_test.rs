@@ -0,0 +1,297 @@
+#![allow(unused_imports)]
+
+extern crate actix_web as aw;
+
+#[macro_use]
+#[path = "../src/main.rs"]
+mod main;
+pub use main::*;
+
+mod db;
+
+use entity::game::GameType;
+use main::manager::{GameManager, GameManagerError, GameStatus};
+use sea_orm::{ActiveModelTrait, ActiveValue, Database, DbConn, DbErr, EntityTrait};
+
+async fn seed_open_game(db: &DbConn) -> Result<(), DbErr> {
+    use ActiveValue::*;
+
+    for id in 1..=4 {
+        entity::user::ActiveModel {
+            id: Set(id),
+            steam_id: Set(id as i64 * 111),
+            login: Set(Some(format!("player{id}"))),
+            ..Default::default()
+        }
+        .insert(db)
+        .await?;
+    }
+
+    entity::game::ActiveModel {
+        id: Set(1),
+        owner_id: Set(1),
+        world_id: Set(0),
+        track_id: Set(0),
+        rnd: Set(12711),
+        game_type: Set(GameType::All),
+        laps: Set(1),
+        seeds: Set(100),
+        duration: Set(10),
+        is_express: Set(true),
+        players_cnt: Set(5),
+        ..Default::default()
+    }
+    .insert(db)
+    .await?;
+
+    entity::turn::ActiveModel {
+        game_id: Set(1),
+        user_id: Set(1),
+        player_number: Set(0),
+        step_number: Set(1),
+        ..Default::default()
+    }
+    .insert(db)
+    .await?;
+
+    Ok(())
+}
+
+/// номера игроков в порядке возрастания вместе с id пользователей
+async fn player_numbers(db: &DbConn) -> Vec<(u32, u32)> {
+    let manager = GameManager::load_game(db, 1).await.unwrap();
+    let mut numbers = manager
+        .turns
+        .iter()
+        .map(|(t, u)| (t.player_number, u.id))
+        .collect::<Vec<_>>();
+    numbers.sort();
+    numbers
+}
+
+#[actix_web::test]
+async fn test_leave_and_kick_renumber_players() {
+    let db = Database::connect("sqlite::memory:").await.unwrap();
+    db::setup_schema(&db).await.unwrap();
+    seed_open_game(&db).await.unwrap();
+
+    for user_id in 2..=4 {
+        let user = entity::user::Entity::find_by_id(user_id)
+            .one(&db)
+            .await
+            .unwrap()
+            .unwrap();
+        let mut manager = GameManager::load_game(&db, 1).await.unwrap();
+        manager.join(&user).await.unwrap();
+
+        assert!(matches!(
+            manager.join(&user).await,
+            Err(GameManagerError::AlreadyJoined(id)) if id == user_id
+        ));
+    }
+    assert_eq!(
+        player_numbers(&db).await,
+        vec![(0, 1), (1, 2), (2, 3), (3, 4)]
+    );
+
+    let mut manager = GameManager::load_game(&db, 1).await.unwrap();
+    manager.leave(2).await.unwrap();
+    assert_eq!(player_numbers(&db).await, vec![(0, 1), (1, 3), (2, 4)]);
+
+    let mut manager = GameManager::load_game(&db, 1).await.unwrap();
+    assert!(matches!(
+        manager.leave(1).await,
+        Err(GameManagerError::OwnerCannotLeave)
+    ));
+    assert!(matches!(
+        manager.leave(2).await,
+        Err(GameManagerError::NotJoined(2))
+    ));
+    manager.leave(4).await.unwrap();
+    assert_eq!(player_numbers(&db).await, vec![(0, 1), (1, 3)]);
+
+    // вернувшийся игрок получает следующий свободный номер
+    let user = entity::user::Entity::find_by_id(2)
+        .one(&db)
+        .await
+        .unwrap()
+        .unwrap();
+    let mut manager = GameManager::load_game(&db, 1).await.unwrap();
+    manager.join(&user).await.unwrap();
+    assert_eq!(player_numbers(&db).await, vec![(0, 1), (1, 3), (2, 2)]);
+}
+
+#[actix_web::test]
+async fn test_cancelled_game_is_closed() {
+    let db = Database::connect("sqlite::memory:").await.unwrap();
+    db::setup_schema(&db).await.unwrap();
+    seed_open_game(&db).await.unwrap();
+
+    let mut manager = GameManager::load_game(&db, 1).await.unwrap();
+    manager.cancel().await.unwrap();
+
+    let mut manager = GameManager::load_game(&db, 1).await.unwrap();
+    assert_eq!(manager.status(), GameStatus::Cancelled);
+
+    let user = entity::user::Entity::find_by_id(2)
+        .one(&db)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(matches!(
+        manager.join(&user).await,
+        Err(GameManagerError::GameNotOpen(1))
+    ));
+    assert!(matches!(
+        manager.cancel().await,
+        Err(GameManagerError::GameNotOpen(1))
+    ));
+}
+
+#[actix_web::test]
+async fn test_private_game_is_hidden() {
+    use aw::{http::StatusCode, test, web::Data};
+    use main::state::Registry;
+    use ActiveValue::*;
+
+    let db = Database::connect("sqlite::memory:").await.unwrap();
+    db::setup_schema(&db).await.unwrap();
+    seed_open_game(&db).await.unwrap();
+
+    entity::game::ActiveModel {
+        id: Unchanged(1),
+        is_private: Set(true),
+        invite_token: Set(Some("secret".into())),
+        ..Default::default()
+    }
+    .update(&db)
+    .await
+    .unwrap();
+
+    let registry = Data::new(Registry {
+        steam_key: None,
+        db,
+        ..Default::default()
+    });
+
+    let app = app!().app_data(Data::clone(&registry));
+    let srv = test::init_service(app).await;
+
+    // без ссылки-приглашения игры нет ни на одной из её страниц
+    for (uri, with_token) in [
+        ("/games/1", StatusCode::OK),
+        ("/games/1/events", StatusCode::OK),
+        ("/games/1/steps.svg", StatusCode::OK),
+        ("/games/1/replay.json", StatusCode::CONFLICT),
+        ("/game-on-line/default.asp?ID=1&USERID=0", StatusCode::OK),
+    ] {
+        let req = test::TestRequest::get().uri(uri).to_request();
+        let resp = test::call_service(&srv, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND, "{uri}");
+
+        let sep = if uri.contains('?') { '&' } else { '?' };
+        let param = if uri.starts_with("/game-on-line") {
+            "TOKEN"
+        } else {
+            "token"
+        };
+        let req = test::TestRequest::get()
+            .uri(&format!("{uri}{sep}{param}=secret"))
+            .to_request();
+        let resp = test::call_service(&srv, req).await;
+        assert_eq!(resp.status(), with_token, "{uri}");
+    }
+
+    // ход и опрос по угаданным номерам игры и игрока тоже требуют приглашения
+    for payload in [
+        "KDLAB;104;3;1;0;0;0;password;0;0;12711;A;1;100;10;0;2;1;Y;;0;;;1;1;0;N;0;0;0;0;0;0;0;14;620#402#51#-1#913#303#51#-1#1190#293#51#-1#1497#402#51#-1#1771#578#51#-1#1955#970#48#-1#1853#1225#48#-1#1727#1506#51#-1#1460#1766#102#-1#1105#3#102#-1#647#39#102#-1#533#1802#102#-1#353#1499#48#-1#211#1059#48#-1;BITRIX",
+        "KDLAB;104;6;1;0;0;0;password;0;0;12711;A;1;100;10;0;2;0;Y;;0;;;BITRIX;0;0;0;0;0;0;0;14;620#402#51#-1#913#303#51#-1#1190#293#51#-1#1497#402#51#-1#1771#578#51#-1#1955#970#48#-1#1853#1225#48#-1#1727#1506#51#-1#1460#1766#102#-1#1105#3#102#-1#647#39#102#-1#533#1802#102#-1#353#1499#48#-1#211#1059#48#-1;BITRIX",
+    ] {
+        let req = test::TestRequest::post()
+            .uri("/game-on-line/default.asp")
+            .set_payload(payload)
+            .to_request();
+        let resp = test::call_service(&srv, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND, "{payload}");
+
+        let req = test::TestRequest::post()
+            .uri("/game-on-line/default.asp?TOKEN=secret")
+            .set_payload(payload)
+            .to_request();
+        let resp = test::call_service(&srv, req).await;
+        assert_ne!(resp.status(), StatusCode::NOT_FOUND, "{payload}");
+    }
+}
+
+#[actix_web::test]
+async fn test_full_game_is_open_until_it_starts() {
+    use aw::{test, web::Data};
+    use main::state::Registry;
+    use ActiveValue::*;
+
+    let db = Database::connect("sqlite::memory:").await.unwrap();
+    db::setup_schema(&db).await.unwrap();
+    seed_open_game(&db).await.unwrap();
+
+    // все места заняты, но время начала ещё не наступило
+    entity::game::ActiveModel {
+        id: Unchanged(1),
+        players_cnt: Set(2),
+        starts_at: Set(Some(
+            ::chrono::Utc::now().naive_utc() + ::chrono::Duration::hours(1),
+        )),
+        ..Default::default()
+    }
+    .update(&db)
+    .await
+    .unwrap();
+    entity::turn::ActiveModel {
+        game_id: Set(1),
+        user_id: Set(2),
+        player_number: Set(1),
+        step_number: Set(1),
+        ..Default::default()
+    }
+    .insert(&db)
+    .await
+    .unwrap();
+
+    let registry = Data::new(Registry {
+        steam_key: None,
+        db: db.clone(),
+        ..Default::default()
+    });
+
+    let app = app!().app_data(Data::clone(&registry));
+    let srv = test::init_service(app).await;
+
+    let listed = |status: &'static str| {
+        let req = test::TestRequest::get()
+            .uri(&format!("/games?status={status}"))
+            .to_request();
+        let srv = &srv;
+        async move {
+            let body = test::call_and_read_body(srv, req).await;
+            String::from_utf8(body.to_vec())
+                .unwrap()
+                .contains("href=\"/games/1\"")
+        }
+    };
+
+    assert!(listed("open").await);
+    assert!(!listed("started").await);
+
+    entity::game::ActiveModel {
+        id: Unchanged(1),
+        starts_at: Set(Some(
+            ::chrono::Utc::now().naive_utc() - ::chrono::Duration::hours(1),
+        )),
+        ..Default::default()
+    }
+    .update(&db)
+    .await
+    .unwrap();
+
+    assert!(!listed("open").await);
+    assert!(listed("started").await);
+}
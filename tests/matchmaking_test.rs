@@ -0,0 +1,93 @@
+#![allow(unused_imports)]
+
+extern crate actix_web as aw;
+
+#[macro_use]
+#[path = "../src/main.rs"]
+mod main;
+pub use main::*;
+
+mod db;
+
+mod common;
+use common::seed_users;
+
+use entity::game::GameType;
+use main::{
+    events::GameEvent,
+    manager::{GameManager, GameStatus},
+    matchmaking::{self, Preferences, QueueStatus, Ticket, DEFAULT_RATING},
+    state::Registry,
+};
+use sea_orm::{ActiveModelTrait, ActiveValue, Database, DbConn, DbErr, EntityTrait};
+
+fn prefs(players_cnt: u32) -> Preferences {
+    Preferences {
+        players_cnt,
+        game_type: GameType::Winner,
+        laps: 1,
+        seeds: 10,
+        duration: 100,
+        rating_range: None,
+    }
+}
+
+#[actix_web::test]
+async fn test_queue_creates_game() {
+    let db = Database::connect("sqlite::memory:").await.unwrap();
+    db::setup_schema(&db).await.unwrap();
+    let users = seed_users(&db, 3).await.unwrap();
+    let reg = Registry {
+        db: db.clone(),
+        ..Default::default()
+    };
+    let mut events = reg.events.subscribe();
+
+    assert_eq!(matchmaking::rating(&db, 1).await.unwrap(), DEFAULT_RATING);
+
+    // игроки с другими настройками друг другу не подходят
+    let ticket = Ticket::new(users[0].clone(), prefs(2), DEFAULT_RATING);
+    assert_eq!(matchmaking::enqueue(&reg, ticket).await.unwrap(), None);
+    let ticket = Ticket::new(users[1].clone(), prefs(3), DEFAULT_RATING);
+    assert_eq!(matchmaking::enqueue(&reg, ticket).await.unwrap(), None);
+
+    let ticket = Ticket::new(users[2].clone(), prefs(2), DEFAULT_RATING);
+    let game_id = matchmaking::enqueue(&reg, ticket).await.unwrap().unwrap();
+
+    let manager = GameManager::load_game(&db, game_id).await.unwrap();
+    assert_eq!(manager.status(), GameStatus::Started);
+    assert_eq!(manager.game.owner_id, 1);
+    let mut players = manager
+        .turns
+        .iter()
+        .map(|(t, u)| (t.player_number, u.id))
+        .collect::<Vec<_>>();
+    players.sort();
+    assert_eq!(players, vec![(0, 1), (1, 3)]);
+
+    assert!(matches!(reg.matchmaking.status(1), QueueStatus::Matched(id) if id == game_id));
+    assert!(matches!(reg.matchmaking.status(3), QueueStatus::Matched(id) if id == game_id));
+    assert!(matches!(
+        reg.matchmaking.status(2),
+        QueueStatus::Waiting(_, 0)
+    ));
+
+    assert_eq!(
+        events.recv().await.unwrap(),
+        GameEvent::GameCreated { game_id }
+    );
+    assert_eq!(
+        events.recv().await.unwrap(),
+        GameEvent::PlayerJoined {
+            game_id,
+            user_id: 3
+        }
+    );
+    assert_eq!(
+        events.recv().await.unwrap(),
+        GameEvent::StatusChanged {
+            game_id,
+            status: GameStatus::Started
+        }
+    );
+}
@@ -0,0 +1,141 @@
+#![allow(unused_imports)]
+
+extern crate actix_web as aw;
+
+#[macro_use]
+#[path = "../src/main.rs"]
+mod main;
+pub use main::*;
+
+mod db;
+
+mod common;
+use common::seed_users;
+
+use entity::{
+    game::{GameType, World},
+    notification::NotificationKind,
+};
+use main::{
+    events::GameEvent,
+    manager::{GameManager, GameStatus},
+    notifications,
+    state::Registry,
+};
+use sea_orm::{ActiveModelTrait, ActiveValue, Database, DbConn, DbErr, EntityTrait};
+
+/// начатая игра на двоих
+async fn started_game(db: &DbConn, users: &[entity::user::Model], is_express: bool) -> u32 {
+    let mut game = entity::game::Model::new(
+        users[0].id,
+        World::Mountain(0),
+        GameType::Winner,
+        1,
+        10,
+        1,
+        2,
+    );
+    game.is_express = is_express;
+
+    let mut manager = GameManager::create(db, game, &users[0], users[0].loadout())
+        .await
+        .unwrap();
+    manager.join(&users[1]).await.unwrap();
+    assert_eq!(manager.status(), GameStatus::Started);
+
+    manager.game.id
+}
+
+/// (пользователь, вид, шаг) всех уведомлений по игре
+async fn sent(db: &DbConn, game_id: u32) -> Vec<(u32, NotificationKind, u32)> {
+    let mut res = entity::notification::Entity::find()
+        .all(db)
+        .await
+        .unwrap()
+        .into_iter()
+        .filter(|n| n.game_id == game_id)
+        .map(|n| (n.user_id, n.kind, n.step_number))
+        .collect::<Vec<_>>();
+    res.sort_by_key(|&(user_id, kind, step)| (user_id, kind as i32, step));
+    res
+}
+
+#[actix_web::test]
+async fn test_game_started_and_read() {
+    let db = Database::connect("sqlite::memory:").await.unwrap();
+    db::setup_schema(&db).await.unwrap();
+    let users = seed_users(&db, 2).await.unwrap();
+    let reg = Registry {
+        db: db.clone(),
+        ..Default::default()
+    };
+
+    let game_id = started_game(&db, &users, true).await;
+    let event = GameEvent::StatusChanged {
+        game_id,
+        status: GameStatus::Started,
+    };
+    notifications::on_event(&reg, &event).await.unwrap();
+    // повторное событие второго уведомления не создаёт
+    notifications::on_event(&reg, &event).await.unwrap();
+
+    assert_eq!(
+        sent(&db, game_id).await,
+        vec![
+            (1, NotificationKind::GameStarted, 0),
+            (2, NotificationKind::GameStarted, 0),
+        ]
+    );
+    assert_eq!(notifications::unread(&db, 1).await.unwrap(), 1);
+
+    notifications::mark_read(&db, 1, None).await.unwrap();
+    assert_eq!(notifications::unread(&db, 1).await.unwrap(), 0);
+    assert_eq!(notifications::unread(&db, 2).await.unwrap(), 1);
+
+    let list = notifications::list(&db, 2).await.unwrap();
+    notifications::mark_read(&db, 2, Some(list[0].id))
+        .await
+        .unwrap();
+    assert_eq!(notifications::unread(&db, 2).await.unwrap(), 0);
+}
+
+#[actix_web::test]
+async fn test_deadline_near() {
+    use ActiveValue::*;
+
+    let db = Database::connect("sqlite::memory:").await.unwrap();
+    db::setup_schema(&db).await.unwrap();
+    let users = seed_users(&db, 2).await.unwrap();
+    let reg = Registry {
+        db: db.clone(),
+        ..Default::default()
+    };
+
+    let express = started_game(&db, &users, true).await;
+    let game_id = started_game(&db, &users, false).await;
+
+    // из часа на ход осталось больше четверти
+    let set_step_started = |minutes_ago| entity::game::ActiveModel {
+        id: Unchanged(game_id),
+        step_started_at: Set(Some(
+            chrono::Utc::now().naive_utc() - chrono::Duration::minutes(minutes_ago),
+        )),
+        ..Default::default()
+    };
+    set_step_started(30).update(&db).await.unwrap();
+    notifications::check_deadlines(&reg).await.unwrap();
+    assert!(sent(&db, game_id).await.is_empty());
+
+    reg.games.invalidate(game_id);
+    set_step_started(50).update(&db).await.unwrap();
+    notifications::check_deadlines(&reg).await.unwrap();
+    notifications::check_deadlines(&reg).await.unwrap();
+    assert_eq!(
+        sent(&db, game_id).await,
+        vec![
+            (1, NotificationKind::DeadlineNear, 1),
+            (2, NotificationKind::DeadlineNear, 1),
+        ]
+    );
+    assert!(sent(&db, express).await.is_empty());
+}
@@ -0,0 +1,124 @@
+#![allow(unused_imports)]
+
+extern crate actix_web as aw;
+
+#[macro_use]
+#[path = "../src/main.rs"]
+mod main;
+pub use main::*;
+
+mod db;
+
+use entity::game::{GameType, World};
+use main::{
+    data::{KdlabCodec, PacketType},
+    manager::{GameManager, GameManagerError},
+    pause,
+};
+use sea_orm::{
+    ActiveModelTrait, ActiveValue, Database, DbConn, DbErr, EntityTrait, PaginatorTrait,
+    TransactionTrait,
+};
+
+async fn seed_game(db: &DbConn) -> Result<u32, DbErr> {
+    use ActiveValue::*;
+
+    let mut users = vec![];
+    for id in 1..=3 {
+        let user = entity::user::ActiveModel {
+            id: Set(id),
+            steam_id: Set(id as i64 * 111),
+            login: Set(Some(format!("player{id}"))),
+            ..Default::default()
+        }
+        .insert(db)
+        .await?;
+        users.push(user);
+    }
+
+    let game = entity::game::Model::new(1, World::Mountain(0), GameType::Winner, 1, 10, 100, 3);
+    let mut manager = GameManager::create(db, game, &users[0], users[0].loadout())
+        .await
+        .unwrap();
+    for user in &users[1..] {
+        manager.join(user).await.unwrap();
+    }
+
+    Ok(manager.game.id)
+}
+
+/// голосует в отдельной транзакции, как это делает обработчик
+async fn vote(db: &DbConn, game_id: u32, user_id: u32, paused: bool) -> bool {
+    let txn = db.begin().await.unwrap();
+    let mut manager = GameManager::lock_game(&txn, game_id).await.unwrap();
+    let changed = pause::vote(&txn, &mut manager, user_id, paused)
+        .await
+        .unwrap();
+    txn.commit().await.unwrap();
+
+    changed
+}
+
+#[actix_web::test]
+async fn test_pause_by_majority() {
+    let db = Database::connect("sqlite::memory:").await.unwrap();
+    db::setup_schema(&db).await.unwrap();
+    let game_id = seed_game(&db).await.unwrap();
+
+    let manager = GameManager::load_game(&db, game_id).await.unwrap();
+    let step_started_at = manager.game.step_started_at.unwrap();
+
+    // одного голоса из трёх мало, повторный голос не считается дважды
+    assert!(!vote(&db, game_id, 1, true).await);
+    assert!(!vote(&db, game_id, 1, true).await);
+    let manager = GameManager::load_game(&db, game_id).await.unwrap();
+    let tally = pause::tally(&db, &manager).await.unwrap();
+    assert_eq!(
+        (tally.pause, tally.voters, tally.needed),
+        (true, vec![1], 2)
+    );
+
+    assert!(vote(&db, game_id, 2, true).await);
+    let mut manager = GameManager::load_game(&db, game_id).await.unwrap();
+    assert!(manager.is_paused());
+    // голоса за паузу после неё удаляются
+    assert_eq!(entity::vote::Entity::find().count(&db).await.unwrap(), 0);
+
+    let mut packet = main::data::Packet::decode("KDLAB;104;3;1;0;0;0;password;0;0;12711;A;1;100;10;0;2;1;Y;;0;;;1;1;0;N;0;0;0;0;0;0;0;2;165#741#51#-1#465#427#51#-1;BITRIX").unwrap();
+    manager.set_pid(0).unwrap();
+    manager.apply_step(&mut packet).await.unwrap();
+
+    // на паузе ходы записываются, но результаты шага выдаются только после неё
+    for (turn, _) in &manager.turns {
+        if turn.seeds.is_none() {
+            entity::turn::ActiveModel {
+                id: ActiveValue::Unchanged(turn.id),
+                seeds: ActiveValue::Set(Some(String::new())),
+                ..Default::default()
+            }
+            .update(&db)
+            .await
+            .unwrap();
+        }
+    }
+    let mut manager = GameManager::load_game(&db, game_id).await.unwrap();
+    manager.set_pid(0).unwrap();
+    assert_eq!(manager.move_cnt(), 1);
+    packet.t_type = PacketType::OG_REFRESH_PACKET;
+    packet.move_cnt = 0;
+    let answer = manager.get_refresh_packet(&packet).await.unwrap();
+    assert_eq!(answer.t_type, PacketType::OG_REFRESH_ANSWER_PACKET);
+
+    // голос за уже действующую паузу ничего не меняет
+    assert!(!vote(&db, game_id, 3, true).await);
+    assert!(!vote(&db, game_id, 3, false).await);
+    assert!(vote(&db, game_id, 1, false).await);
+
+    let mut manager = GameManager::load_game(&db, game_id).await.unwrap();
+    manager.set_pid(0).unwrap();
+    assert!(!manager.is_paused());
+    let answer = manager.get_refresh_packet(&packet).await.unwrap();
+    assert_eq!(answer.t_type, PacketType::OG_GAME_PACKET);
+    // время паузы не идёт в счёт времени на ход
+    assert!(manager.game.step_started_at.unwrap() >= step_started_at);
+}
@@ -0,0 +1,265 @@
+#![allow(unused_imports)]
+
+extern crate actix_web as aw;
+
+#[macro_use]
+#[path = "../src/main.rs"]
+mod main;
+pub use main::*;
+
+mod db;
+
+use aw::{test, web::Data};
+use entity::game::GameType;
+use main::{
+    manager::GameManager,
+    replay::{Replay, ReplayError},
+    state::Registry,
+};
+use sea_orm::{ActiveModelTrait, ActiveValue, Database, DbConn, DbErr};
+
+/// игра двух игроков, завершённая за два хода
+async fn seed_finished_game(db: &DbConn) -> Result<(), DbErr> {
+    use ActiveValue::*;
+
+    for id in 1..=2 {
+        entity::user::ActiveModel {
+            id: Set(id),
+            steam_id: Set(id as i64 * 111),
+            login: Set(Some(format!("player{id}"))),
+            ..Default::default()
+        }
+        .insert(db)
+        .await?;
+    }
+
+    entity::game::ActiveModel {
+        id: Set(1),
+        owner_id: Set(1),
+        world_id: Set(0),
+        track_id: Set(0),
+        rnd: Set(12711),
+        game_type: Set(GameType::Winner),
+        laps: Set(1),
+        seeds: Set(10),
+        duration: Set(100),
+        is_express: Set(true),
+        players_cnt: Set(2),
+        ..Default::default()
+    }
+    .insert(db)
+    .await?;
+
+    for step_number in 1..=2 {
+        for player_number in 0..2 {
+            entity::turn::ActiveModel {
+                game_id: Set(1),
+                user_id: Set(player_number + 1),
+                player_number: Set(player_number),
+                step_number: Set(step_number),
+                is_finished: Set(step_number == 2 && player_number == 0),
+                rank: Set(if step_number == 2 { player_number + 1 } else { 0 }),
+                user_seeds_cnt: Set(1),
+                seeds: Set(Some(format!("{}#{}#51#-1", step_number * 100, player_number))),
+                ..Default::default()
+            }
+            .insert(db)
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+#[actix_web::test]
+async fn test_replay_roundtrip() {
+    use ActiveValue::*;
+
+    let db = Database::connect("sqlite::memory:").await.unwrap();
+    db::setup_schema(&db).await.unwrap();
+    seed_finished_game(&db).await.unwrap();
+
+    // пока игра не завершена, запись не выгружается
+    let gm = GameManager::load_game(&db, 1).await.unwrap();
+    assert!(matches!(
+        Replay::from_game(&gm),
+        Err(ReplayError::GameNotFinished(1))
+    ));
+
+    entity::game::ActiveModel {
+        id: Unchanged(1),
+        finished_at: Set(Some(::chrono::Utc::now().naive_utc())),
+        ..Default::default()
+    }
+    .update(&db)
+    .await
+    .unwrap();
+
+    let gm = GameManager::load_game(&db, 1).await.unwrap();
+    let replay = Replay::from_game(&gm).unwrap();
+    assert_eq!(replay.players.len(), 2);
+    assert_eq!(replay.players[1].nickname, "player2");
+    assert_eq!(replay.steps.len(), 2);
+    assert_eq!(replay.steps[1][1].seeds, "200#1#51#-1");
+
+    assert_eq!(Replay::parse(&replay.to_text()).unwrap(), replay);
+    let json = ::serde_json::to_string(&replay).unwrap();
+    assert_eq!(Replay::parse(&json).unwrap(), replay);
+}
+
+#[actix_web::test]
+async fn test_replay_export() {
+    use ActiveValue::*;
+
+    let db = Database::connect("sqlite::memory:").await.unwrap();
+    db::setup_schema(&db).await.unwrap();
+    seed_finished_game(&db).await.unwrap();
+
+    let registry = Data::new(Registry {
+        steam_key: None,
+        db,
+        ..Default::default()
+    });
+
+    let app = app!().app_data(Data::clone(&registry));
+    let srv = test::init_service(app).await;
+
+    let req = test::TestRequest::get()
+        .uri("/games/1/replay.txt")
+        .to_request();
+    let resp = test::call_service(&srv, req).await;
+    assert_eq!(resp.status(), ::aw::http::StatusCode::CONFLICT);
+
+    entity::game::ActiveModel {
+        id: Unchanged(1),
+        finished_at: Set(Some(::chrono::Utc::now().naive_utc())),
+        ..Default::default()
+    }
+    .update(&registry.db)
+    .await
+    .unwrap();
+
+    let req = test::TestRequest::get()
+        .uri("/games/1/replay.txt")
+        .to_request();
+    let body = test::call_and_read_body(&srv, req).await;
+    let text = String::from_utf8(body.to_vec()).unwrap();
+    assert_eq!(text.lines().count(), 2);
+    assert!(text.starts_with("KDLAB;104;1;1;"));
+
+    let req = test::TestRequest::get()
+        .uri("/games/1/replay.json")
+        .to_request();
+    let body = test::call_and_read_body(&srv, req).await;
+    let replay = Replay::parse(std::str::from_utf8(&body).unwrap()).unwrap();
+    assert_eq!(Replay::parse(&text).unwrap(), replay);
+}
+
+#[actix_web::test]
+async fn test_steps_svg() {
+    let db = Database::connect("sqlite::memory:").await.unwrap();
+    db::setup_schema(&db).await.unwrap();
+    seed_finished_game(&db).await.unwrap();
+
+    let registry = Data::new(Registry {
+        steam_key: None,
+        db,
+        ..Default::default()
+    });
+
+    let app = app!().app_data(Data::clone(&registry));
+    let srv = test::init_service(app).await;
+
+    let req = test::TestRequest::get()
+        .uri("/games/1/steps/2.svg")
+        .to_request();
+    let resp = test::call_service(&srv, req).await;
+    assert_eq!(resp.status(), ::aw::http::StatusCode::OK);
+    assert_eq!(
+        resp.headers().get(::aw::http::header::CONTENT_TYPE).unwrap(),
+        "image/svg+xml"
+    );
+    let body = test::read_body(resp).await;
+    let svg = std::str::from_utf8(&body).unwrap();
+    assert_eq!(svg.matches("<circle").count(), 2);
+    assert!(svg.contains(r#"cx="200" cy="1""#));
+    assert!(svg.contains("player2"));
+    // картинка охватывает семена хода с отступом и не уже легенды
+    assert!(svg.contains(r#"viewBox="136 -64 480 144""#));
+
+    let req = test::TestRequest::get().uri("/games/1/steps.svg").to_request();
+    let body = test::call_and_read_body(&srv, req).await;
+    let svg = std::str::from_utf8(&body).unwrap();
+    assert_eq!(svg.matches(r#"class="step""#).count(), 2);
+    assert_eq!(svg.matches("<circle").count(), 4);
+    assert!(svg.contains(r#"viewBox="36 -64 480 144""#));
+
+    // третьего хода не было
+    let req = test::TestRequest::get()
+        .uri("/games/1/steps/3.svg")
+        .to_request();
+    let resp = test::call_service(&srv, req).await;
+    assert_eq!(resp.status(), ::aw::http::StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn test_replay_import() {
+    use ActiveValue::*;
+
+    let db = Database::connect("sqlite::memory:").await.unwrap();
+    db::setup_schema(&db).await.unwrap();
+    seed_finished_game(&db).await.unwrap();
+
+    entity::game::ActiveModel {
+        id: Unchanged(1),
+        finished_at: Set(Some(::chrono::Utc::now().naive_utc())),
+        ..Default::default()
+    }
+    .update(&db)
+    .await
+    .unwrap();
+
+    let gm = GameManager::load_game(&db, 1).await.unwrap();
+    let replay = Replay::from_game(&gm).unwrap();
+
+    // запись загружает второй игрок, все места новой игры принадлежат ему
+    let rating = main::matchmaking::rating(&db, 2).await.unwrap();
+    let game = replay.import(&db, 2).await.unwrap();
+    assert_eq!(game.imported_from, Some(1));
+    // загруженная игра не идёт в рейтинг загрузившего
+    assert_eq!(main::matchmaking::rating(&db, 2).await.unwrap(), rating);
+
+    let registry = Data::new(Registry {
+        steam_key: None,
+        db,
+        ..Default::default()
+    });
+
+    let app = app!().app_data(Data::clone(&registry));
+    let srv = test::init_service(app).await;
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/games/{}", game.id))
+        .to_request();
+    let body = test::call_and_read_body(&srv, req).await;
+    let html = std::str::from_utf8(&body).unwrap();
+    assert!(html.contains("from a replay of game #1"));
+    assert!(html.contains("<li>player1</li>"));
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/games/{}/steps.svg", game.id))
+        .to_request();
+    let body = test::call_and_read_body(&srv, req).await;
+    let svg = std::str::from_utf8(&body).unwrap();
+    assert_eq!(svg.matches("<circle").count(), 4);
+    assert!(svg.contains("player1"));
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/games/{}/replay.json", game.id))
+        .to_request();
+    let body = test::call_and_read_body(&srv, req).await;
+    let exported = Replay::parse(std::str::from_utf8(&body).unwrap()).unwrap();
+    assert_eq!(exported.game_id, 1);
+    assert_eq!(exported.players, replay.players);
+    assert_eq!(exported.steps, replay.steps);
+}
@@ -12,8 +12,9 @@ mod db;
 
 mod common;
 use common::*;
+use main::events::GameEvent;
 use main::state::Registry;
-use sea_orm::{ActiveModelTrait, ActiveValue, Database, DbConn, DbErr};
+use sea_orm::{ActiveModelTrait, ActiveValue, ConnectOptions, Database, DbConn, DbErr};
 
 use crate::common::tapi::RetGame;
 
@@ -80,6 +81,20 @@ async fn seed_required_data(db: &DbConn) -> Result<(), DbErr> {
     Ok(())
 }
 
+/// База в файле с пулом из нескольких соединений.
+///
+/// У `sqlite::memory:` соединение одно, и одновременные запросы выстраивались бы
+/// в очередь за ним ещё до начала транзакции, так и не встретившись в `lock_game`.
+async fn file_db(name: &str) -> (DbConn, std::path::PathBuf) {
+    let path = std::env::temp_dir().join(format!("samogonki-{name}-{}.db", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let mut opts = ConnectOptions::new(format!("sqlite://{}?mode=rwc", path.display()));
+    opts.min_connections(2).max_connections(4);
+
+    (Database::connect(opts).await.unwrap(), path)
+}
+
 #[derive(Debug)]
 struct Action {
     #[allow(dead_code)]
@@ -100,6 +115,7 @@ async fn test_full_players2_turn2() {
     let registry = Data::new(Registry {
         steam_key: None,
         db,
+        ..Default::default()
     });
 
     let app = app!()
@@ -341,6 +357,402 @@ async fn test_full_players2_turn2() {
     }
 }
 
+#[actix_web::test]
+async fn test_control_packet_with_unknown_car_component_is_accepted() {
+    let db = Database::connect("sqlite::memory:").await.unwrap();
+    db::setup_schema(&db).await.unwrap();
+    seed_required_data(&db).await.unwrap();
+
+    let registry = Data::new(Registry {
+        steam_key: None,
+        db,
+        ..Default::default()
+    });
+
+    let app = app!().app_data(Data::clone(&registry));
+    let srv = test::init_service(app).await;
+
+    // персонажа №99 нет в каталоге, но каталог не сверен с клиентом: ход принимается
+    let req = test::TestRequest::post()
+        .uri("/game-on-line/default.asp")
+        .set_payload("KDLAB;104;2;1;0;0;0;password;0;0;12711;A;1;100;10;0;2;0;Y;;0;;;0;player;99;1;1;1;N;1;player2;1;1;1;1;N;BITRIX")
+        .to_request();
+    let resp = test::call_service(&srv, req).await;
+    assert_eq!(resp.status(), ::aw::http::StatusCode::OK);
+
+    let req = test::TestRequest::post()
+        .uri("/game-on-line/default.asp")
+        .set_payload("KDLAB;104;2;1;0;0;0;password;0;0;12711;A;1;100;10;0;2;0;Y;;0;;;0;player;1;1;1;1;N;1;player2;1;1;1;1;N;BITRIX")
+        .to_request();
+    let resp = test::call_service(&srv, req).await;
+    assert_eq!(resp.status(), ::aw::http::StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn test_simultaneous_seeds_packets() {
+    let (db, path) = file_db("simultaneous-seeds").await;
+    db::setup_schema(&db).await.unwrap();
+    seed_required_data(&db).await.unwrap();
+
+    let registry = Data::new(Registry {
+        steam_key: None,
+        db,
+        ..Default::default()
+    });
+
+    let app = app!()
+        .route(TEST_URL_GET_GAME, web::get().to(tapi::get_game))
+        .app_data(Data::clone(&registry));
+
+    let srv = test::init_service(app).await;
+
+    let get_game = || {
+        let req = test::TestRequest::get()
+            .uri(&format!("{TEST_URL_GET_GAME}?id=1"))
+            .to_request();
+
+        test::call_and_read_body_json(&srv, req)
+    };
+
+    let seeds = |payload: &'static str| {
+        test::TestRequest::post()
+            .uri("/game-on-line/default.asp")
+            .set_payload(payload)
+            .to_request()
+    };
+
+    // оба клиента отправляют первый ход одновременно
+    let (resp0, resp1) = ::futures::join!(
+        test::call_and_read_body(&srv, seeds("KDLAB;104;3;1;0;0;0;password;0;0;12711;A;1;100;10;0;2;1;Y;;0;;;1;1;0;N;0;0;0;0;0;0;0;14;620#402#51#-1#913#303#51#-1#1190#293#51#-1#1497#402#51#-1#1771#578#51#-1#1955#970#48#-1#1853#1225#48#-1#1727#1506#51#-1#1460#1766#102#-1#1105#3#102#-1#647#39#102#-1#533#1802#102#-1#353#1499#48#-1#211#1059#48#-1;BITRIX")),
+        test::call_and_read_body(&srv, seeds("KDLAB;104;3;1;0;0;1;password;0;0;12711;A;1;100;10;0;2;1;Y;;0;;;1;1;1;N;1;0;0;0;0;0;0;12;592#382#51#-1#892#329#61#-1#1534#377#51#-1#1945#949#48#-1#1882#1209#48#-1#1700#1528#51#-1#1564#1702#71#-1#1229#1941#102#-1#925#28#103#-1#756#49#102#-1#563#1842#102#-1#268#1233#48#-1;BITRIX")),
+    );
+    assert_eq!(&resp0[..], b"OK:KDLAB");
+    assert_eq!(&resp1[..], b"OK:KDLAB");
+
+    let game: RetGame = get_game().await;
+    assert_eq!(game.turns.len(), 2);
+    assert!(game.turns.iter().all(|(t, _)| t.seeds.is_some()));
+
+    // клиент №0 не дождался ответа и повторил отправку второго хода,
+    // обе копии пакета обрабатываются одновременно
+    let step2 = "KDLAB;104;3;1;0;0;0;password;0;0;12711;A;1;100;10;1;2;1;Y;;0;;;2;1;0;N;0;21;0;16;14;0;1;2;165#741#51#-1#465#427#51#-1;BITRIX1;0;;1;N;1;21;0;20;12;0;1;0;;BITRIX1955#970#48#-1#1853#1225#48#-1#1727#1506#51#-1#1460#1766#102#-1#1105#3#102#-1#647#39#102#-1#533#1802#102#-1#353#1499#48#-1#211#1059#48#-1;BITRIX";
+    let (resp0, resp1) = ::futures::join!(
+        test::call_and_read_body(&srv, seeds(step2)),
+        test::call_and_read_body(&srv, seeds(step2)),
+    );
+    assert_eq!(&resp0[..], b"OK:KDLAB");
+    assert_eq!(&resp1[..], b"OK:KDLAB");
+
+    let game: RetGame = get_game().await;
+    assert_eq!(game.turns.len(), 3);
+    assert_eq!(
+        game.turns
+            .iter()
+            .filter(|(t, _)| t.step_number == 2 && t.player_number == 0)
+            .count(),
+        1
+    );
+    // каждый принятый пакет захватывал игру на запись
+    assert_eq!(game.game.version, 4);
+
+    registry.db.clone().close().await.unwrap();
+    let _ = std::fs::remove_file(path);
+}
+
+#[actix_web::test]
+async fn test_seeds_packet_resubmission() {
+    let db = Database::connect("sqlite::memory:").await.unwrap();
+    db::setup_schema(&db).await.unwrap();
+    seed_required_data(&db).await.unwrap();
+
+    let registry = Data::new(Registry {
+        steam_key: None,
+        db,
+        ..Default::default()
+    });
+
+    let app = app!()
+        .route(TEST_URL_GET_GAME, web::get().to(tapi::get_game))
+        .app_data(Data::clone(&registry));
+
+    let srv = test::init_service(app).await;
+
+    let get_game = || {
+        let req = test::TestRequest::get()
+            .uri(&format!("{TEST_URL_GET_GAME}?id=1"))
+            .to_request();
+
+        test::call_and_read_body_json(&srv, req)
+    };
+
+    let send = |payload: &'static str| {
+        let req = test::TestRequest::post()
+            .uri("/game-on-line/default.asp")
+            .set_payload(payload)
+            .to_request();
+
+        test::call_service(&srv, req)
+    };
+
+    let step1_client0 = "KDLAB;104;3;1;0;0;0;password;0;0;12711;A;1;100;10;0;2;1;Y;;0;;;1;1;0;N;0;0;0;0;0;0;0;14;620#402#51#-1#913#303#51#-1#1190#293#51#-1#1497#402#51#-1#1771#578#51#-1#1955#970#48#-1#1853#1225#48#-1#1727#1506#51#-1#1460#1766#102#-1#1105#3#102#-1#647#39#102#-1#533#1802#102#-1#353#1499#48#-1#211#1059#48#-1;BITRIX";
+    let step1_client0_changed = "KDLAB;104;3;1;0;0;0;password;0;0;12711;A;1;100;10;0;2;1;Y;;0;;;1;1;0;N;0;0;0;0;0;0;0;2;165#741#51#-1#465#427#51#-1;BITRIX";
+    let step1_client1 = "KDLAB;104;3;1;0;0;1;password;0;0;12711;A;1;100;10;0;2;1;Y;;0;;;1;1;1;N;1;0;0;0;0;0;0;12;592#382#51#-1#892#329#61#-1#1534#377#51#-1#1945#949#48#-1#1882#1209#48#-1#1700#1528#51#-1#1564#1702#71#-1#1229#1941#102#-1#925#28#103#-1#756#49#102#-1#563#1842#102#-1#268#1233#48#-1;BITRIX";
+
+    // ход ещё открыт: повторная отправка того же хода подтверждается без изменений
+    for _ in 0..2 {
+        let resp = send(step1_client0).await;
+        assert_eq!(resp.status(), ::aw::http::StatusCode::OK);
+        assert_eq!(&test::read_body(resp).await[..], b"OK:KDLAB");
+    }
+    let game: RetGame = get_game().await;
+    check::client_0_sent_their_own_first_turn(&game);
+
+    let resp = send(step1_client1).await;
+    assert_eq!(&test::read_body(resp).await[..], b"OK:KDLAB");
+    let game: RetGame = get_game().await;
+    check::client_1_sent_their_own_first_turn(&game);
+
+    // ход закрыт: тот же самый ход по-прежнему подтверждается
+    let resp = send(step1_client0).await;
+    assert_eq!(&test::read_body(resp).await[..], b"OK:KDLAB");
+
+    // ход закрыт: изменённый ход не принимается, клиента отправляют к следующему ходу
+    let resp = send(step1_client0_changed).await;
+    assert_eq!(resp.status(), ::aw::http::StatusCode::OK);
+    assert_eq!(&test::read_body(resp).await[..], b"NEXT_MOVE");
+
+    let game: RetGame = get_game().await;
+    check::client_1_sent_their_own_first_turn(&game);
+
+    // ход из будущего
+    let resp = send("KDLAB;104;3;1;0;0;0;password;0;0;12711;A;1;100;10;1;2;1;Y;;0;;;5;1;0;N;0;0;0;0;0;0;0;2;165#741#51#-1#465#427#51#-1;BITRIX").await;
+    assert_eq!(resp.status(), ::aw::http::StatusCode::NOT_ACCEPTABLE);
+}
+
+#[actix_web::test]
+async fn test_active_game_cache() {
+    let db = Database::connect("sqlite::memory:").await.unwrap();
+    db::setup_schema(&db).await.unwrap();
+    seed_required_data(&db).await.unwrap();
+
+    let registry = Data::new(Registry {
+        steam_key: None,
+        db,
+        ..Default::default()
+    });
+
+    let app = app!().app_data(Data::clone(&registry));
+    let srv = test::init_service(app).await;
+
+    let get_info = || {
+        let req = test::TestRequest::get()
+            .uri("/game-on-line/default.asp?ID=1&USERID=0")
+            .to_request();
+
+        test::call_and_read_body(&srv, req)
+    };
+
+    let first = get_info().await;
+    let second = get_info().await;
+    assert_eq!(first, second);
+
+    let stats = registry.games.stats();
+    assert_eq!((stats.misses, stats.hits, stats.entries), (1, 1, 1));
+    // игра и её ходы читались из базы один раз
+    assert_eq!(stats.queries, 2);
+
+    // ход игрока сбрасывает кэш игры
+    let req = test::TestRequest::post()
+        .uri("/game-on-line/default.asp")
+        .set_payload("KDLAB;104;3;1;0;0;0;password;0;0;12711;A;1;100;10;0;2;1;Y;;0;;;1;1;0;N;0;0;0;0;0;0;0;2;165#741#51#-1#465#427#51#-1;BITRIX")
+        .to_request();
+    assert_eq!(&test::call_and_read_body(&srv, req).await[..], b"OK:KDLAB");
+
+    let stats = registry.games.stats();
+    assert_eq!((stats.invalidations, stats.entries), (1, 0));
+
+    get_info().await;
+    let stats = registry.games.stats();
+    assert_eq!((stats.misses, stats.hits, stats.entries), (2, 1, 1));
+
+    let req = test::TestRequest::get().uri("/stats/cache").to_request();
+    let body: ::serde_json::Value = test::call_and_read_body_json(&srv, req).await;
+    assert_eq!(body["hits"], 1);
+    assert_eq!(body["misses"], 2);
+    assert_eq!(body["queries"], 4);
+}
+
+#[actix_web::test]
+async fn test_refresh_long_poll() {
+    let db = Database::connect("sqlite::memory:").await.unwrap();
+    db::setup_schema(&db).await.unwrap();
+    seed_required_data(&db).await.unwrap();
+
+    let registry = Data::new(Registry {
+        steam_key: None,
+        db,
+        refresh_long_poll: Some(std::time::Duration::from_secs(1)),
+        ..Default::default()
+    });
+
+    let app = app!().app_data(Data::clone(&registry));
+    let srv = test::init_service(app).await;
+
+    let send = |payload: &'static str| {
+        let req = test::TestRequest::post()
+            .uri("/game-on-line/default.asp")
+            .set_payload(payload)
+            .to_request();
+
+        test::call_and_read_body(&srv, req)
+    };
+
+    let refresh_client0 = "KDLAB;104;6;1;0;0;0;password;0;0;12711;A;1;100;10;0;2;0;Y;;0;;;BITRIX;0;0;0;0;0;0;0;14;620#402#51#-1#913#303#51#-1#1190#293#51#-1#1497#402#51#-1#1771#578#51#-1#1955#970#48#-1#1853#1225#48#-1#1727#1506#51#-1#1460#1766#102#-1#1105#3#102#-1#647#39#102#-1#533#1802#102#-1#353#1499#48#-1#211#1059#48#-1;BITRIX";
+
+    let resp = send("KDLAB;104;3;1;0;0;0;password;0;0;12711;A;1;100;10;0;2;1;Y;;0;;;1;1;0;N;0;0;0;0;0;0;0;14;620#402#51#-1#913#303#51#-1#1190#293#51#-1#1497#402#51#-1#1771#578#51#-1#1955#970#48#-1#1853#1225#48#-1#1727#1506#51#-1#1460#1766#102#-1#1105#3#102#-1#647#39#102#-1#533#1802#102#-1#353#1499#48#-1#211#1059#48#-1;BITRIX").await;
+    assert_eq!(&resp[..], b"OK:KDLAB");
+
+    // клиент №1 не ходит: запрос ждёт до таймаута и получает OG_REFRESH_ANSWER_PACKET
+    let started = std::time::Instant::now();
+    let resp = send(refresh_client0).await;
+    assert!(started.elapsed() >= std::time::Duration::from_secs(1));
+    assert!(resp.starts_with(b"KDLAB;104;7;"));
+
+    // ход клиента №1 будит ожидающий запрос клиента №0
+    let started = std::time::Instant::now();
+    let (resp, _) = ::futures::join!(send(refresh_client0), async {
+        ::tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        send("KDLAB;104;3;1;0;0;1;password;0;0;12711;A;1;100;10;0;2;1;Y;;0;;;1;1;1;N;1;0;0;0;0;0;0;12;592#382#51#-1#892#329#61#-1#1534#377#51#-1#1945#949#48#-1#1882#1209#48#-1#1700#1528#51#-1#1564#1702#71#-1#1229#1941#102#-1#925#28#103#-1#756#49#102#-1#563#1842#102#-1#268#1233#48#-1;BITRIX").await
+    });
+    assert!(started.elapsed() < std::time::Duration::from_secs(1));
+    assert!(resp.starts_with(b"KDLAB;104;1;"));
+}
+
+#[actix_web::test]
+async fn test_refresh_on_pause() {
+    use ActiveValue::*;
+
+    let db = Database::connect("sqlite::memory:").await.unwrap();
+    db::setup_schema(&db).await.unwrap();
+    seed_required_data(&db).await.unwrap();
+
+    let registry = Data::new(Registry {
+        steam_key: None,
+        db: db.clone(),
+        ..Default::default()
+    });
+
+    let app = app!().app_data(Data::clone(&registry));
+    let srv = test::init_service(app).await;
+
+    let refresh = || {
+        test::TestRequest::post()
+            .uri("/game-on-line/default.asp")
+            .set_payload("KDLAB;104;6;1;0;0;0;password;0;0;12711;A;1;100;10;0;2;0;Y;;0;;;BITRIX;0;0;0;0;0;0;0;14;620#402#51#-1#913#303#51#-1#1190#293#51#-1#1497#402#51#-1#1771#578#51#-1#1955#970#48#-1#1853#1225#48#-1#1727#1506#51#-1#1460#1766#102#-1#1105#3#102#-1#647#39#102#-1#533#1802#102#-1#353#1499#48#-1#211#1059#48#-1;BITRIX")
+            .to_request()
+    };
+
+    let resp = test::call_service(&srv, refresh()).await;
+    assert!(resp.headers().get("X-Samogonki-Paused").is_none());
+
+    entity::game::ActiveModel {
+        id: Unchanged(1),
+        paused_at: Set(Some(chrono::Utc::now().naive_utc())),
+        ..Default::default()
+    }
+    .update(&db)
+    .await
+    .unwrap();
+    registry.games.invalidate(1);
+
+    // пакет обычный ответ ожидания, о паузе говорит заголовок
+    let resp = test::call_service(&srv, refresh()).await;
+    assert_eq!(resp.headers().get("X-Samogonki-Paused").unwrap(), "1");
+    let body = test::read_body(resp).await;
+    assert!(body.starts_with(b"KDLAB;104;7;"));
+}
+
+#[actix_web::test]
+async fn test_seeds_packet_publishes_event() {
+    let db = Database::connect("sqlite::memory:").await.unwrap();
+    db::setup_schema(&db).await.unwrap();
+    seed_required_data(&db).await.unwrap();
+
+    let registry = Data::new(Registry {
+        steam_key: None,
+        db,
+        ..Default::default()
+    });
+    let mut events = registry.events.subscribe();
+
+    let app = app!().app_data(Data::clone(&registry));
+    let srv = test::init_service(app).await;
+
+    let req = test::TestRequest::post()
+        .uri("/game-on-line/default.asp")
+        .set_payload("KDLAB;104;3;1;0;0;0;password;0;0;12711;A;1;100;10;0;2;1;Y;;0;;;1;1;0;N;0;0;0;0;0;0;0;14;620#402#51#-1#913#303#51#-1#1190#293#51#-1#1497#402#51#-1#1771#578#51#-1#1955#970#48#-1#1853#1225#48#-1#1727#1506#51#-1#1460#1766#102#-1#1105#3#102#-1#647#39#102#-1#533#1802#102#-1#353#1499#48#-1#211#1059#48#-1;BITRIX")
+        .to_request();
+    let resp = test::call_and_read_body(&srv, req).await;
+    assert_eq!(&resp[..], b"OK:KDLAB");
+
+    assert_eq!(
+        events.try_recv().unwrap(),
+        GameEvent::TurnSaved {
+            game_id: 1,
+            player_number: 0,
+            step_number: 1,
+        }
+    );
+    assert!(events.try_recv().is_err());
+}
+
+#[actix_web::test]
+async fn test_spectator_packets() {
+    let db = Database::connect("sqlite::memory:").await.unwrap();
+    db::setup_schema(&db).await.unwrap();
+    seed_required_data(&db).await.unwrap();
+
+    entity::game::ActiveModel {
+        id: ActiveValue::Unchanged(1),
+        spectator_pid: ActiveValue::Set(Some(1234)),
+        ..Default::default()
+    }
+    .update(&db)
+    .await
+    .unwrap();
+
+    let registry = Data::new(Registry {
+        steam_key: None,
+        db,
+        ..Default::default()
+    });
+
+    let app = app!().app_data(Data::clone(&registry));
+    let srv = test::init_service(app).await;
+
+    let req = test::TestRequest::get()
+        .uri("/game-on-line/default.asp?USERID=1234&ID=1")
+        .to_request();
+    let resp = test::call_service(&srv, req).await;
+    assert!(resp.status().is_success());
+
+    // зритель не видит ход, пока его не сделали все игроки
+    let req = test::TestRequest::post()
+        .uri("/game-on-line/default.asp")
+        .set_payload("KDLAB;104;6;1;0;0;1234;password;0;0;12711;A;1;100;10;0;2;0;Y;;0;;;BITRIX;0;0;0;0;0;0;0;14;620#402#51#-1#913#303#51#-1#1190#293#51#-1#1497#402#51#-1#1771#578#51#-1#1955#970#48#-1#1853#1225#48#-1#1727#1506#51#-1#1460#1766#102#-1#1105#3#102#-1#647#39#102#-1#533#1802#102#-1#353#1499#48#-1#211#1059#48#-1;BITRIX")
+        .to_request();
+    let resp = test::call_and_read_body(&srv, req).await;
+    assert!(resp.starts_with(b"KDLAB;104;7;"));
+
+    // и не может ходить сам
+    let req = test::TestRequest::post()
+        .uri("/game-on-line/default.asp")
+        .set_payload("KDLAB;104;3;1;0;0;1234;password;0;0;12711;A;1;100;10;0;2;1;Y;;0;;;1;1;0;N;0;0;0;0;0;0;0;2;165#741#51#-1#465#427#51#-1;BITRIX")
+        .to_request();
+    let resp = test::call_service(&srv, req).await;
+    assert_eq!(resp.status(), ::aw::http::StatusCode::FORBIDDEN);
+}
+
 mod check {
     use super::PlayerTurnInfo;
     use super::RetGame;
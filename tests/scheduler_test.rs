@@ -0,0 +1,163 @@
+#![allow(unused_imports)]
+
+extern crate actix_web as aw;
+
+#[macro_use]
+#[path = "../src/main.rs"]
+mod main;
+pub use main::*;
+
+mod db;
+
+mod common;
+use common::seed_users;
+
+use entity::game::{GameType, World};
+use main::{
+    events::GameEvent,
+    manager::{GameManager, GameStatus},
+    scheduler::{self, ROBOT_STEAM_ID},
+    state::Registry,
+};
+use sea_orm::{ActiveModelTrait, ActiveValue, Database, DbConn, DbErr, EntityTrait};
+
+/// игра на троих, время начала которой уже наступило
+async fn due_game(
+    db: &DbConn,
+    owner: &entity::user::Model,
+    fill_with_robots: bool,
+) -> GameManager<'_, DbConn> {
+    let mut game = entity::game::Model::new(
+        owner.id,
+        World::Mountain(0),
+        GameType::Winner,
+        1,
+        10,
+        100,
+        3,
+    );
+    game.starts_at = Some(chrono::Utc::now().naive_utc() - chrono::Duration::minutes(1));
+    game.fill_with_robots = fill_with_robots;
+
+    GameManager::create(db, game, owner, owner.loadout())
+        .await
+        .unwrap()
+}
+
+#[actix_web::test]
+async fn test_due_games_start() {
+    let db = Database::connect("sqlite::memory:").await.unwrap();
+    db::setup_schema(&db).await.unwrap();
+    let users = seed_users(&db, 2).await.unwrap();
+    let reg = Registry {
+        db: db.clone(),
+        ..Default::default()
+    };
+
+    let lonely = due_game(&db, &users[0], false).await.game.id;
+    let mut manager = due_game(&db, &users[0], false).await;
+    manager.join(&users[1]).await.unwrap();
+    let pair = manager.game.id;
+    let robots = due_game(&db, &users[1], true).await.game.id;
+
+    let mut events = reg.events.subscribe();
+    scheduler::start_due_games(&reg).await.unwrap();
+
+    // одному играть не с кем
+    let manager = GameManager::load_game(&db, lonely).await.unwrap();
+    assert_eq!(manager.status(), GameStatus::Cancelled);
+
+    // вдвоём игра начинается, лишнее место убирается
+    let manager = GameManager::load_game(&db, pair).await.unwrap();
+    assert_eq!(manager.status(), GameStatus::Started);
+    assert_eq!(manager.game.players_cnt, 2);
+
+    let manager = GameManager::load_game(&db, robots).await.unwrap();
+    assert_eq!(manager.status(), GameStatus::Started);
+    assert_eq!(manager.game.players_cnt, 3);
+    let robot_turns = manager
+        .turns
+        .iter()
+        .filter(|(t, u)| t.is_robot && u.steam_id == ROBOT_STEAM_ID)
+        .count();
+    assert_eq!(robot_turns, 2);
+
+    let mut changed = vec![];
+    for _ in 0..3 {
+        match events.recv().await.unwrap() {
+            GameEvent::StatusChanged { game_id, status } => changed.push((game_id, status)),
+            e => panic!("unexpected event: {e:?}"),
+        }
+    }
+    assert_eq!(
+        changed,
+        vec![
+            (lonely, GameStatus::Cancelled),
+            (pair, GameStatus::Started),
+            (robots, GameStatus::Started),
+        ]
+    );
+
+    // начатые игры второй раз не трогаются
+    scheduler::start_due_games(&reg).await.unwrap();
+    assert!(events.try_recv().is_err());
+}
+
+#[actix_web::test]
+async fn test_full_game_starts_on_schedule() {
+    use ActiveValue::*;
+
+    let db = Database::connect("sqlite::memory:").await.unwrap();
+    db::setup_schema(&db).await.unwrap();
+    let users = seed_users(&db, 2).await.unwrap();
+    let reg = Registry {
+        db: db.clone(),
+        ..Default::default()
+    };
+
+    // все места заняты заранее, до назначенного времени
+    let game_id = due_game(&db, &users[0], false).await.game.id;
+    entity::game::ActiveModel {
+        id: Unchanged(game_id),
+        players_cnt: Set(2),
+        starts_at: Set(Some(
+            chrono::Utc::now().naive_utc() + chrono::Duration::minutes(1),
+        )),
+        ..Default::default()
+    }
+    .update(&db)
+    .await
+    .unwrap();
+    let mut manager = GameManager::load_game(&db, game_id).await.unwrap();
+    manager.join(&users[1]).await.unwrap();
+    assert_eq!(manager.status(), GameStatus::Open);
+
+    entity::game::ActiveModel {
+        id: Unchanged(game_id),
+        starts_at: Set(Some(
+            chrono::Utc::now().naive_utc() - chrono::Duration::minutes(1),
+        )),
+        ..Default::default()
+    }
+    .update(&db)
+    .await
+    .unwrap();
+
+    let mut events = reg.events.subscribe();
+    scheduler::start_due_games(&reg).await.unwrap();
+
+    let manager = GameManager::load_game(&db, game_id).await.unwrap();
+    assert_eq!(manager.status(), GameStatus::Started);
+    assert!(manager.game.step_started_at.is_some());
+    assert!(matches!(
+        events.try_recv(),
+        Ok(GameEvent::StatusChanged {
+            status: GameStatus::Started,
+            ..
+        })
+    ));
+
+    // время на ход отсчитывается, повторно игра не трогается
+    scheduler::start_due_games(&reg).await.unwrap();
+    assert!(events.try_recv().is_err());
+}
@@ -0,0 +1,244 @@
+#![allow(unused_imports)]
+
+extern crate actix_web as aw;
+
+#[macro_use]
+#[path = "../src/main.rs"]
+mod main;
+pub use main::*;
+
+mod db;
+
+use entity::{
+    game::GameType,
+    tournament::{TournamentFormat, TrackRotation},
+};
+use main::{
+    manager::{GameManager, GameStatus},
+    state::Registry,
+    tournament::{self, TournamentError},
+};
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ActiveValue, ColumnTrait, Database, DbConn, DbErr,
+    EntityTrait, QueryFilter,
+};
+
+async fn seed_tournament(
+    db: &DbConn,
+    format: TournamentFormat,
+    players: u32,
+) -> Result<(), DbErr> {
+    use ActiveValue::*;
+
+    for id in 1..=players {
+        entity::user::ActiveModel {
+            id: Set(id),
+            steam_id: Set(id as i64 * 111),
+            login: Set(Some(format!("player{id}"))),
+            ..Default::default()
+        }
+        .insert(db)
+        .await?;
+    }
+
+    entity::tournament::ActiveModel {
+        id: Set(1),
+        owner_id: Set(1),
+        name: Set(String::from("cup")),
+        format: Set(format),
+        players_per_race: Set(2),
+        tracks: Set(TrackRotation(vec![(0, 0), (1, 0)])),
+        game_type: Set(GameType::Winner),
+        ..Default::default()
+    }
+    .insert(db)
+    .await?;
+
+    Ok(())
+}
+
+/// игры тура вместе с id их участников по номерам игроков
+async fn round_games(db: &DbConn, round: u32) -> Vec<(u32, Vec<u32>)> {
+    let games = entity::tournament_game::Entity::find()
+        .filter(entity::tournament_game::Column::Round.eq(round))
+        .all(db)
+        .await
+        .unwrap();
+
+    let mut res = vec![];
+    for game in games {
+        let manager = GameManager::load_game(db, game.game_id).await.unwrap();
+        let mut players = manager
+            .turns
+            .iter()
+            .map(|(t, u)| (t.player_number, u.id))
+            .collect::<Vec<_>>();
+        players.sort();
+        res.push((
+            game.game_id,
+            players.into_iter().map(|(_, id)| id).collect(),
+        ));
+    }
+    res
+}
+
+/// завершает заезд победой заданного участника
+async fn finish_game(db: &DbConn, game_id: u32, winner: u32) {
+    entity::turn::Entity::update_many()
+        .col_expr(
+            entity::turn::Column::Rank,
+            Expr::case(entity::turn::Column::UserId.eq(winner), 1)
+                .finally(2)
+                .into(),
+        )
+        .filter(entity::turn::Column::GameId.eq(game_id))
+        .exec(db)
+        .await
+        .unwrap();
+
+    entity::game::ActiveModel {
+        id: ActiveValue::Unchanged(game_id),
+        finished_at: ActiveValue::Set(Some(chrono::Utc::now().naive_utc())),
+        ..Default::default()
+    }
+    .update(db)
+    .await
+    .unwrap();
+}
+
+#[actix_web::test]
+async fn test_elimination_rounds() {
+    let db = Database::connect("sqlite::memory:").await.unwrap();
+    db::setup_schema(&db).await.unwrap();
+    seed_tournament(&db, TournamentFormat::Elimination, 4)
+        .await
+        .unwrap();
+    let reg = Registry {
+        db: db.clone(),
+        ..Default::default()
+    };
+
+    let cup = entity::tournament::Entity::find_by_id(1)
+        .one(&db)
+        .await
+        .unwrap()
+        .unwrap();
+    tournament::join(&db, &cup, 1).await.unwrap();
+    assert!(matches!(
+        tournament::start(&reg, 1).await,
+        Err(TournamentError::NotEnoughParticipants(1))
+    ));
+    for user_id in 2..=4 {
+        tournament::join(&db, &cup, user_id).await.unwrap();
+    }
+    assert!(matches!(
+        tournament::join(&db, &cup, 4).await,
+        Err(TournamentError::AlreadyJoined(4))
+    ));
+
+    tournament::start(&reg, 1).await.unwrap();
+    assert!(matches!(
+        tournament::start(&reg, 1).await,
+        Err(TournamentError::AlreadyStarted(1))
+    ));
+
+    let round = round_games(&db, 1).await;
+    assert_eq!(
+        round.iter().map(|(_, p)| p.clone()).collect::<Vec<_>>(),
+        vec![vec![1, 2], vec![3, 4]]
+    );
+
+    // тур закрывается только когда закончены все его игры
+    finish_game(&db, round[0].0, 2).await;
+    assert!(!tournament::advance(&reg, 1).await.unwrap());
+    finish_game(&db, round[1].0, 3).await;
+    assert!(tournament::advance(&reg, 1).await.unwrap());
+
+    let final_round = round_games(&db, 2).await;
+    assert_eq!(final_round.len(), 1);
+    assert_eq!(final_round[0].1, vec![2, 3]);
+    let game = entity::game::Entity::find_by_id(final_round[0].0)
+        .one(&db)
+        .await
+        .unwrap()
+        .unwrap();
+    // трассы туров идут по кругу
+    assert_eq!((game.world_id, game.track_id), (1, 0));
+
+    finish_game(&db, final_round[0].0, 3).await;
+    assert!(tournament::advance(&reg, 1).await.unwrap());
+
+    let cup = entity::tournament::Entity::find_by_id(1)
+        .one(&db)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(cup.finished_at.is_some());
+    assert_eq!((cup.current_round, cup.rounds), (2, 2));
+
+    let eliminated = tournament::participants(&db, 1)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|p| (p.user_id, p.eliminated_in))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        eliminated,
+        vec![(1, Some(1)), (2, Some(2)), (3, None), (4, Some(1))]
+    );
+
+    let races = tournament::races(&db, 1).await.unwrap();
+    let table = tournament::standings(&[1, 2, 3, 4], &races);
+    assert_eq!(table[0].user_id, 3);
+    assert_eq!((table[0].points, table[0].wins), (2, 2));
+}
+
+#[actix_web::test]
+async fn test_round_robin_rounds() {
+    let db = Database::connect("sqlite::memory:").await.unwrap();
+    db::setup_schema(&db).await.unwrap();
+    seed_tournament(&db, TournamentFormat::RoundRobin, 3)
+        .await
+        .unwrap();
+    let reg = Registry {
+        db: db.clone(),
+        ..Default::default()
+    };
+
+    let cup = entity::tournament::Entity::find_by_id(1)
+        .one(&db)
+        .await
+        .unwrap()
+        .unwrap();
+    for user_id in 1..=3 {
+        tournament::join(&db, &cup, user_id).await.unwrap();
+    }
+    tournament::start(&reg, 1).await.unwrap();
+
+    let mut met = vec![];
+    for round in 1..=3 {
+        // при трёх участниках один из них в каждом туре отдыхает
+        let games = round_games(&db, round).await;
+        assert_eq!(games.len(), 1, "round {round}");
+        let (game_id, players) = &games[0];
+        met.push((players[0].min(players[1]), players[0].max(players[1])));
+
+        finish_game(&db, *game_id, players[0]).await;
+        assert!(tournament::advance(&reg, 1).await.unwrap());
+    }
+    met.sort();
+    assert_eq!(met, vec![(1, 2), (1, 3), (2, 3)]);
+
+    let cup = entity::tournament::Entity::find_by_id(1)
+        .one(&db)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(cup.finished_at.is_some());
+    assert!(!tournament::advance(&reg, 1).await.unwrap());
+
+    let races = tournament::races(&db, 1).await.unwrap();
+    assert!(races.iter().all(|r| r.status == GameStatus::Finished));
+    let table = tournament::standings(&[1, 2, 3], &races);
+    assert_eq!(table.iter().map(|s| s.races).sum::<u32>(), 6);
+}
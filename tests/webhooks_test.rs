@@ -0,0 +1,252 @@
+#![allow(unused_imports)]
+
+extern crate actix_web as aw;
+
+#[macro_use]
+#[path = "../src/main.rs"]
+mod main;
+pub use main::*;
+
+mod db;
+
+mod common;
+use common::seed_users;
+
+use std::sync::{Arc, Mutex};
+
+use aw::{web, HttpRequest, HttpResponse, HttpServer};
+use entity::game::{GameType, World};
+use main::{
+    events::GameEvent,
+    manager::GameManager,
+    state::Registry,
+    webhooks::{self, Targets, WebhookError, EVENT_HEADER, SIGNATURE_HEADER},
+};
+use sea_orm::{ActiveModelTrait, ActiveValue, Database, DbConn, DbErr, EntityTrait};
+
+/// запросы, полученные заглушкой: событие, подпись и тело
+#[derive(Clone, Default)]
+struct Stub {
+    received: Arc<Mutex<Vec<(String, String, String)>>>,
+}
+
+async fn hook(stub: web::Data<Stub>, req: HttpRequest, body: String) -> HttpResponse {
+    let header = |name| {
+        req.headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default()
+            .to_owned()
+    };
+
+    let mut received = stub.received.lock().unwrap();
+    received.push((header(EVENT_HEADER), header(SIGNATURE_HEADER), body));
+
+    // первая попытка проваливается
+    match received.len() {
+        1 => HttpResponse::InternalServerError().finish(),
+        _ => HttpResponse::Ok().finish(),
+    }
+}
+
+async fn deliveries(db: &DbConn) -> Vec<entity::webhook_delivery::Model> {
+    entity::webhook_delivery::Entity::find()
+        .all(db)
+        .await
+        .unwrap()
+}
+
+#[actix_web::test]
+async fn test_delivery_with_retry() {
+    use ActiveValue::*;
+
+    let db = Database::connect("sqlite::memory:").await.unwrap();
+    db::setup_schema(&db).await.unwrap();
+    let users = seed_users(&db, 2).await.unwrap();
+    let reg = Registry {
+        db: db.clone(),
+        ..Default::default()
+    };
+
+    let stub = Stub::default();
+    let srv = {
+        let stub = stub.clone();
+        HttpServer::new(move || {
+            aw::App::new()
+                .app_data(web::Data::new(stub.clone()))
+                .route("/hook", web::post().to(hook))
+        })
+    }
+    .workers(1)
+    .bind(("127.0.0.1", 0))
+    .unwrap();
+    let addr = srv.addrs()[0];
+    actix_web::rt::spawn(srv.run());
+
+    assert!(matches!(
+        webhooks::create(&db, 1, "ftp://example.com", Targets::Public).await,
+        Err(WebhookError::InvalidUrl(_))
+    ));
+    // сам сервер и его сеть вебхукам недоступны
+    for url in [
+        format!("http://{addr}/hook"),
+        String::from("http://localhost/hook"),
+        String::from("http://[::1]/hook"),
+        String::from("http://169.254.169.254/latest/meta-data"),
+    ] {
+        assert!(matches!(
+            webhooks::create(&db, 1, &url, Targets::Public).await,
+            Err(WebhookError::ForbiddenTarget(_))
+        ));
+    }
+    let webhook = webhooks::create(&db, 1, &format!("http://{addr}/hook"), Targets::Any)
+        .await
+        .unwrap();
+
+    let game = entity::game::Model::new(
+        users[0].id,
+        World::Mountain(0),
+        GameType::Winner,
+        1,
+        10,
+        100,
+        2,
+    );
+    let game_id = GameManager::create(&db, game, &users[0], users[0].loadout())
+        .await
+        .unwrap()
+        .game
+        .id;
+
+    webhooks::on_event(&reg, &GameEvent::GameCreated { game_id })
+        .await
+        .unwrap();
+    // шаг не закрыт, сообщать не о чем
+    let turn_saved = GameEvent::TurnSaved {
+        game_id,
+        player_number: 0,
+        step_number: 1,
+    };
+    webhooks::on_event(&reg, &turn_saved).await.unwrap();
+    assert_eq!(deliveries(&db).await.len(), 1);
+
+    let client = webhooks::client(Targets::Any);
+    webhooks::deliver_due(&db, &client).await.unwrap();
+    let failed = deliveries(&db).await.remove(0);
+    assert_eq!(failed.attempts, 1);
+    assert_eq!(failed.status_code, Some(500));
+    assert!(failed.delivered_at.is_none());
+    assert!(failed.next_attempt_at.unwrap() > chrono::Utc::now().naive_utc());
+
+    // повтор ещё не подошёл
+    webhooks::deliver_due(&db, &client).await.unwrap();
+    assert_eq!(stub.received.lock().unwrap().len(), 1);
+
+    entity::webhook_delivery::ActiveModel {
+        id: Unchanged(failed.id),
+        next_attempt_at: Set(Some(chrono::Utc::now().naive_utc())),
+        ..Default::default()
+    }
+    .update(&db)
+    .await
+    .unwrap();
+    webhooks::deliver_due(&db, &client).await.unwrap();
+
+    let delivered = deliveries(&db).await.remove(0);
+    assert_eq!(delivered.attempts, 2);
+    assert_eq!(delivered.status_code, Some(200));
+    assert!(delivered.delivered_at.is_some());
+    assert!(delivered.next_attempt_at.is_none());
+
+    let received = stub.received.lock().unwrap().clone();
+    assert_eq!(received.len(), 2);
+    let (event, signature, body) = &received[1];
+    assert_eq!(event, "game_created");
+    assert_eq!(signature, &webhooks::sign(&webhook.secret, body.as_bytes()));
+
+    let payload: serde_json::Value = serde_json::from_str(body).unwrap();
+    assert_eq!(payload["event"], "game_created");
+    assert_eq!(payload["game"]["id"], game_id);
+    // ход открытого шага в тело не попадает
+    assert_eq!(payload["turns"].as_array().unwrap().len(), 0);
+}
+
+#[actix_web::test]
+async fn test_hooks_see_only_own_games_and_closed_steps() {
+    use main::manager::GameStorage;
+    use ActiveValue::*;
+
+    let db = Database::connect("sqlite::memory:").await.unwrap();
+    db::setup_schema(&db).await.unwrap();
+    let users = seed_users(&db, 2).await.unwrap();
+    let outsider = entity::user::ActiveModel {
+        id: Set(3),
+        steam_id: Set(333),
+        login: Set(Some(String::from("player3"))),
+        ..Default::default()
+    }
+    .insert(&db)
+    .await
+    .unwrap();
+    let reg = Registry {
+        db: db.clone(),
+        ..Default::default()
+    };
+
+    for user_id in [users[1].id, outsider.id] {
+        webhooks::create(&db, user_id, "http://127.0.0.1:9/hook", Targets::Any)
+            .await
+            .unwrap();
+    }
+
+    let game = entity::game::Model::new(
+        users[0].id,
+        World::Mountain(0),
+        GameType::Winner,
+        1,
+        10,
+        100,
+        2,
+    );
+    let mut manager = GameManager::create(&db, game, &users[0], users[0].loadout())
+        .await
+        .unwrap();
+    manager.join(&users[1]).await.unwrap();
+    let game_id = manager.game.id;
+
+    // первый шаг закрыт, во втором уже сходил первый игрок
+    for (mut turn, _) in manager.turns.clone() {
+        turn.seeds = Some(String::new());
+        db.update_turn(turn).await.unwrap();
+    }
+    let mut next = manager.turns[0].0.clone();
+    next.step_number = 2;
+    next.seeds = Some(String::from("165#741#51#-1"));
+    db.insert_turn(next).await.unwrap();
+
+    let turn_saved = GameEvent::TurnSaved {
+        game_id,
+        player_number: 1,
+        step_number: 1,
+    };
+    webhooks::on_event(&reg, &turn_saved).await.unwrap();
+
+    // вебхук постороннего о чужой игре не узнаёт
+    let sent = deliveries(&db).await;
+    assert_eq!(sent.len(), 1);
+    let hook = entity::webhook::Entity::find_by_id(sent[0].webhook_id)
+        .one(&db)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(hook.owner_id, users[1].id);
+
+    let payload: serde_json::Value = serde_json::from_str(&sent[0].payload).unwrap();
+    let steps = payload["turns"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|t| t["step_number"].as_u64().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(steps, [1, 1]);
+}